@@ -1,45 +1,112 @@
 use crate::{
     bz::{
+        advertisement::Advertisement,
         agent::AgentManager,
+        backend::AdapterBackend,
+        beacon::decode,
         controller::Controller,
-        pairing::{PairingConfirmationHandler, PairingManager},
-        scanner::Scanner,
+        device::Device,
+        device_info::DeviceInfo,
+        error::BluetoothError,
+        gatt::{decode_value, parse_value, GattCharacteristic},
+        pairing::{ConnectionPolicy, PairingConfirmationHandler, PairingManager},
+        scanner::{ScanTally, Scanner},
+        subscription::{Subscription, SubscriptionOutput},
     },
+    filter::DeviceFilter,
+    history::History,
     icons::Icons,
-    menu::{AdapterMenuOptions, DeviceMenuOptions, MainMenuOptions, Menu, SettingsMenuOptions},
+    launcher::Launcher,
+    menu::{
+        AdapterMenuOptions, DetailsMenuOptions, DeviceListOptions, DeviceMenuOptions,
+        GattMenuOptions, MainMenuOptions, Menu, SettingsMenuOptions,
+    },
+    navigation::{MenuState, Navigation, OpenTarget, Transition},
     notification::NotificationManager,
+    state::{now, DeviceOrder, State, StateStore},
 };
-use anyhow::Result;
-use bluer::Session;
-use log::{debug, error, info};
+use anyhow::{anyhow, Result};
+use bluer::{Address, Session};
+use log::{debug, error, info, warn};
 use rust_i18n::t;
-use std::sync::atomic::Ordering;
-use std::sync::Arc;
-use tokio::runtime::Builder;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{path::PathBuf, time::Duration};
+use tokio::sync::mpsc::unbounded_channel;
+
+/// How many of the devices found by a scan its completion notification lists.
+const SCAN_SUMMARY_DEVICES: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    pub scan_duration: u64,
+    pub interactive: bool,
+    pub quick_connect: bool,
+    /// Discovers devices in the background whenever the main menu opens.
+    pub auto_scan: bool,
+    pub device_order: DeviceOrder,
+    /// Order of discovered devices, which are never pinned.
+    pub new_device_order: DeviceOrder,
+    pub filter: DeviceFilter,
+    /// Removes discovered devices not seen for this long before showing the
+    /// main menu.
+    pub prune_after: Option<Duration>,
+    /// Where pins and history are persisted; `None` keeps them in memory.
+    pub state_dir: Option<PathBuf>,
+    pub connection_policy: ConnectionPolicy,
+    pub subscription_output: SubscriptionOutput,
+}
+
+enum ConnectionOutcome {
+    Connected,
+    Cancelled,
+    Failed(BluetoothError),
+}
 
 pub struct App {
-    pub running: bool,
-    pub reset_mode: bool,
     pub interactive: bool,
-    session: Arc<Session>,
+    pub quick_connect: bool,
+    pub auto_scan: bool,
+    pub device_order: DeviceOrder,
+    pub new_device_order: DeviceOrder,
+    filter: DeviceFilter,
+    /// Set once the user asked to list the devices the filter hides.
+    show_hidden: bool,
+    hidden_devices: Vec<Device>,
+    prune_after: Option<Duration>,
+    /// Devices whose Device Information Service was read during this run.
+    device_info_read: HashSet<Address>,
+    /// Set while the Advertisement view runs a discovery it started, which
+    /// is stopped on leaving the view.
+    advertisement_scan: bool,
+    /// Device picked from the scan completion notification, opened by the
+    /// menu loop once the current launcher is closed.
+    pending_device: Arc<Mutex<Option<Address>>>,
+    navigation: Navigation,
+    state_store: StateStore,
+    state: Mutex<State>,
+    history: Mutex<History>,
+    session: Option<Arc<Session>>,
     controller: Controller,
-    agent_manager: AgentManager,
+    agent_manager: Option<AgentManager>,
     scanner: Scanner,
     pairing_manager: PairingManager,
     notification_manager: Arc<NotificationManager>,
     scan_duration: u64,
+    subscription_output: SubscriptionOutput,
 }
 
 impl App {
-    pub fn get_session(&self) -> Arc<Session> {
+    pub fn get_session(&self) -> Option<Arc<Session>> {
         self.session.clone()
     }
 
-    pub fn get_agent_manager(&self) -> &AgentManager {
-        &self.agent_manager
+    pub fn get_agent_manager(&self) -> Option<&AgentManager> {
+        self.agent_manager.as_ref()
     }
 
-    pub async fn new(icons: Arc<Icons>, scan_duration: u64, interactive: bool) -> Result<Self> {
+    pub async fn new(icons: Arc<Icons>, config: AppConfig) -> Result<Self> {
         let session = Arc::new(Session::new().await?);
         let notification_manager = Arc::new(NotificationManager::new(icons.clone()));
 
@@ -51,30 +118,233 @@ impl App {
 
         let controller = Controller::new(session.clone()).await?;
 
+        let mut app = Self::from_controller(controller, notification_manager, config);
+        app.session = Some(session);
+        app.agent_manager = Some(agent_manager);
+
+        Ok(app)
+    }
+
+    /// Builds the app on top of an arbitrary backend, without registering a
+    /// pairing agent. Used to drive the app against the `fake` backend.
+    pub async fn with_backend(
+        adapter: Arc<dyn AdapterBackend>,
+        icons: Arc<Icons>,
+        config: AppConfig,
+    ) -> Result<Self> {
+        let notification_manager = Arc::new(NotificationManager::new(icons));
+        let controller = Controller::with_backend(adapter).await?;
+
+        Ok(Self::from_controller(
+            controller,
+            notification_manager,
+            config,
+        ))
+    }
+
+    fn from_controller(
+        mut controller: Controller,
+        notification_manager: Arc<NotificationManager>,
+        config: AppConfig,
+    ) -> Self {
         let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
 
-        let pairing_manager = PairingManager::new(controller.adapter.clone());
+        let pairing_manager =
+            PairingManager::new(controller.adapter.clone(), config.connection_policy);
 
         if !controller.is_powered {
             info!("{}", t!("notifications.bt.adapter_powered_off"));
         }
 
-        Ok(Self {
-            running: true,
-            reset_mode: false,
-            interactive,
-            session,
+        let state_store = StateStore::new(config.state_dir);
+        let state = state_store.load_state();
+        let mut history = state_store.load_history();
+
+        history.observe(
+            controller
+                .paired_devices
+                .iter()
+                .chain(&controller.new_devices),
+            now(),
+        );
+        state.sort_devices(
+            &mut controller.paired_devices,
+            config.device_order,
+            &history,
+        );
+
+        Self {
+            interactive: config.interactive,
+            quick_connect: config.quick_connect,
+            auto_scan: config.auto_scan,
+            device_order: config.device_order,
+            new_device_order: config.new_device_order,
+            filter: config.filter,
+            show_hidden: false,
+            hidden_devices: Vec::new(),
+            prune_after: config.prune_after,
+            device_info_read: HashSet::new(),
+            advertisement_scan: false,
+            pending_device: Arc::default(),
+            navigation: Navigation::new(MenuState::Main),
+            state_store,
+            state: Mutex::new(state),
+            history: Mutex::new(history),
+            session: None,
             controller,
-            agent_manager,
+            agent_manager: None,
             scanner,
             pairing_manager,
             notification_manager,
-            scan_duration,
-        })
+            scan_duration: config.scan_duration,
+            subscription_output: config.subscription_output,
+        }
+    }
+
+    /// Opens `state` on top of the current menu, so the app starts there and
+    /// going back leads to the menu below it.
+    pub fn open(&mut self, state: MenuState) {
+        self.navigation.push(state);
+    }
+
+    /// Starts in `target` instead of the main menu. Nothing sits below it,
+    /// so leaving it exits the app.
+    pub fn start_in(&mut self, target: &OpenTarget) -> Result<()> {
+        let state = match target {
+            OpenTarget::Main => MenuState::Main,
+            OpenTarget::Settings => MenuState::Settings,
+            OpenTarget::Scan => MenuState::Scan,
+            OpenTarget::Device(query) => {
+                let device = self
+                    .controller
+                    .find_device(query)
+                    .ok_or_else(|| anyhow!("No known device matches '{query}'"))?;
+                MenuState::Device(device.addr)
+            }
+        };
+
+        self.navigation = Navigation::new(state);
+        Ok(())
+    }
+
+    pub fn navigation(&self) -> &Navigation {
+        &self.navigation
+    }
+
+    pub fn state(&self) -> State {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn history(&self) -> History {
+        self.history.lock().unwrap().clone()
+    }
+
+    /// Refreshes the adapter and its devices, records them in the history,
+    /// orders them by the configured [`DeviceOrder`]s and drops the
+    /// discovered devices the [`DeviceFilter`] hides.
+    async fn refresh(&mut self) -> Result<()> {
+        self.controller.refresh().await?;
+        self.scanner
+            .apply_signals(&mut self.controller.paired_devices);
+        self.scanner.apply_signals(&mut self.controller.new_devices);
+
+        let controller = &self.controller;
+        self.update_history(|history| {
+            history.observe(
+                controller
+                    .paired_devices
+                    .iter()
+                    .chain(&controller.new_devices),
+                now(),
+            )
+        });
+
+        let history = self.history.lock().unwrap();
+        let state = self.state.lock().unwrap();
+        state.sort_devices(
+            &mut self.controller.paired_devices,
+            self.device_order,
+            &history,
+        );
+        state.sort_devices(
+            &mut self.controller.new_devices,
+            self.new_device_order,
+            &history,
+        );
+        drop(state);
+        drop(history);
+
+        self.hidden_devices.clear();
+        if self.filter.is_active() && !self.show_hidden {
+            let new_devices = std::mem::take(&mut self.controller.new_devices);
+            let (visible, hidden) = self.filter.split(new_devices);
+            self.controller.new_devices = visible;
+            self.hidden_devices = hidden;
+        }
+
+        Ok(())
+    }
+
+    /// Removes discovered devices that are not connected from BlueZ, hidden
+    /// ones included. With `stale_after`, only the ones not seen for that long
+    /// are removed. Returns how many were removed.
+    async fn clear_discovered_devices(&mut self, stale_after: Option<Duration>) -> Result<usize> {
+        let now = now();
+        let addrs: Vec<Address> = {
+            let history = self.history.lock().unwrap();
+            self.controller
+                .new_devices
+                .iter()
+                .chain(&self.hidden_devices)
+                .filter(|device| !device.is_connected)
+                .filter(|device| {
+                    stale_after
+                        .is_none_or(|max_age| history.is_stale(device.addr, now, max_age.as_secs()))
+                })
+                .map(|device| device.addr)
+                .collect()
+        };
+
+        if addrs.is_empty() {
+            return Ok(0);
+        }
+
+        let removed = self.controller.remove_devices(addrs.iter().copied()).await;
+        self.update_history(|history| {
+            for &addr in &addrs {
+                history.forget(addr);
+            }
+        });
+        self.refresh().await?;
+
+        Ok(removed)
+    }
+
+    fn update_state(&self, update: impl FnOnce(&mut State)) {
+        let mut state = self.state.lock().unwrap();
+        update(&mut state);
+
+        if let Err(e) = self.state_store.save_state(&state) {
+            warn!("Failed to save state: {e:#}");
+        }
+    }
+
+    /// Applies `update` to the history, only saving it if it changed.
+    fn update_history(&self, update: impl FnOnce(&mut History)) {
+        let mut history = self.history.lock().unwrap();
+        let before = history.clone();
+        update(&mut history);
+        if *history == before {
+            return;
+        }
+
+        if let Err(e) = self.state_store.save_history(&history) {
+            warn!("Failed to save history: {e:#}");
+        }
     }
 
     pub fn quit(&mut self) {
-        self.running = false;
+        self.navigation.clear();
     }
 
     pub async fn run(
@@ -83,129 +353,249 @@ impl App {
         menu_command: &Option<String>,
         icon_type: &str,
         spaces: usize,
-    ) -> Result<Option<String>> {
-        if !self.controller.is_powered {
-            self.handle_adapter_options(menu, menu_command, icon_type, spaces)
-                .await?;
-            if self.running {
-                self.controller.refresh().await?;
-            } else {
-                return Ok(None);
-            }
+    ) -> Result<()> {
+        if !self.controller.is_powered && self.navigation.current() != Some(&MenuState::Adapter) {
+            self.navigation.push(MenuState::Adapter);
         }
 
-        while self.running {
-            self.controller.refresh().await?;
-
-            match menu
-                .show_main_menu(menu_command, &self.controller, icon_type, spaces)
-                .await?
-            {
-                Some(main_menu_option) => {
-                    self.handle_main_options(
+        while let Some(state) = self.navigation.current().cloned() {
+            let transition = match state {
+                MenuState::Main => {
+                    self.handle_main_menu(menu, menu_command, icon_type, spaces)
+                        .await?
+                }
+                MenuState::Settings => {
+                    self.handle_settings_menu(menu, menu_command, icon_type, spaces)
+                        .await?
+                }
+                MenuState::Devices => {
+                    self.handle_device_list(menu, menu_command, icon_type, spaces)
+                        .await?
+                }
+                MenuState::Device(addr) => {
+                    self.handle_device_menu(menu, menu_command, addr, icon_type, spaces)
+                        .await?
+                }
+                MenuState::Details(addr) => {
+                    self.handle_details_menu(menu, menu_command, addr, icon_type, spaces)
+                        .await?
+                }
+                MenuState::Advertisement(addr) => {
+                    self.handle_advertisement_menu(menu, menu_command, addr, icon_type, spaces)
+                        .await?
+                }
+                MenuState::Services(addr) => {
+                    self.handle_services_menu(menu, menu_command, addr, icon_type, spaces)
+                        .await?
+                }
+                MenuState::Service(addr, service_id) => {
+                    self.handle_service_menu(
                         menu,
                         menu_command,
+                        addr,
+                        service_id,
                         icon_type,
                         spaces,
-                        main_menu_option,
                     )
-                    .await?;
+                    .await?
                 }
-                None => {
-                    debug!("{}", t!("notifications.bt.main_menu_exited"));
-                    self.running = false;
+                MenuState::Characteristic(addr, service_id, id) => {
+                    self.handle_characteristic_menu(
+                        menu,
+                        menu_command,
+                        addr,
+                        (service_id, id),
+                        icon_type,
+                        spaces,
+                    )
+                    .await?
                 }
-            }
+                MenuState::Adapter => {
+                    self.handle_adapter_menu(menu, menu_command, icon_type, spaces)
+                        .await?
+                }
+                MenuState::Scan => {
+                    self.perform_device_scan().await?;
+                    Transition::Replace(MenuState::Main)
+                }
+            };
+
+            let transition = match self.pending_device.lock().unwrap().take() {
+                Some(addr) => Transition::Push(MenuState::Device(addr)),
+                None => transition,
+            };
+
+            debug!("{state:?} -> {transition:?}");
+            self.navigation.apply(transition);
+        }
+
+        if self.controller.is_scanning.load(Ordering::Relaxed) {
+            self.scanner.stop_discovery().await?;
         }
 
-        Ok(None)
+        Ok(())
+    }
+
+    /// Escape goes back a level in interactive mode and leaves otherwise.
+    fn escape(&self) -> Transition {
+        if self.interactive {
+            Transition::Back
+        } else {
+            Transition::Exit
+        }
+    }
+
+    /// After an action, interactive mode shows the same menu again.
+    fn action_done(&self) -> Transition {
+        if self.interactive {
+            Transition::Stay
+        } else {
+            Transition::Exit
+        }
     }
 
-    async fn handle_main_options(
+    async fn handle_main_menu(
         &mut self,
         menu: &Menu,
         menu_command: &Option<String>,
         icon_type: &str,
         spaces: usize,
-        main_menu_option: MainMenuOptions,
-    ) -> Result<Option<String>> {
-        match main_menu_option {
-            MainMenuOptions::Scan => {
+    ) -> Result<Transition> {
+        if self.auto_scan
+            && self.controller.is_powered
+            && !self.controller.is_scanning.load(Ordering::Relaxed)
+        {
+            self.scanner.start_discovery(self.scan_duration).await?;
+        }
+
+        self.refresh().await?;
+
+        if let Some(max_age) = self.prune_after {
+            let pruned = self.clear_discovered_devices(Some(max_age)).await?;
+            if pruned > 0 {
+                debug!("Pruned {pruned} discovered devices not seen for {max_age:?}");
+            }
+        }
+
+        let transition = match menu
+            .show_main_menu(
+                menu_command,
+                &self.controller,
+                icon_type,
+                spaces,
+                self.quick_connect,
+                self.hidden_devices.len(),
+            )
+            .await?
+        {
+            Some(MainMenuOptions::Scan) => {
                 self.perform_device_scan().await?;
+                Transition::Stay
             }
-            MainMenuOptions::Settings => {
-                self.handle_settings_menu(menu, menu_command, icon_type, spaces)
-                    .await?;
+            Some(MainMenuOptions::Scanning) => Transition::Stay,
+            Some(MainMenuOptions::ShowHidden(_)) => {
+                self.show_hidden = true;
+                Transition::Stay
             }
-            MainMenuOptions::Device(output) => {
-                if let Some(device) = self
-                    .handle_device_selection(menu, menu_command, &output, icon_type, spaces)
-                    .await?
-                {
-                    return Ok(Some(device.addr.to_string()));
+            Some(MainMenuOptions::Settings) => Transition::Push(MenuState::Settings),
+            Some(MainMenuOptions::ManageDevices) => Transition::Push(MenuState::Devices),
+            Some(MainMenuOptions::Device(output)) => {
+                match self.find_device(menu, &output, icon_type, spaces).cloned() {
+                    Some(device) if self.quick_connect && device.is_paired => {
+                        if device.is_connected {
+                            self.perform_device_disconnection(&device).await;
+                        } else {
+                            self.connect_or_repair(menu, menu_command, &device, icon_type, spaces)
+                                .await?;
+                        }
+                        self.action_done()
+                    }
+                    Some(device) => Transition::Push(MenuState::Device(device.addr)),
+                    None => Transition::Stay,
                 }
             }
-        }
-        Ok(None)
+            None => {
+                debug!("{}", t!("notifications.bt.main_menu_exited"));
+                Transition::Exit
+            }
+        };
+
+        Ok(transition)
     }
 
-    async fn handle_settings_menu(
+    async fn handle_device_list(
         &mut self,
         menu: &Menu,
         menu_command: &Option<String>,
         icon_type: &str,
         spaces: usize,
-    ) -> Result<()> {
-        let mut stay_in_settings = true;
-
-        while stay_in_settings {
-            self.controller.refresh().await?;
-
-            if let Some(option) = menu
-                .show_settings_menu(
-                    menu_command,
-                    &self.controller,
-                    icon_type,
-                    spaces,
-                    self.interactive,
-                )
-                .await?
-            {
-                if matches!(option, SettingsMenuOptions::Back) {
-                    stay_in_settings = false;
-                } else if matches!(option, SettingsMenuOptions::DisableAdapter) {
-                    self.handle_settings_options(menu, menu_command, icon_type, spaces, option)
-                        .await?;
-                    stay_in_settings = false;
-                } else {
-                    self.handle_settings_options(menu, menu_command, icon_type, spaces, option)
-                        .await?;
-                    if !self.interactive {
-                        self.running = false;
-                        stay_in_settings = false;
-                    }
+    ) -> Result<Transition> {
+        self.refresh().await?;
+
+        let transition = match menu
+            .show_device_list(
+                menu_command,
+                &self.controller,
+                icon_type,
+                spaces,
+                self.interactive,
+            )
+            .await?
+        {
+            Some(DeviceListOptions::Back) => Transition::Back,
+            Some(DeviceListOptions::Device(output)) => {
+                match self.find_device(menu, &output, icon_type, spaces) {
+                    Some(device) => Transition::Push(MenuState::Device(device.addr)),
+                    None => Transition::Stay,
                 }
-            } else {
-                if !self.interactive {
-                    self.running = false;
-                }
-                stay_in_settings = false;
             }
-        }
+            None => self.escape(),
+        };
 
-        Ok(())
+        Ok(transition)
     }
 
-    async fn handle_settings_options(
+    async fn handle_settings_menu(
         &mut self,
         menu: &Menu,
         menu_command: &Option<String>,
         icon_type: &str,
         spaces: usize,
-        option: SettingsMenuOptions,
-    ) -> Result<()> {
+    ) -> Result<Transition> {
+        self.refresh().await?;
+
+        let transition = match menu
+            .show_settings_menu(
+                menu_command,
+                &self.controller,
+                icon_type,
+                spaces,
+                self.interactive,
+            )
+            .await?
+        {
+            Some(SettingsMenuOptions::Back) => Transition::Back,
+            Some(SettingsMenuOptions::DisableAdapter) => {
+                self.perform_adapter_disable().await?;
+                if self.interactive {
+                    Transition::Replace(MenuState::Adapter)
+                } else {
+                    Transition::Exit
+                }
+            }
+            Some(option) => {
+                self.handle_settings_options(option).await?;
+                self.action_done()
+            }
+            None => self.escape(),
+        };
+
+        Ok(transition)
+    }
+
+    async fn handle_settings_options(&mut self, option: SettingsMenuOptions) -> Result<()> {
         match option {
-            SettingsMenuOptions::Back => {}
+            SettingsMenuOptions::Back | SettingsMenuOptions::DisableAdapter => {}
             SettingsMenuOptions::ToggleDiscoverable => {
                 let new_state = !self.controller.is_discoverable;
                 self.controller.set_discoverable(new_state).await?;
@@ -226,6 +616,23 @@ impl App {
                     None
                 );
             }
+            SettingsMenuOptions::ClearDevices => {
+                let removed = self.clear_discovered_devices(None).await?;
+                let msg = t!(
+                    "notifications.bt.discovered_devices_cleared",
+                    count = removed
+                );
+
+                info!("{msg}");
+                try_send_notification!(
+                    self.notification_manager,
+                    None,
+                    Some(msg.to_string()),
+                    Some("bluetooth"),
+                    None,
+                    None
+                );
+            }
             SettingsMenuOptions::TogglePairable => {
                 let new_state = !self.controller.is_pairable;
                 self.controller.set_pairable(new_state).await?;
@@ -246,186 +653,604 @@ impl App {
                     None
                 );
             }
-            SettingsMenuOptions::DisableAdapter => {
-                self.perform_adapter_disable(menu, menu_command, icon_type, spaces)
+        }
+        Ok(())
+    }
+
+    async fn handle_adapter_menu(
+        &mut self,
+        menu: &Menu,
+        menu_command: &Option<String>,
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<Transition> {
+        match menu.prompt_enable_adapter(menu_command, icon_type, spaces) {
+            Some(AdapterMenuOptions::PowerOnDevice) => {
+                self.controller.power_on().await?;
+                self.refresh().await?;
+
+                info!("{}", t!("notifications.bt.adapter_enabled"));
+                try_send_notification!(
+                    self.notification_manager,
+                    None,
+                    Some(t!("notifications.bt.adapter_enabled").to_string()),
+                    Some("bluetooth"),
+                    None,
+                    None
+                );
+
+                Ok(Transition::Back)
+            }
+            None => {
+                info!("{}", t!("notifications.bt.adapter_menu_exited"));
+                Ok(Transition::Exit)
+            }
+        }
+    }
+
+    async fn handle_device_menu(
+        &mut self,
+        menu: &Menu,
+        menu_command: &Option<String>,
+        addr: Address,
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<Transition> {
+        let Ok(device) = Device::new(&self.controller.adapter, &addr).await else {
+            error!("Device {addr} is no longer available");
+            return Ok(Transition::Back);
+        };
+
+        let pinned = self.state.lock().unwrap().is_pinned(device.addr);
+        let has_gatt_services = device.has_gatt_services().await;
+
+        let available_options = if device.is_paired {
+            menu.get_paired_device_options(&device, pinned, has_gatt_services)
+        } else {
+            let mut options = vec![DeviceMenuOptions::Connect, DeviceMenuOptions::Details];
+            if has_gatt_services {
+                options.push(DeviceMenuOptions::Services);
+            }
+            options
+        };
+
+        let Some(option) = menu
+            .show_device_options(
+                menu_command,
+                icon_type,
+                spaces,
+                available_options,
+                &device.alias,
+                self.interactive,
+            )
+            .await?
+        else {
+            return Ok(self.escape());
+        };
+
+        let transition = match option {
+            DeviceMenuOptions::Back => Transition::Back,
+            DeviceMenuOptions::Connect if !device.is_connected => {
+                self.connect_or_repair(menu, menu_command, &device, icon_type, spaces)
                     .await?;
+                self.action_done()
+            }
+            DeviceMenuOptions::Disconnect if device.is_connected => {
+                self.perform_device_disconnection(&device).await;
+                self.action_done()
             }
+            DeviceMenuOptions::Trust if !device.is_trusted => {
+                self.perform_trust_device(&device, true).await?;
+                self.action_done()
+            }
+            DeviceMenuOptions::RevokeTrust if device.is_trusted => {
+                self.perform_trust_device(&device, false).await?;
+                self.action_done()
+            }
+            DeviceMenuOptions::Pin | DeviceMenuOptions::Unpin => {
+                let pin = option == DeviceMenuOptions::Pin;
+                info!(
+                    "{} device: {}",
+                    if pin { "Pinning" } else { "Unpinning" },
+                    device.alias
+                );
+                self.update_state(|state| state.set_pinned(device.addr, pin));
+                self.action_done()
+            }
+            DeviceMenuOptions::Details => Transition::Push(MenuState::Details(addr)),
+            DeviceMenuOptions::Services => Transition::Push(MenuState::Services(addr)),
+            DeviceMenuOptions::Forget => {
+                if !self.perform_forget_device(&device).await? {
+                    Transition::Stay
+                } else if self.interactive {
+                    Transition::Back
+                } else {
+                    Transition::Exit
+                }
+            }
+            _ => Transition::Stay,
+        };
+
+        Ok(transition)
+    }
+
+    async fn handle_details_menu(
+        &mut self,
+        menu: &Menu,
+        menu_command: &Option<String>,
+        addr: Address,
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<Transition> {
+        let Ok(device) = Device::new(&self.controller.adapter, &addr).await else {
+            error!("Device {addr} is no longer available");
+            return Ok(Transition::Back);
+        };
+
+        let beacons = match self.controller.adapter.device(addr) {
+            Ok(backend) => decode(&Advertisement::read(backend.as_ref()).await),
+            Err(_) => Vec::new(),
+        };
+
+        self.refresh_device_info(&device).await;
+
+        let details = {
+            let history = self.history.lock().unwrap();
+            menu.get_device_details(&device, history.get(addr), &beacons, now())
+        };
+
+        let picked = menu.show_device_details(
+            menu_command,
+            &details,
+            &device.alias,
+            icon_type,
+            spaces,
+            self.interactive,
+        )?;
+
+        Ok(match picked {
+            Some(DetailsMenuOptions::Advertisement) => {
+                Transition::Push(MenuState::Advertisement(addr))
+            }
+            Some(_) => Transition::Back,
+            None => self.escape(),
+        })
+    }
+
+    /// Shows the latest advertisement of a device. Discovery runs in the
+    /// background meanwhile, and the view is shown again whenever a new
+    /// advertisement arrives or discovery ends. Discovery is stopped on
+    /// leaving if it was started here.
+    async fn handle_advertisement_menu(
+        &mut self,
+        menu: &Menu,
+        menu_command: &Option<String>,
+        addr: Address,
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<Transition> {
+        let Ok(device) = self.controller.adapter.device(addr) else {
+            error!("Device {addr} is no longer available");
+            return Ok(Transition::Back);
+        };
+
+        if self.controller.is_powered && !self.controller.is_scanning.load(Ordering::Relaxed) {
+            self.scanner.start_discovery(self.scan_duration).await?;
+            self.advertisement_scan = true;
         }
-        Ok(())
+
+        let advertisement = Advertisement::read(device.as_ref()).await;
+        let details = menu.get_advertisement_details(&advertisement);
+        let alias = device.alias().await.unwrap_or_else(|_| addr.to_string());
+
+        let changed = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let scanner = self.scanner.clone();
+            let changed = changed.clone();
+            tokio::spawn(async move {
+                scanner
+                    .wait_for_advertisement_change(addr, &advertisement)
+                    .await;
+                changed.store(true, Ordering::Relaxed);
+                Launcher::close_current();
+            })
+        };
+
+        let picked = menu.show_advertisement(
+            menu_command,
+            &details,
+            &alias,
+            icon_type,
+            spaces,
+            self.interactive,
+        );
+        watcher.abort();
+
+        let transition = match picked? {
+            Some(DetailsMenuOptions::Entry) => Transition::Stay,
+            Some(_) => Transition::Back,
+            None if changed.load(Ordering::Relaxed) => Transition::Stay,
+            None => self.escape(),
+        };
+
+        if !matches!(transition, Transition::Stay)
+            && std::mem::take(&mut self.advertisement_scan)
+            && self.controller.is_scanning.load(Ordering::Relaxed)
+        {
+            self.scanner.stop_discovery().await?;
+        }
+
+        Ok(transition)
     }
 
-    async fn handle_adapter_options(
+    async fn handle_services_menu(
         &mut self,
         menu: &Menu,
         menu_command: &Option<String>,
+        addr: Address,
         icon_type: &str,
         spaces: usize,
-    ) -> Result<()> {
-        if let Some(option) = menu.prompt_enable_adapter(menu_command, icon_type, spaces) {
-            match option {
-                AdapterMenuOptions::PowerOnDevice => {
-                    self.controller.power_on().await?;
-                    self.controller.refresh().await?;
-
-                    info!("{}", t!("notifications.bt.adapter_enabled"));
-                    try_send_notification!(
-                        self.notification_manager,
-                        None,
-                        Some(t!("notifications.bt.adapter_enabled").to_string()),
-                        Some("bluetooth"),
+    ) -> Result<Transition> {
+        let Ok(device) = Device::new(&self.controller.adapter, &addr).await else {
+            error!("Device {addr} is no longer available");
+            return Ok(Transition::Back);
+        };
+
+        let services = match device.primary_services().await {
+            Ok(services) => services,
+            Err(e) => {
+                self.notify_gatt_failure(&device, &e);
+                return Ok(Transition::Back);
+            }
+        };
+
+        let picked = menu.show_services(
+            menu_command,
+            &services,
+            &device.alias,
+            icon_type,
+            spaces,
+            self.interactive,
+        )?;
+
+        Ok(match picked {
+            Some(GattMenuOptions::Open(service_id)) => {
+                Transition::Push(MenuState::Service(addr, service_id))
+            }
+            Some(GattMenuOptions::Back) => Transition::Back,
+            Some(_) => Transition::Stay,
+            None => self.escape(),
+        })
+    }
+
+    async fn handle_service_menu(
+        &mut self,
+        menu: &Menu,
+        menu_command: &Option<String>,
+        addr: Address,
+        service_id: u16,
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<Transition> {
+        let Ok(device) = Device::new(&self.controller.adapter, &addr).await else {
+            error!("Device {addr} is no longer available");
+            return Ok(Transition::Back);
+        };
+
+        let lookup = async {
+            let services = device.primary_services().await?;
+            let characteristics = device.characteristics(service_id).await?;
+            anyhow::Ok((services, characteristics))
+        };
+        let (services, characteristics) = match lookup.await {
+            Ok(found) => found,
+            Err(e) => {
+                self.notify_gatt_failure(&device, &e);
+                return Ok(Transition::Back);
+            }
+        };
+
+        let service_name = services
+            .iter()
+            .find(|service| service.id == service_id)
+            .map_or_else(|| format!("0x{service_id:04X}"), |service| service.name());
+
+        let picked = menu.show_characteristics(
+            menu_command,
+            &characteristics,
+            &service_name,
+            icon_type,
+            spaces,
+            self.interactive,
+        )?;
+
+        Ok(match picked {
+            Some(GattMenuOptions::Open(id)) => {
+                Transition::Push(MenuState::Characteristic(addr, service_id, id))
+            }
+            Some(GattMenuOptions::Back) => Transition::Back,
+            Some(_) => Transition::Stay,
+            None => self.escape(),
+        })
+    }
+
+    /// Shows a characteristic, reading its value again each time.
+    async fn handle_characteristic_menu(
+        &mut self,
+        menu: &Menu,
+        menu_command: &Option<String>,
+        addr: Address,
+        (service_id, id): (u16, u16),
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<Transition> {
+        let Ok(device) = Device::new(&self.controller.adapter, &addr).await else {
+            error!("Device {addr} is no longer available");
+            return Ok(Transition::Back);
+        };
+
+        let characteristic = match device.characteristics(service_id).await {
+            Ok(characteristics) => characteristics.into_iter().find(|c| c.id == id),
+            Err(e) => {
+                self.notify_gatt_failure(&device, &e);
+                return Ok(Transition::Back);
+            }
+        };
+        let Some(characteristic) = characteristic else {
+            error!("Characteristic 0x{id:04X} of {addr} is no longer available");
+            return Ok(Transition::Back);
+        };
+
+        let mut value = None;
+        if characteristic.is_readable() {
+            match device.read_characteristic(service_id, id).await {
+                Ok(read) => value = Some(read),
+                Err(e) => self.notify_gatt_failure(&device, &e),
+            }
+        }
+
+        let details = menu.get_characteristic_details(&characteristic, value.as_deref());
+
+        let picked = menu.show_characteristic(
+            menu_command,
+            &details,
+            &characteristic,
+            icon_type,
+            spaces,
+            self.interactive,
+        )?;
+
+        Ok(match picked {
+            Some(GattMenuOptions::WriteValue) => {
+                let name = characteristic.name();
+                match menu.prompt_value(menu_command, &name, icon_type)? {
+                    Some(input) => {
+                        self.perform_characteristic_write(&device, &characteristic, &input)
+                            .await;
+                        self.action_done()
+                    }
+                    None => Transition::Stay,
+                }
+            }
+            Some(GattMenuOptions::Subscribe) => {
+                let interrupted = self
+                    .watch_characteristic(
+                        menu,
+                        menu_command,
+                        &device,
+                        &characteristic,
+                        icon_type,
+                        spaces,
+                    )
+                    .await?;
+                if interrupted {
+                    Transition::Exit
+                } else {
+                    Transition::Stay
+                }
+            }
+            Some(GattMenuOptions::Back) => Transition::Back,
+            Some(_) => Transition::Stay,
+            None => self.escape(),
+        })
+    }
+
+    /// Streams values of `characteristic` until the user stops watching it.
+    /// Returns whether that was by SIGINT or SIGTERM, so the app can exit
+    /// once notifications are disabled.
+    async fn watch_characteristic(
+        &self,
+        menu: &Menu,
+        menu_command: &Option<String>,
+        device: &Device,
+        characteristic: &GattCharacteristic,
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<bool> {
+        let name = characteristic.name();
+        let values = match device
+            .subscribe_characteristic(characteristic.service_id, characteristic.id)
+            .await
+        {
+            Ok(values) => values,
+            Err(e) => {
+                self.notify_gatt_failure(device, &e);
+                return Ok(false);
+            }
+        };
+        info!("Watching {name} of {}", device.alias);
+
+        let output = self.subscription_output;
+        let notification_manager = self.notification_manager.clone();
+        let summary = format!("{}: {name}", device.alias);
+        let mut notification_id = None;
+
+        let subscription = Subscription::start(values, output.interval(), move |value| {
+            let value = decode_value(&value).summary();
+            match output {
+                SubscriptionOutput::Stdout => println!("{summary}: {value}"),
+                SubscriptionOutput::Notification => {
+                    notification_id = try_send_notification_with_id!(
+                        notification_manager,
+                        Some(summary.clone()),
+                        Some(value),
+                        Some("subscribe"),
                         None,
-                        None
+                        notification_id
                     );
                 }
             }
-        } else {
-            info!("{}", t!("notifications.bt.adapter_menu_exited"));
-            self.running = false;
-        }
+        });
 
-        Ok(())
+        let (shown, interrupted) = Launcher::catch_signals(|| {
+            menu.show_subscription(menu_command, &name, icon_type, spaces)
+        });
+        subscription.stop().await;
+        info!("Stopped watching {name} of {}", device.alias);
+
+        shown?;
+        Ok(interrupted)
     }
 
-    async fn handle_device_menu(
-        &mut self,
+    /// Connects `device`, offering to re-pair it when its bond keys were
+    /// rejected.
+    async fn connect_or_repair(
+        &self,
         menu: &Menu,
         menu_command: &Option<String>,
-        device: &crate::bz::device::Device,
+        device: &Device,
         icon_type: &str,
         spaces: usize,
     ) -> Result<()> {
-        let mut device_clone = device.clone();
-        let mut stay_in_device_menu = true;
+        let outcome = self.perform_device_connection(device).await?;
 
-        while stay_in_device_menu {
-            if let Ok(refreshed_device) =
-                crate::bz::device::Device::new(&self.controller.adapter, &device_clone.addr).await
+        if let ConnectionOutcome::Failed(error) = outcome {
+            if device.is_paired
+                && error.is_authentication_error()
+                && menu.prompt_repair(menu_command, &device.alias, icon_type, spaces)?
             {
-                device_clone = refreshed_device;
-            } else {
-                error!("Device {} is no longer available", device_clone.alias);
-                break;
+                self.perform_device_repair(device).await?;
             }
+        }
 
-            let available_options = if device_clone.is_paired {
-                menu.get_paired_device_options(&device_clone)
-            } else {
-                vec![DeviceMenuOptions::Connect]
-            };
+        Ok(())
+    }
 
-            match menu
-                .show_device_options(
-                    menu_command,
-                    icon_type,
-                    spaces,
-                    available_options,
-                    &device_clone.alias,
-                    self.interactive,
-                )
-                .await?
-            {
-                Some(option) => {
-                    match option {
-                        DeviceMenuOptions::Back => {
-                            stay_in_device_menu = false;
-                        }
-                        DeviceMenuOptions::Connect => {
-                            if !device_clone.is_connected {
-                                self.perform_device_connection(&device_clone).await?;
-                                if !self.interactive {
-                                    stay_in_device_menu = false;
-                                    self.running = false;
-                                }
-                            }
-                        }
-                        DeviceMenuOptions::Disconnect => {
-                            if device_clone.is_connected {
-                                self.perform_device_disconnection(&device_clone).await?;
-                                if !self.interactive {
-                                    stay_in_device_menu = false;
-                                    self.running = false;
-                                }
-                            }
-                        }
-                        DeviceMenuOptions::Trust => {
-                            if !device_clone.is_trusted {
-                                self.perform_trust_device(&device_clone, true).await?;
-                                if !self.interactive {
-                                    stay_in_device_menu = false;
-                                    self.running = false;
-                                }
-                            }
-                        }
-                        DeviceMenuOptions::RevokeTrust => {
-                            if device_clone.is_trusted {
-                                self.perform_trust_device(&device_clone, false).await?;
-                                if !self.interactive {
-                                    stay_in_device_menu = false;
-                                    self.running = false;
-                                }
-                            }
-                        }
-                        DeviceMenuOptions::Forget => {
-                            if self.perform_forget_device(&device_clone).await? {
-                                stay_in_device_menu = false;
-                                if !self.interactive {
-                                    self.running = false;
-                                }
-                            }
-                        }
-                    }
+    /// Reads the Device Information Service of `device` into the history,
+    /// once per run and only while it is connected and lists the service,
+    /// as reading it takes a few round trips.
+    async fn refresh_device_info(&mut self, device: &Device) {
+        if self.device_info_read.contains(&device.addr) || !DeviceInfo::is_offered(device).await {
+            return;
+        }
+
+        match DeviceInfo::read(device).await {
+            Ok(info) => {
+                self.device_info_read.insert(device.addr);
+                // Keep what was read before if nothing could be read now.
+                if let Some(info) = info.filter(|info| !info.is_empty()) {
+                    self.update_history(|history| history.record_device_info(device, info));
+                }
+            }
+            Err(e) => warn!(
+                "Failed to read device information of {}: {e:#}",
+                device.alias
+            ),
+        }
+    }
+
+    /// The Device Information of the device matching `query`, read anew if
+    /// it is connected and as last read otherwise.
+    pub async fn device_info(&mut self, query: &str) -> Result<(Device, Option<DeviceInfo>)> {
+        let device = self
+            .controller
+            .find_device(query)
+            .cloned()
+            .ok_or_else(|| anyhow!("No known device matches '{query}'"))?;
+
+        self.refresh_device_info(&device).await;
+
+        let info = self
+            .history
+            .lock()
+            .unwrap()
+            .get(device.addr)
+            .and_then(|entry| entry.device_info.clone());
+        Ok((device, info))
+    }
+
+    /// Connects the device matching `query` if it is disconnected, and
+    /// disconnects it otherwise.
+    pub async fn toggle_device(&self, query: &str) -> Result<()> {
+        let device = self
+            .controller
+            .find_device(query)
+            .cloned()
+            .ok_or_else(|| anyhow!("No known device matches '{query}'"))?;
+
+        if device.is_connected {
+            return self.disconnect_device(&device).await;
+        }
+
+        self.connect_device(&device).await
+    }
+
+    /// Scans until a device matching `query` shows up, then pairs and
+    /// connects it if `connect` is set. Fails if none shows up in time.
+    pub async fn scan_until(
+        &mut self,
+        query: &str,
+        timeout_sec: u64,
+        connect: bool,
+    ) -> Result<Device> {
+        let device = self
+            .scanner
+            .discover_matching(query, timeout_sec)
+            .await?
+            .ok_or_else(|| {
+                anyhow!("No device matching '{query}' found within {timeout_sec} seconds")
+            })?;
+
+        self.refresh().await?;
+
+        if connect {
+            self.connect_device(&device).await?;
+        }
+
+        Ok(device)
+    }
 
-                    self.controller.refresh().await?;
-                }
-                None => {
-                    if !self.interactive {
-                        self.running = false;
-                    }
-                    stay_in_device_menu = false;
-                }
-            }
+    async fn connect_device(&self, device: &Device) -> Result<()> {
+        match self.perform_device_connection(device).await? {
+            ConnectionOutcome::Failed(error) => Err(anyhow!(error.describe())),
+            ConnectionOutcome::Connected | ConnectionOutcome::Cancelled => Ok(()),
         }
+    }
 
-        Ok(())
+    async fn disconnect_device(&self, device: &Device) -> Result<()> {
+        match self.perform_device_disconnection(device).await {
+            Some(error) => Err(anyhow!(error.describe())),
+            None => Ok(()),
+        }
     }
 
-    async fn handle_device_selection(
-        &mut self,
+    fn find_device(
+        &self,
         menu: &Menu,
-        menu_command: &Option<String>,
         output: &str,
         icon_type: &str,
         spaces: usize,
-    ) -> Result<Option<crate::bz::device::Device>> {
+    ) -> Option<&Device> {
         let cleaned_output = menu.clean_menu_output(output, icon_type);
 
-        let paired_device_clone = self
-            .controller
+        self.controller
             .paired_devices
             .iter()
+            .chain(&self.controller.new_devices)
             .find(|device| {
                 let formatted = menu.format_device_display(device, icon_type, spaces);
                 menu.clean_menu_output(&formatted, icon_type) == cleaned_output
             })
-            .cloned();
-
-        let new_device_clone = self
-            .controller
-            .new_devices
-            .iter()
-            .find(|device| {
-                let formatted = menu.format_device_display(device, icon_type, spaces);
-                menu.clean_menu_output(&formatted, icon_type) == cleaned_output
-            })
-            .cloned();
-
-        if let Some(device) = paired_device_clone.or(new_device_clone) {
-            self.handle_device_menu(menu, menu_command, &device, icon_type, spaces)
-                .await?;
-            return Ok(Some(device));
-        }
-
-        Ok(None)
     }
 
     async fn perform_device_scan(&mut self) -> Result<()> {
@@ -447,73 +1272,185 @@ impl App {
 
         self.scanner.start_discovery(scan_duration).await?;
 
-        let scanner_clone = self.scanner.clone();
+        let cancel_token = self.scanner.cancellation_token();
 
-        let progress_msg = t!("notifications.bt.scan_in_progress");
-        let completed_msg = t!("notifications.bt.scan_completed");
+        let scanner = self.scanner.clone();
 
         let id = self.notification_manager.send_progress_notification(
             scan_duration,
             move || {
                 debug!("User cancelled Bluetooth scan");
-
-                let rt = Builder::new_current_thread().enable_all().build().unwrap();
-
-                rt.block_on(async {
-                    let _ = scanner_clone.stop_discovery().await;
-                });
+                cancel_token.cancel();
             },
-            progress_msg.to_string(),
+            move || scan_progress_body(&scanner.tally()),
             Some("scan_in_progress"),
         )?;
 
         self.scanner.wait_for_discovery_completion().await?;
 
-        self.controller.refresh().await?;
+        self.refresh().await?;
 
-        let _ = self.notification_manager.send_notification(
-            None,
-            Some(completed_msg.to_string()),
+        self.notify_scan_completed(id);
+
+        Ok(())
+    }
+
+    /// Replaces the progress notification with the newest named devices
+    /// found, each offered as an action opening its menu.
+    fn notify_scan_completed(&self, id: u32) {
+        let tally = self.scanner.tally();
+        let newest = tally.newest_named(SCAN_SUMMARY_DEVICES);
+
+        if newest.is_empty() {
+            let _ = self.notification_manager.send_notification(
+                None,
+                Some(t!("notifications.bt.scan_completed").to_string()),
+                Some("ok"),
+                None,
+                Some(id),
+            );
+            return;
+        }
+
+        let names: Vec<&str> = newest.iter().map(|device| device.alias.as_str()).collect();
+        let body = t!(
+            "notifications.bt.scan_completed_found",
+            devices = names.join(", ")
+        );
+        let actions: Vec<(String, String)> = newest
+            .iter()
+            .map(|device| (device.addr.to_string(), device.alias.clone()))
+            .collect();
+
+        let pending_device = self.pending_device.clone();
+        let _ = self.notification_manager.send_action_notification(
+            body.to_string(),
             Some("ok"),
-            None,
+            &actions,
             Some(id),
+            move |action| {
+                if let Ok(addr) = action.parse::<Address>() {
+                    debug!("Opening device {addr} from scan notification");
+                    *pending_device.lock().unwrap() = Some(addr);
+                    Launcher::close_current();
+                }
+            },
         );
+    }
 
-        Ok(())
+    async fn perform_device_connection(&self, device: &Device) -> Result<ConnectionOutcome> {
+        self.connect_with_progress(device, None).await
     }
 
-    async fn perform_device_connection(&self, device: &crate::bz::device::Device) -> Result<()> {
+    /// Pairs if needed, then connects `device`, reporting progress in the
+    /// notification `progress_id` when one is already shown.
+    async fn connect_with_progress(
+        &self,
+        device: &Device,
+        progress_id: Option<u32>,
+    ) -> Result<ConnectionOutcome> {
         debug!("Connecting to device: {}", device.alias);
 
-        let result = if !device.is_paired {
-            self.pairing_manager.pair_device(device).await
+        let (cancel_sender, mut cancel_receiver) = unbounded_channel::<()>();
+
+        let initial_msg = if !device.is_paired {
+            t!(
+                "notifications.bt.pairing_in_progress",
+                device_name = device.alias
+            )
         } else {
-            Ok(())
+            t!("notifications.bt.connecting", device_name = device.alias)
         };
 
-        if let Err(err) = result {
+        let progress_id = self
+            .notification_manager
+            .send_cancellable_notification(
+                initial_msg.to_string(),
+                Some("bluetooth"),
+                progress_id,
+                move || {
+                    debug!("User cancelled Bluetooth connection");
+                    let _ = cancel_sender.send(());
+                },
+            )
+            .map_err(|e| eprintln!("Notification failed: {e}"))
+            .ok()
+            .or(progress_id);
+
+        if !device.is_paired {
+            let result = tokio::select! {
+                result = self.pairing_manager.pair_device(device) => result,
+                Some(()) = cancel_receiver.recv() => {
+                    self.notify_connection_cancelled(device, progress_id);
+                    return Ok(ConnectionOutcome::Cancelled);
+                }
+            };
+
+            if let Err(err) = result {
+                let error = BluetoothError::classify(&err);
+                let msg = t!(
+                    "notifications.bt.pairing_failed",
+                    device_name = device.alias,
+                    error = error.describe()
+                );
+
+                info!("{msg}");
+                try_send_notification!(
+                    self.notification_manager,
+                    None,
+                    Some(msg.to_string()),
+                    Some("bluetooth"),
+                    None,
+                    progress_id
+                );
+                return Ok(ConnectionOutcome::Failed(error));
+            }
+
+            if let Some(id) = progress_id {
+                let msg = t!("notifications.bt.connecting", device_name = device.alias);
+                let _ = self
+                    .notification_manager
+                    .update_cancellable_notification(id, msg.to_string(), Some("bluetooth"))
+                    .map_err(|e| eprintln!("Notification failed: {e}"));
+            }
+        }
+
+        let report_attempt = |attempt: u32, max_attempts: u32| {
+            if max_attempts == 1 {
+                return;
+            }
+
             let msg = t!(
-                "notifications.bt.pairing_failed",
+                "notifications.bt.connecting_attempt",
                 device_name = device.alias,
-                error = err.to_string()
+                attempt = attempt,
+                max_attempts = max_attempts
             );
-
             info!("{msg}");
-            try_send_notification!(
-                self.notification_manager,
-                None,
-                Some(msg.to_string()),
-                Some("bluetooth"),
-                None,
-                None
-            );
-            return Ok(());
-        }
 
-        let connection_result = self.pairing_manager.connect_device(device).await;
+            if let Some(id) = progress_id {
+                let _ = self
+                    .notification_manager
+                    .update_cancellable_notification(id, msg.to_string(), Some("bluetooth"))
+                    .map_err(|e| eprintln!("Notification failed: {e}"));
+            }
+        };
+
+        let connection_result = tokio::select! {
+            result = self.pairing_manager.connect_device(device, report_attempt) => result,
+            Some(()) = cancel_receiver.recv() => {
+                if let Err(err) = self.pairing_manager.cancel_connection(device).await {
+                    debug!("Failed to cancel connection to {}: {err}", device.alias);
+                }
+                self.notify_connection_cancelled(device, progress_id);
+                return Ok(ConnectionOutcome::Cancelled);
+            }
+        };
 
         match connection_result {
             Ok(_) => {
+                self.update_history(|history| history.record_connected(device, now()));
+
                 let msg = t!(
                     "notifications.bt.device_connected",
                     device_name = device.alias
@@ -526,22 +1463,22 @@ impl App {
                     Some(msg.to_string()),
                     Some("bluetooth"),
                     None,
-                    None
+                    progress_id
                 );
-                Ok(())
+                Ok(ConnectionOutcome::Connected)
             }
             Err(err) => {
-                let msg = if err.to_string().contains("Page Timeout") {
-                    t!(
+                let error = BluetoothError::classify(&err);
+                let msg = match error {
+                    BluetoothError::PageTimeout => t!(
                         "notifications.bt.device_out_of_range",
                         device_name = device.alias
-                    )
-                } else {
-                    t!(
+                    ),
+                    ref error => t!(
                         "notifications.bt.connection_failed",
                         device_name = device.alias,
-                        error = err.to_string()
-                    )
+                        error = error.describe()
+                    ),
                 };
 
                 info!("{msg}");
@@ -552,19 +1489,119 @@ impl App {
                     Some(msg.to_string()),
                     Some("bluetooth"),
                     None,
-                    None
+                    progress_id
                 );
 
-                Ok(())
+                Ok(ConnectionOutcome::Failed(error))
+            }
+        }
+    }
+
+    async fn perform_device_repair(&self, device: &Device) -> Result<()> {
+        info!("Re-pairing device: {}", device.alias);
+
+        if let Err(e) = self.pairing_manager.forget_device(device).await {
+            self.notify_repair_failure(&e, None);
+            return Ok(());
+        }
+
+        let msg = t!(
+            "notifications.bt.repair_searching",
+            device_name = device.alias
+        );
+        info!("{msg}");
+        let progress_id = try_send_notification_with_id!(
+            self.notification_manager,
+            None,
+            Some(msg.to_string()),
+            Some("scan_in_progress"),
+            None,
+            None
+        );
+
+        let found = match self
+            .scanner
+            .discover_device(device.addr, self.scan_duration)
+            .await
+        {
+            Ok(found) => found,
+            Err(e) => {
+                self.notify_repair_failure(&e, progress_id);
+                return Ok(());
             }
+        };
+
+        if !found {
+            let msg = t!(
+                "notifications.bt.repair_not_found",
+                device_name = device.alias
+            );
+            info!("{msg}");
+            try_send_notification!(
+                self.notification_manager,
+                None,
+                Some(msg.to_string()),
+                Some("bluetooth"),
+                None,
+                progress_id
+            );
+            return Ok(());
         }
+
+        let rediscovered = match Device::new(&self.controller.adapter, &device.addr).await {
+            Ok(rediscovered) => rediscovered,
+            Err(e) => {
+                self.notify_repair_failure(&e, progress_id);
+                return Ok(());
+            }
+        };
+        self.connect_with_progress(&rediscovered, progress_id)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Reports a failed re-pairing step, replacing the notification
+    /// `progress_id` when one is shown.
+    fn notify_repair_failure(&self, err: &anyhow::Error, progress_id: Option<u32>) {
+        let msg = BluetoothError::classify(err).describe();
+        info!("{msg}");
+        try_send_notification!(
+            self.notification_manager,
+            None,
+            Some(msg),
+            Some("bluetooth"),
+            None,
+            progress_id
+        );
+    }
+
+    fn notify_connection_cancelled(&self, device: &Device, progress_id: Option<u32>) {
+        let msg = t!(
+            "notifications.bt.connection_cancelled",
+            device_name = device.alias
+        );
+
+        info!("{msg}");
+        try_send_notification!(
+            self.notification_manager,
+            None,
+            Some(msg.to_string()),
+            Some("bluetooth"),
+            None,
+            progress_id
+        );
     }
 
-    async fn perform_device_disconnection(&self, device: &crate::bz::device::Device) -> Result<()> {
+    /// Disconnects `device`, notifying the outcome, and returns the error
+    /// if it failed.
+    async fn perform_device_disconnection(&self, device: &Device) -> Option<BluetoothError> {
         debug!("Disconnecting from device: {}", device.alias);
 
         match self.pairing_manager.disconnect_device(device).await {
             Ok(()) => {
+                self.update_history(|history| history.record_disconnected(device, now()));
+
                 let msg = t!(
                     "notifications.bt.device_disconnected",
                     device_name = device.alias
@@ -578,9 +1615,11 @@ impl App {
                     None,
                     None
                 );
+                None
             }
             Err(e) => {
-                let msg = e.to_string();
+                let error = BluetoothError::classify(&e);
+                let msg = error.describe();
                 info!("{msg}");
                 try_send_notification!(
                     self.notification_manager,
@@ -590,17 +1629,12 @@ impl App {
                     None,
                     None
                 );
+                Some(error)
             }
         }
-
-        Ok(())
     }
 
-    async fn perform_trust_device(
-        &self,
-        device: &crate::bz::device::Device,
-        trust: bool,
-    ) -> Result<()> {
+    async fn perform_trust_device(&self, device: &Device, trust: bool) -> Result<()> {
         info!(
             "{} trust for device: {}",
             if trust { "Enabling" } else { "Revoking" },
@@ -631,7 +1665,7 @@ impl App {
                 );
             }
             Err(e) => {
-                let msg = e.to_string();
+                let msg = BluetoothError::classify(&e).describe();
                 info!("{msg}");
                 try_send_notification!(
                     self.notification_manager,
@@ -647,7 +1681,7 @@ impl App {
         Ok(())
     }
 
-    async fn perform_forget_device(&self, device: &crate::bz::device::Device) -> Result<bool> {
+    async fn perform_forget_device(&self, device: &Device) -> Result<bool> {
         info!("Forgetting device: {}", device.alias);
 
         match self.pairing_manager.forget_device(device).await {
@@ -668,7 +1702,7 @@ impl App {
                 Ok(true)
             }
             Err(e) => {
-                let msg = e.to_string();
+                let msg = BluetoothError::classify(&e).describe();
                 info!("{msg}");
                 try_send_notification!(
                     self.notification_manager,
@@ -683,13 +1717,63 @@ impl App {
         }
     }
 
-    async fn perform_adapter_disable(
-        &mut self,
-        menu: &Menu,
-        menu_command: &Option<String>,
-        icon_type: &str,
-        spaces: usize,
-    ) -> Result<()> {
+    async fn perform_characteristic_write(
+        &self,
+        device: &Device,
+        characteristic: &GattCharacteristic,
+        input: &str,
+    ) {
+        let name = characteristic.name();
+        info!("Writing '{input}' to {name} of {}", device.alias);
+
+        let written = match parse_value(input) {
+            Ok(value) => {
+                device
+                    .write_characteristic(characteristic.service_id, characteristic.id, value)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = written {
+            self.notify_gatt_failure(device, &e);
+            return;
+        }
+
+        let msg = t!(
+            "notifications.bt.characteristic_written",
+            characteristic = name
+        );
+        info!("{msg}");
+        try_send_notification!(
+            self.notification_manager,
+            None,
+            Some(msg.to_string()),
+            Some("bluetooth"),
+            None,
+            None
+        );
+    }
+
+    fn notify_gatt_failure(&self, device: &Device, err: &anyhow::Error) {
+        let msg = t!(
+            "notifications.bt.gatt_failed",
+            device_name = device.alias,
+            error = BluetoothError::classify(err).describe()
+        );
+
+        info!("{msg}");
+        try_send_notification!(
+            self.notification_manager,
+            None,
+            Some(msg.to_string()),
+            Some("bluetooth"),
+            None,
+            None
+        );
+    }
+
+    async fn perform_adapter_disable(&mut self) -> Result<()> {
         self.controller.power_off().await?;
 
         let msg = t!("notifications.bt.adapter_disabled").to_string();
@@ -703,13 +1787,318 @@ impl App {
             None
         );
 
-        if self.interactive {
-            self.handle_adapter_options(menu, menu_command, icon_type, spaces)
-                .await?;
-        } else {
-            self.running = false;
+        Ok(())
+    }
+}
+
+fn scan_progress_body(tally: &ScanTally) -> String {
+    match (tally.len(), tally.audio()) {
+        (0, _) => t!("notifications.bt.scan_in_progress").to_string(),
+        (count, 0) => t!("notifications.bt.scan_found", count = count).to_string(),
+        (count, audio) => t!(
+            "notifications.bt.scan_found_audio",
+            count = count,
+            audio = audio
+        )
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bz::fake::{FakeAdapter, FakeDevice, FakeOutcome};
+    use bluer::ErrorKind;
+    use std::time::Duration;
+
+    const ADAPTER: Address = Address::new([0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]);
+    const HEADPHONES: Address = Address::new([0xc0, 0xff, 0xee, 0x12, 0x34, 0x56]);
+    const SPEAKER: Address = Address::new([0xe0, 0x00, 0x00, 0x00, 0x00, 0x02]);
+
+    fn config() -> AppConfig {
+        AppConfig {
+            scan_duration: 1,
+            interactive: false,
+            quick_connect: false,
+            auto_scan: false,
+            device_order: DeviceOrder::default(),
+            new_device_order: DeviceOrder::Address,
+            filter: DeviceFilter::default(),
+            prune_after: None,
+            state_dir: None,
+            connection_policy: ConnectionPolicy {
+                retries: 2,
+                retry_delay: Duration::from_millis(10),
+                timeout: Duration::from_secs(5),
+            },
+            subscription_output: SubscriptionOutput::default(),
         }
+    }
 
-        Ok(())
+    async fn app_with(adapter: &FakeAdapter, config: AppConfig) -> App {
+        App::with_backend(
+            Arc::new(adapter.clone()),
+            Arc::new(Icons::default()),
+            config,
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn device(app: &App, addr: Address) -> Device {
+        Device::new(&app.controller.adapter, &addr).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn connects_paired_device() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER)
+            .with_device(FakeDevice::new(HEADPHONES, "Headphones").paired(true));
+        let app = app_with(&adapter, config()).await;
+
+        let outcome = app
+            .perform_device_connection(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+
+        let fake = adapter.fake_device(HEADPHONES).unwrap();
+        assert!(matches!(outcome, ConnectionOutcome::Connected));
+        assert_eq!(fake.pair_attempts(), 0);
+        assert_eq!(fake.connect_attempts(), 1);
+        assert!(fake.is_connected_now());
+    }
+
+    #[tokio::test]
+    async fn pairs_new_device_before_connecting() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER)
+            .with_device(FakeDevice::new(HEADPHONES, "Headphones"));
+        let app = app_with(&adapter, config()).await;
+
+        let outcome = app
+            .perform_device_connection(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+
+        let fake = adapter.fake_device(HEADPHONES).unwrap();
+        assert!(matches!(outcome, ConnectionOutcome::Connected));
+        assert_eq!(fake.pair_attempts(), 1);
+        assert!(fake.is_paired_now());
+        assert!(fake.is_connected_now());
+    }
+
+    #[tokio::test]
+    async fn stops_when_pairing_is_rejected() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .pair_outcomes([FakeOutcome::Fail(ErrorKind::AuthenticationRejected)]),
+        );
+        let app = app_with(&adapter, config()).await;
+
+        let outcome = app
+            .perform_device_connection(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+
+        let fake = adapter.fake_device(HEADPHONES).unwrap();
+        assert!(matches!(
+            outcome,
+            ConnectionOutcome::Failed(BluetoothError::AuthenticationRejected)
+        ));
+        assert_eq!(fake.connect_attempts(), 0);
+    }
+
+    #[tokio::test]
+    async fn retries_transient_connection_failures() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connect_outcomes([
+                    FakeOutcome::Fail(ErrorKind::InProgress),
+                    FakeOutcome::Fail(ErrorKind::NotReady),
+                ]),
+        );
+        let app = app_with(&adapter, config()).await;
+
+        let outcome = app
+            .perform_device_connection(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+
+        assert!(matches!(outcome, ConnectionOutcome::Connected));
+        assert_eq!(
+            adapter.fake_device(HEADPHONES).unwrap().connect_attempts(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_last_attempt() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connect_outcomes(vec![FakeOutcome::Fail(ErrorKind::NotReady); 3]),
+        );
+        let app = app_with(&adapter, config()).await;
+
+        let outcome = app
+            .perform_device_connection(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            ConnectionOutcome::Failed(BluetoothError::NotReady)
+        ));
+        assert_eq!(
+            adapter.fake_device(HEADPHONES).unwrap().connect_attempts(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_authentication_failures() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connect_outcomes([FakeOutcome::Fail(ErrorKind::AuthenticationFailed)]),
+        );
+        let app = app_with(&adapter, config()).await;
+
+        let outcome = app
+            .perform_device_connection(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+
+        match outcome {
+            ConnectionOutcome::Failed(error) => assert!(error.is_authentication_error()),
+            _ => panic!("expected an authentication failure"),
+        }
+        assert_eq!(
+            adapter.fake_device(HEADPHONES).unwrap().connect_attempts(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn aborts_connection_on_timeout() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connect_outcomes([FakeOutcome::Hang]),
+        );
+        let mut config = config();
+        config.connection_policy.timeout = Duration::from_millis(100);
+        let app = app_with(&adapter, config).await;
+
+        let outcome = app
+            .perform_device_connection(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+
+        let fake = adapter.fake_device(HEADPHONES).unwrap();
+        assert!(matches!(
+            outcome,
+            ConnectionOutcome::Failed(BluetoothError::TimedOut(_))
+        ));
+        assert_eq!(fake.disconnects(), 1);
+        assert!(!fake.is_connected_now());
+    }
+
+    #[tokio::test]
+    async fn repair_forgets_and_pairs_again() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .trusted(true),
+        );
+        let app = app_with(&adapter, config()).await;
+
+        app.perform_device_repair(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+
+        let fake = adapter.fake_device(HEADPHONES).unwrap();
+        assert_eq!(fake.pair_attempts(), 1);
+        assert!(fake.is_paired_now());
+        assert!(!fake.is_trusted_now());
+        assert!(fake.is_connected_now());
+        assert!(!adapter.is_discovering_now());
+    }
+
+    #[tokio::test]
+    async fn forgetting_removes_device() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER)
+            .with_device(FakeDevice::new(HEADPHONES, "Headphones").paired(true));
+        let mut app = app_with(&adapter, config()).await;
+        assert_eq!(app.controller.paired_devices.len(), 1);
+
+        let forgotten = app
+            .perform_forget_device(&device(&app, HEADPHONES).await)
+            .await
+            .unwrap();
+        app.controller.refresh().await.unwrap();
+
+        assert!(forgotten);
+        assert!(app.controller.paired_devices.is_empty());
+        assert!(app.controller.new_devices.is_empty());
+    }
+
+    #[tokio::test]
+    async fn toggles_trust() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER)
+            .with_device(FakeDevice::new(HEADPHONES, "Headphones").paired(true));
+        let app = app_with(&adapter, config()).await;
+        let device = device(&app, HEADPHONES).await;
+
+        app.perform_trust_device(&device, true).await.unwrap();
+        assert!(adapter.fake_device(HEADPHONES).unwrap().is_trusted_now());
+
+        app.perform_trust_device(&device, false).await.unwrap();
+        assert!(!adapter.fake_device(HEADPHONES).unwrap().is_trusted_now());
+    }
+
+    #[tokio::test]
+    async fn toggles_device_by_alias() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER)
+            .with_device(FakeDevice::new(HEADPHONES, "Headphones").paired(true));
+        let mut app = app_with(&adapter, config()).await;
+        let fake = adapter.fake_device(HEADPHONES).unwrap();
+
+        app.toggle_device("headphones").await.unwrap();
+        assert!(fake.is_connected_now());
+
+        app.controller.refresh().await.unwrap();
+        app.toggle_device(&HEADPHONES.to_string()).await.unwrap();
+        assert!(!fake.is_connected_now());
+
+        assert!(app.toggle_device("Speaker").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn toggle_fails_when_disconnecting_fails() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connected(true)
+                .disconnect_outcomes([FakeOutcome::Fail(ErrorKind::Failed)]),
+        );
+        let app = app_with(&adapter, config()).await;
+
+        assert!(app.toggle_device("headphones").await.is_err());
+        assert!(adapter.fake_device(HEADPHONES).unwrap().is_connected_now());
+    }
+
+    #[tokio::test]
+    async fn scans_until_device_appears_and_connects() {
+        let adapter = FakeAdapter::new("hci0", ADAPTER)
+            .with_discoverable_device(FakeDevice::new(SPEAKER, "JBL Flip 5"));
+        let mut app = app_with(&adapter, config()).await;
+
+        let device = app.scan_until("jbl*", 5, true).await.unwrap();
+        assert_eq!(device.addr, SPEAKER);
+        assert!(!adapter.is_discovering_now());
+
+        let fake = adapter.fake_device(SPEAKER).unwrap();
+        assert!(fake.is_paired_now());
+        assert!(fake.is_connected_now());
     }
 }
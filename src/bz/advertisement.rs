@@ -0,0 +1,116 @@
+//! Advertisement data of LE devices, as last received by BlueZ.
+
+use bluer::{id::Manufacturer, Uuid};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use crate::bz::backend::DeviceBackend;
+
+/// Bluetooth SIG Base UUID, which 16 and 32-bit UUIDs are shorthands for.
+pub(crate) const BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_0080_5f9b_34fb;
+
+/// Bits of the advertising Flags field, from the Core Specification
+/// Supplement, Part A, 1.3.
+const FLAGS: &[(u8, &str)] = &[
+    (0x01, "LE Limited Discoverable"),
+    (0x02, "LE General Discoverable"),
+    (0x04, "BR/EDR Not Supported"),
+    (0x08, "LE and BR/EDR Controller"),
+    (0x10, "LE and BR/EDR Host"),
+];
+
+/// What a device put in its latest advertisement. BlueZ keeps these up to
+/// date while discovering.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Advertisement {
+    pub manufacturer_data: BTreeMap<u16, Vec<u8>>,
+    pub service_data: BTreeMap<Uuid, Vec<u8>>,
+    pub flags: Option<u8>,
+    pub tx_power: Option<i16>,
+}
+
+impl Advertisement {
+    /// Reads the advertisement of `device`. Missing properties are left
+    /// empty, as devices that never advertised have none of them.
+    pub async fn read(device: &dyn DeviceBackend) -> Self {
+        Self {
+            manufacturer_data: device
+                .manufacturer_data()
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            service_data: device
+                .service_data()
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            flags: device
+                .advertising_flags()
+                .await
+                .ok()
+                .flatten()
+                .and_then(|flags| flags.first().copied()),
+            tx_power: device.tx_power().await.ok().flatten(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.manufacturer_data.is_empty()
+            && self.service_data.is_empty()
+            && self.flags.is_none()
+            && self.tx_power.is_none()
+    }
+}
+
+/// Name the Bluetooth SIG assigned to company identifier `id`, from the
+/// Bluetooth numbers database bundled with bluer.
+pub fn company_name(id: u16) -> Option<String> {
+    Manufacturer::try_from(id)
+        .ok()
+        .map(|company| company.to_string())
+}
+
+/// Company name followed by its identifier, such as `Apple, Inc. (0x004C)`.
+pub fn format_company(id: u16) -> String {
+    match company_name(id) {
+        Some(name) => format!("{name} (0x{id:04X})"),
+        None => format!("0x{id:04X}"),
+    }
+}
+
+/// Names of the bits set in an advertising Flags field.
+pub fn flag_names(flags: u8) -> Vec<&'static str> {
+    FLAGS
+        .iter()
+        .filter(|&&(bit, _)| flags & bit != 0)
+        .map(|&(_, name)| name)
+        .collect()
+}
+
+/// UUID in its short form, such as `0xFEAA`, when it derives from the
+/// Bluetooth SIG Base UUID.
+pub fn format_uuid(uuid: &Uuid) -> String {
+    let value = uuid.as_u128();
+    if value & ((1 << 96) - 1) == BASE_UUID {
+        match value >> 96 {
+            short @ 0..=0xFFFF => format!("0x{short:04X}"),
+            short => format!("0x{short:08X}"),
+        }
+    } else {
+        uuid.to_string()
+    }
+}
+
+/// Bytes as uppercase hex digits, with `separator` between bytes.
+pub fn format_hex(bytes: &[u8], separator: &str) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
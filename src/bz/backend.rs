@@ -0,0 +1,336 @@
+//! Abstraction over the BlueZ D-Bus API used by the `bz` layer.
+//!
+//! [`BluerAdapter`] forwards to `bluer` and is what the application runs on.
+//! `FakeAdapter`, behind the `fake` feature, implements the same traits in
+//! memory so the rest of the crate can be exercised without an adapter.
+
+use anyhow::Result;
+use bluer::{Adapter, AdapterEvent, Address, Device, Session, Uuid};
+use futures_util::{stream::BoxStream, FutureExt, StreamExt};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+};
+
+use crate::bz::gatt::{GattCharacteristic, GattService};
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Discovery stays active for as long as the returned stream is alive.
+pub type DiscoveryStream = BoxStream<'static, AdapterEvent>;
+
+/// Values notified or indicated by a characteristic. Its notifications stay
+/// enabled for as long as the stream is alive.
+pub type ValueStream = BoxStream<'static, Vec<u8>>;
+
+pub trait AdapterBackend: Debug + Send + Sync {
+    fn name(&self) -> &str;
+    fn address(&self) -> BackendFuture<'_, Address>;
+    fn alias(&self) -> BackendFuture<'_, String>;
+    fn is_powered(&self) -> BackendFuture<'_, bool>;
+    fn is_pairable(&self) -> BackendFuture<'_, bool>;
+    fn is_discoverable(&self) -> BackendFuture<'_, bool>;
+    fn is_discovering(&self) -> BackendFuture<'_, bool>;
+    fn set_powered(&self, powered: bool) -> BackendFuture<'_, ()>;
+    fn set_pairable(&self, pairable: bool) -> BackendFuture<'_, ()>;
+    fn set_discoverable(&self, discoverable: bool) -> BackendFuture<'_, ()>;
+    fn device_addresses(&self) -> BackendFuture<'_, Vec<Address>>;
+    fn device(&self, addr: Address) -> Result<Arc<dyn DeviceBackend>>;
+    fn remove_device(&self, addr: Address) -> BackendFuture<'_, ()>;
+    fn discover_devices(&self) -> BackendFuture<'_, DiscoveryStream>;
+}
+
+pub trait DeviceBackend: Debug + Send + Sync {
+    fn address(&self) -> Address;
+    fn alias(&self) -> BackendFuture<'_, String>;
+    /// Remote name, unlike the alias absent until the device sent one.
+    fn name(&self) -> BackendFuture<'_, Option<String>>;
+    fn icon(&self) -> BackendFuture<'_, Option<String>>;
+    fn class(&self) -> BackendFuture<'_, Option<u32>>;
+    fn appearance(&self) -> BackendFuture<'_, Option<u16>>;
+    fn uuids(&self) -> BackendFuture<'_, Option<HashSet<Uuid>>>;
+    fn is_paired(&self) -> BackendFuture<'_, bool>;
+    fn is_trusted(&self) -> BackendFuture<'_, bool>;
+    fn is_connected(&self) -> BackendFuture<'_, bool>;
+    fn battery_percentage(&self) -> BackendFuture<'_, Option<u8>>;
+    /// Signal strength in dBm, only known while the device is in range
+    /// during discovery.
+    fn rssi(&self) -> BackendFuture<'_, Option<i16>>;
+    /// Advertised transmit power in dBm, when the device includes it.
+    fn tx_power(&self) -> BackendFuture<'_, Option<i16>>;
+    /// Manufacturer specific data of the latest advertisement, by company
+    /// identifier.
+    fn manufacturer_data(&self) -> BackendFuture<'_, Option<HashMap<u16, Vec<u8>>>>;
+    /// Service data of the latest advertisement, by service UUID.
+    fn service_data(&self) -> BackendFuture<'_, Option<HashMap<Uuid, Vec<u8>>>>;
+    /// Flags field of the latest advertisement.
+    fn advertising_flags(&self) -> BackendFuture<'_, Option<Vec<u8>>>;
+    fn connect(&self) -> BackendFuture<'_, ()>;
+    fn disconnect(&self) -> BackendFuture<'_, ()>;
+    fn pair(&self) -> BackendFuture<'_, ()>;
+    fn set_trusted(&self, trusted: bool) -> BackendFuture<'_, ()>;
+    /// Whether BlueZ exposes GATT services for the device. Only devices
+    /// connected over LE, or the few serving GATT over BR/EDR, have any.
+    /// False until BlueZ resolved them, rather than waiting for it.
+    fn has_gatt_services(&self) -> BackendFuture<'_, bool>;
+    /// GATT services, only resolved while the device is connected.
+    fn gatt_services(&self) -> BackendFuture<'_, Vec<GattService>>;
+    fn gatt_characteristics(&self, service_id: u16) -> BackendFuture<'_, Vec<GattCharacteristic>>;
+    fn read_characteristic(&self, service_id: u16, id: u16) -> BackendFuture<'_, Vec<u8>>;
+    fn write_characteristic(
+        &self,
+        service_id: u16,
+        id: u16,
+        value: Vec<u8>,
+    ) -> BackendFuture<'_, ()>;
+    fn subscribe_characteristic(&self, service_id: u16, id: u16) -> BackendFuture<'_, ValueStream>;
+}
+
+/// Backend talking to `bluetoothd` through `bluer`.
+#[derive(Debug, Clone)]
+pub struct BluerAdapter {
+    adapter: Adapter,
+}
+
+impl BluerAdapter {
+    pub fn new(adapter: Adapter) -> Self {
+        Self { adapter }
+    }
+
+    /// Opens the first adapter known to BlueZ.
+    pub async fn first(session: &Session) -> Result<Self> {
+        let adapter_names = session.adapter_names().await?;
+        let adapter_name = adapter_names
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No Bluetooth adapter found"))?;
+
+        Ok(Self::new(session.adapter(adapter_name)?))
+    }
+}
+
+macro_rules! forward {
+    ($target:expr) => {
+        async move { Ok($target.await?) }.boxed()
+    };
+}
+
+impl AdapterBackend for BluerAdapter {
+    fn name(&self) -> &str {
+        self.adapter.name()
+    }
+
+    fn address(&self) -> BackendFuture<'_, Address> {
+        forward!(self.adapter.address())
+    }
+
+    fn alias(&self) -> BackendFuture<'_, String> {
+        forward!(self.adapter.alias())
+    }
+
+    fn is_powered(&self) -> BackendFuture<'_, bool> {
+        forward!(self.adapter.is_powered())
+    }
+
+    fn is_pairable(&self) -> BackendFuture<'_, bool> {
+        forward!(self.adapter.is_pairable())
+    }
+
+    fn is_discoverable(&self) -> BackendFuture<'_, bool> {
+        forward!(self.adapter.is_discoverable())
+    }
+
+    fn is_discovering(&self) -> BackendFuture<'_, bool> {
+        forward!(self.adapter.is_discovering())
+    }
+
+    fn set_powered(&self, powered: bool) -> BackendFuture<'_, ()> {
+        forward!(self.adapter.set_powered(powered))
+    }
+
+    fn set_pairable(&self, pairable: bool) -> BackendFuture<'_, ()> {
+        forward!(self.adapter.set_pairable(pairable))
+    }
+
+    fn set_discoverable(&self, discoverable: bool) -> BackendFuture<'_, ()> {
+        forward!(self.adapter.set_discoverable(discoverable))
+    }
+
+    fn device_addresses(&self) -> BackendFuture<'_, Vec<Address>> {
+        forward!(self.adapter.device_addresses())
+    }
+
+    fn device(&self, addr: Address) -> Result<Arc<dyn DeviceBackend>> {
+        Ok(Arc::new(BluerDevice {
+            device: self.adapter.device(addr)?,
+        }))
+    }
+
+    fn remove_device(&self, addr: Address) -> BackendFuture<'_, ()> {
+        forward!(self.adapter.remove_device(addr))
+    }
+
+    fn discover_devices(&self) -> BackendFuture<'_, DiscoveryStream> {
+        async move { Ok(self.adapter.discover_devices().await?.boxed()) }.boxed()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BluerDevice {
+    device: Device,
+}
+
+impl DeviceBackend for BluerDevice {
+    fn address(&self) -> Address {
+        self.device.address()
+    }
+
+    fn alias(&self) -> BackendFuture<'_, String> {
+        forward!(self.device.alias())
+    }
+
+    fn name(&self) -> BackendFuture<'_, Option<String>> {
+        forward!(self.device.name())
+    }
+
+    fn icon(&self) -> BackendFuture<'_, Option<String>> {
+        forward!(self.device.icon())
+    }
+
+    fn class(&self) -> BackendFuture<'_, Option<u32>> {
+        forward!(self.device.class())
+    }
+
+    fn appearance(&self) -> BackendFuture<'_, Option<u16>> {
+        forward!(self.device.appearance())
+    }
+
+    fn uuids(&self) -> BackendFuture<'_, Option<HashSet<Uuid>>> {
+        forward!(self.device.uuids())
+    }
+
+    fn is_paired(&self) -> BackendFuture<'_, bool> {
+        forward!(self.device.is_paired())
+    }
+
+    fn is_trusted(&self) -> BackendFuture<'_, bool> {
+        forward!(self.device.is_trusted())
+    }
+
+    fn is_connected(&self) -> BackendFuture<'_, bool> {
+        forward!(self.device.is_connected())
+    }
+
+    fn battery_percentage(&self) -> BackendFuture<'_, Option<u8>> {
+        forward!(self.device.battery_percentage())
+    }
+
+    fn rssi(&self) -> BackendFuture<'_, Option<i16>> {
+        forward!(self.device.rssi())
+    }
+
+    fn tx_power(&self) -> BackendFuture<'_, Option<i16>> {
+        forward!(self.device.tx_power())
+    }
+
+    fn manufacturer_data(&self) -> BackendFuture<'_, Option<HashMap<u16, Vec<u8>>>> {
+        forward!(self.device.manufacturer_data())
+    }
+
+    fn service_data(&self) -> BackendFuture<'_, Option<HashMap<Uuid, Vec<u8>>>> {
+        forward!(self.device.service_data())
+    }
+
+    fn advertising_flags(&self) -> BackendFuture<'_, Option<Vec<u8>>> {
+        forward!(self.device.advertising_flags())
+    }
+
+    fn connect(&self) -> BackendFuture<'_, ()> {
+        forward!(self.device.connect())
+    }
+
+    fn disconnect(&self) -> BackendFuture<'_, ()> {
+        forward!(self.device.disconnect())
+    }
+
+    fn pair(&self) -> BackendFuture<'_, ()> {
+        forward!(self.device.pair())
+    }
+
+    fn set_trusted(&self, trusted: bool) -> BackendFuture<'_, ()> {
+        forward!(self.device.set_trusted(trusted))
+    }
+
+    fn has_gatt_services(&self) -> BackendFuture<'_, bool> {
+        async move {
+            // Listing services waits up to two minutes for them to resolve.
+            Ok(self.device.is_services_resolved().await?
+                && !self.device.services().await?.is_empty())
+        }
+        .boxed()
+    }
+
+    fn gatt_services(&self) -> BackendFuture<'_, Vec<GattService>> {
+        async move {
+            let mut services = Vec::new();
+            for service in self.device.services().await? {
+                services.push(GattService {
+                    id: service.id(),
+                    uuid: service.uuid().await?,
+                    primary: service.primary().await?,
+                });
+            }
+            Ok(services)
+        }
+        .boxed()
+    }
+
+    fn gatt_characteristics(&self, service_id: u16) -> BackendFuture<'_, Vec<GattCharacteristic>> {
+        async move {
+            let service = self.device.service(service_id).await?;
+            let mut characteristics = Vec::new();
+            for characteristic in service.characteristics().await? {
+                characteristics.push(GattCharacteristic {
+                    service_id,
+                    id: characteristic.id(),
+                    uuid: characteristic.uuid().await?,
+                    flags: characteristic.flags().await?,
+                });
+            }
+            Ok(characteristics)
+        }
+        .boxed()
+    }
+
+    fn read_characteristic(&self, service_id: u16, id: u16) -> BackendFuture<'_, Vec<u8>> {
+        async move {
+            let service = self.device.service(service_id).await?;
+            Ok(service.characteristic(id).await?.read().await?)
+        }
+        .boxed()
+    }
+
+    fn write_characteristic(
+        &self,
+        service_id: u16,
+        id: u16,
+        value: Vec<u8>,
+    ) -> BackendFuture<'_, ()> {
+        async move {
+            let service = self.device.service(service_id).await?;
+            Ok(service.characteristic(id).await?.write(&value).await?)
+        }
+        .boxed()
+    }
+
+    fn subscribe_characteristic(&self, service_id: u16, id: u16) -> BackendFuture<'_, ValueStream> {
+        async move {
+            let service = self.device.service(service_id).await?;
+            let values = service.characteristic(id).await?.notify().await?;
+            Ok(values.boxed())
+        }
+        .boxed()
+    }
+}
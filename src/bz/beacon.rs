@@ -0,0 +1,302 @@
+//! Decoders for well-known beacon and sensor broadcasts found in
+//! [`Advertisement`]s.
+
+use bluer::Uuid;
+use serde::Serialize;
+
+use crate::bz::advertisement::{format_hex, Advertisement, BASE_UUID};
+
+const APPLE: u16 = 0x004C;
+const EDDYSTONE: Uuid = sig_uuid(0xFEAA);
+const BTHOME: Uuid = sig_uuid(0xFCD2);
+const MIBEACON: Uuid = sig_uuid(0xFE95);
+/// Environmental Sensing service, which the custom firmwares of Xiaomi
+/// thermometers (ATC1441 and pvvx) broadcast under.
+const ENVIRONMENTAL_SENSING: Uuid = sig_uuid(0x181A);
+
+const fn sig_uuid(short: u16) -> Uuid {
+    Uuid::from_u128(((short as u128) << 96) | BASE_UUID)
+}
+
+/// A broadcast decoded from an advertisement.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum Beacon {
+    #[serde(rename = "ibeacon")]
+    IBeacon {
+        uuid: Uuid,
+        major: u16,
+        minor: u16,
+        /// Calibrated signal strength at 1 m, in dBm.
+        measured_power: i8,
+    },
+    EddystoneUid {
+        namespace: String,
+        instance: String,
+        /// Calibrated transmit power at 0 m, in dBm.
+        tx_power: i8,
+    },
+    EddystoneUrl {
+        url: String,
+        tx_power: i8,
+    },
+    EddystoneTlm {
+        battery_mv: Option<u16>,
+        temperature: Option<f32>,
+        advertisements: u32,
+        /// Time since the beacon booted, in seconds.
+        uptime: u32,
+    },
+    Sensor {
+        source: SensorSource,
+        #[serde(flatten)]
+        reading: SensorReading,
+    },
+}
+
+/// Format a [`Beacon::Sensor`] was decoded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorSource {
+    BtHome,
+    MiBeacon,
+    Atc1441,
+    Pvvx,
+}
+
+impl SensorSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SensorSource::BtHome => "BTHome",
+            SensorSource::MiBeacon => "MiBeacon",
+            SensorSource::Atc1441 => "ATC1441",
+            SensorSource::Pvvx => "pvvx",
+        }
+    }
+}
+
+/// Measurements of an environmental sensor. Temperatures are in degrees
+/// Celsius, humidity and battery in percent.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SensorReading {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_mv: Option<u16>,
+}
+
+impl SensorReading {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Decodes every broadcast of a known format in `advertisement`.
+pub fn decode(advertisement: &Advertisement) -> Vec<Beacon> {
+    let manufacturer = advertisement
+        .manufacturer_data
+        .iter()
+        .filter_map(|(&company, data)| match company {
+            APPLE => decode_ibeacon(data),
+            _ => None,
+        });
+
+    let services = advertisement
+        .service_data
+        .iter()
+        .filter_map(|(uuid, data)| match *uuid {
+            EDDYSTONE => decode_eddystone(data),
+            BTHOME => decode_bthome(data),
+            MIBEACON => decode_mibeacon(data),
+            ENVIRONMENTAL_SENSING => decode_thermometer(data),
+            _ => None,
+        });
+
+    manufacturer.chain(services).collect()
+}
+
+/// Apple iBeacon: type 0x02, length 0x15, proximity UUID, major, minor and
+/// measured power, all big-endian.
+pub fn decode_ibeacon(data: &[u8]) -> Option<Beacon> {
+    let [0x02, 0x15, rest @ ..] = data else {
+        return None;
+    };
+    if rest.len() != 21 {
+        return None;
+    }
+
+    Some(Beacon::IBeacon {
+        uuid: Uuid::from_slice(&rest[..16]).ok()?,
+        major: u16::from_be_bytes([rest[16], rest[17]]),
+        minor: u16::from_be_bytes([rest[18], rest[19]]),
+        measured_power: rest[20] as i8,
+    })
+}
+
+/// Eddystone UID, URL and unencrypted TLM frames.
+pub fn decode_eddystone(data: &[u8]) -> Option<Beacon> {
+    match data {
+        [0x00, tx_power, rest @ ..] if rest.len() >= 16 => Some(Beacon::EddystoneUid {
+            namespace: format_hex(&rest[..10], ""),
+            instance: format_hex(&rest[10..16], ""),
+            tx_power: *tx_power as i8,
+        }),
+        [0x10, tx_power, scheme, encoded @ ..] => Some(Beacon::EddystoneUrl {
+            url: decode_eddystone_url(*scheme, encoded)?,
+            tx_power: *tx_power as i8,
+        }),
+        [0x20, 0x00, rest @ ..] if rest.len() >= 12 => {
+            let battery_mv = u16::from_be_bytes([rest[0], rest[1]]);
+            let temperature = i16::from_be_bytes([rest[2], rest[3]]);
+            Some(Beacon::EddystoneTlm {
+                battery_mv: (battery_mv != 0).then_some(battery_mv),
+                // Signed 8.8 fixed point, 0x8000 when unsupported.
+                temperature: (temperature != i16::MIN).then(|| temperature as f32 / 256.0),
+                advertisements: u32::from_be_bytes([rest[4], rest[5], rest[6], rest[7]]),
+                uptime: u32::from_be_bytes([rest[8], rest[9], rest[10], rest[11]]) / 10,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn decode_eddystone_url(scheme: u8, encoded: &[u8]) -> Option<String> {
+    const SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+    const EXPANSIONS: [&str; 14] = [
+        ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/", ".com", ".org", ".edu",
+        ".net", ".info", ".biz", ".gov",
+    ];
+
+    let mut url = SCHEMES.get(scheme as usize)?.to_string();
+    for &byte in encoded {
+        match byte {
+            0x00..=0x0D => url.push_str(EXPANSIONS[byte as usize]),
+            0x21..=0x7E => url.push(byte as char),
+            _ => return None,
+        }
+    }
+    Some(url)
+}
+
+/// BTHome v2, unencrypted. Objects are read until one of unknown size is
+/// met, as the rest of the payload cannot be located past it.
+pub fn decode_bthome(data: &[u8]) -> Option<Beacon> {
+    let (&info, mut objects) = data.split_first()?;
+    let encrypted = info & 0x01 != 0;
+    let version = info >> 5;
+    if encrypted || version != 2 {
+        return None;
+    }
+
+    let mut reading = SensorReading::default();
+    while let Some((&id, rest)) = objects.split_first() {
+        let size = match id {
+            0x00 | 0x01 | 0x2E => 1,
+            0x02 | 0x03 | 0x0C | 0x45 => 2,
+            0x04 | 0x05 => 3,
+            _ => break,
+        };
+        let Some(value) = rest.get(..size) else {
+            break;
+        };
+
+        match id {
+            0x01 => reading.battery = Some(value[0]),
+            0x02 => {
+                reading.temperature = Some(i16::from_le_bytes([value[0], value[1]]) as f32 / 100.0)
+            }
+            0x03 => {
+                reading.humidity = Some(u16::from_le_bytes([value[0], value[1]]) as f32 / 100.0)
+            }
+            0x0C => reading.battery_mv = Some(u16::from_le_bytes([value[0], value[1]])),
+            0x2E => reading.humidity = Some(value[0] as f32),
+            0x45 => {
+                reading.temperature = Some(i16::from_le_bytes([value[0], value[1]]) as f32 / 10.0)
+            }
+            _ => {}
+        }
+        objects = &rest[size..];
+    }
+
+    sensor(SensorSource::BtHome, reading)
+}
+
+/// Xiaomi MiBeacon carrying an unencrypted temperature, humidity or battery
+/// object.
+pub fn decode_mibeacon(data: &[u8]) -> Option<Beacon> {
+    let control = u16::from_le_bytes([*data.first()?, *data.get(1)?]);
+    let encrypted = control & 0x0008 != 0;
+    let has_mac = control & 0x0010 != 0;
+    let has_capability = control & 0x0020 != 0;
+    let has_object = control & 0x0040 != 0;
+    if encrypted || !has_object {
+        return None;
+    }
+
+    // Frame control, product id and frame counter come first.
+    let mut offset = 5;
+    if has_mac {
+        offset += 6;
+    }
+    if has_capability {
+        offset += 1;
+    }
+
+    let object = data.get(offset..)?;
+    let [id_low, id_high, length, value @ ..] = object else {
+        return None;
+    };
+    let value = value.get(..*length as usize)?;
+
+    let temperature = |bytes: &[u8]| i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 10.0;
+    let humidity = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 10.0;
+
+    let mut reading = SensorReading::default();
+    match (u16::from_le_bytes([*id_low, *id_high]), value.len()) {
+        (0x1004, 2) => reading.temperature = Some(temperature(value)),
+        (0x1006, 2) => reading.humidity = Some(humidity(value)),
+        (0x100A, 1) => reading.battery = Some(value[0]),
+        (0x100D, 4) => {
+            reading.temperature = Some(temperature(&value[..2]));
+            reading.humidity = Some(humidity(&value[2..]));
+        }
+        _ => return None,
+    }
+
+    sensor(SensorSource::MiBeacon, reading)
+}
+
+/// Custom firmwares of Xiaomi thermometers, told apart by length: ATC1441
+/// sends 13 big-endian bytes, pvvx 15 little-endian ones. Both start with
+/// the MAC address.
+pub fn decode_thermometer(data: &[u8]) -> Option<Beacon> {
+    match data.len() {
+        13 => sensor(
+            SensorSource::Atc1441,
+            SensorReading {
+                temperature: Some(i16::from_be_bytes([data[6], data[7]]) as f32 / 10.0),
+                humidity: Some(data[8] as f32),
+                battery: Some(data[9]),
+                battery_mv: Some(u16::from_be_bytes([data[10], data[11]])),
+            },
+        ),
+        15 => sensor(
+            SensorSource::Pvvx,
+            SensorReading {
+                temperature: Some(i16::from_le_bytes([data[6], data[7]]) as f32 / 100.0),
+                humidity: Some(u16::from_le_bytes([data[8], data[9]]) as f32 / 100.0),
+                battery_mv: Some(u16::from_le_bytes([data[10], data[11]])),
+                battery: Some(data[12]),
+            },
+        ),
+        _ => None,
+    }
+}
+
+fn sensor(source: SensorSource, reading: SensorReading) -> Option<Beacon> {
+    (!reading.is_empty()).then_some(Beacon::Sensor { source, reading })
+}
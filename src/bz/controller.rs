@@ -1,14 +1,18 @@
-use anyhow::{anyhow, Result};
-use bluer::{Adapter, Session};
-use log::info;
+use anyhow::Result;
+use bluer::{Address, Session};
+use log::{info, warn};
 use std::sync::{atomic::AtomicBool, Arc};
 
-use crate::bz::device::Device;
+use crate::bz::{
+    backend::{AdapterBackend, BluerAdapter},
+    device::Device,
+};
 
 #[derive(Debug, Clone)]
 pub struct Controller {
-    pub adapter: Arc<Adapter>,
+    pub adapter: Arc<dyn AdapterBackend>,
     pub name: String,
+    pub address: Address,
     pub alias: String,
     pub is_powered: bool,
     pub is_pairable: bool,
@@ -20,15 +24,13 @@ pub struct Controller {
 
 impl Controller {
     pub async fn new(session: Arc<Session>) -> Result<Self> {
-        let adapter_names = session.adapter_names().await?;
-        let adapter_name = adapter_names
-            .first()
-            .ok_or_else(|| anyhow!("No Bluetooth adapter found"))?;
-
-        let adapter = session.adapter(adapter_name)?;
-        let adapter_arc = Arc::new(adapter);
+        let adapter = BluerAdapter::first(&session).await?;
+        Self::with_backend(Arc::new(adapter)).await
+    }
 
+    pub async fn with_backend(adapter_arc: Arc<dyn AdapterBackend>) -> Result<Self> {
         let name = adapter_arc.name().to_owned();
+        let address = adapter_arc.address().await?;
         let alias = adapter_arc.alias().await?;
         let is_powered = adapter_arc.is_powered().await?;
         let is_pairable = adapter_arc.is_pairable().await?;
@@ -42,6 +44,7 @@ impl Controller {
         Ok(Self {
             adapter: adapter_arc,
             name,
+            address,
             alias,
             is_powered,
             is_pairable,
@@ -84,7 +87,29 @@ impl Controller {
         Ok(())
     }
 
-    async fn get_devices(adapter: &Adapter) -> Result<(Vec<Device>, Vec<Device>)> {
+    /// Removes devices from BlueZ, skipping the ones that cannot be removed.
+    /// Returns how many were removed.
+    pub async fn remove_devices(&self, addrs: impl IntoIterator<Item = Address>) -> usize {
+        let mut removed = 0;
+        for addr in addrs {
+            match self.adapter.remove_device(addr).await {
+                Ok(()) => removed += 1,
+                Err(e) => warn!("Failed to remove device {addr}: {e}"),
+            }
+        }
+        removed
+    }
+
+    /// Looks up a known device by address, or by alias ignoring case. See
+    /// [`Device::matches`] for the patterns accepted.
+    pub fn find_device(&self, query: &str) -> Option<&Device> {
+        self.paired_devices
+            .iter()
+            .chain(&self.new_devices)
+            .find(|device| device.matches(query))
+    }
+
+    async fn get_devices(adapter: &Arc<dyn AdapterBackend>) -> Result<(Vec<Device>, Vec<Device>)> {
         let mut paired_devices = Vec::new();
         let mut new_devices = Vec::new();
 
@@ -1,27 +1,36 @@
 use std::sync::Arc;
 
 use anyhow::Result;
-use bluer::{Adapter, Address, Device as BluerDevice};
+use bluer::Address;
+
+use crate::bz::{
+    backend::{AdapterBackend, DeviceBackend, ValueStream},
+    gatt::{assigned_number, GattCharacteristic, GattService},
+};
 
 #[derive(Debug, Clone)]
 pub struct Device {
-    device: BluerDevice,
-    adapter: Arc<Adapter>,
+    device: Arc<dyn DeviceBackend>,
+    adapter: Arc<dyn AdapterBackend>,
     pub addr: Address,
     pub icon: Option<String>,
     pub device_type: String,
     pub alias: String,
+    pub name: Option<String>,
     pub is_paired: bool,
     pub is_trusted: bool,
     pub is_connected: bool,
     pub battery_percentage: Option<u8>,
+    pub rssi: Option<i16>,
+    pub tx_power: Option<i16>,
 }
 
 impl Device {
-    pub async fn new(adapter: &Adapter, addr: &Address) -> Result<Self> {
+    pub async fn new(adapter: &Arc<dyn AdapterBackend>, addr: &Address) -> Result<Self> {
         let device = adapter.device(*addr)?;
 
         let alias = device.alias().await?;
+        let name = device.name().await.ok().flatten();
         let icon_name = device.icon().await?.unwrap_or_default();
         let icon = if !icon_name.is_empty() {
             Some(icon_name.clone())
@@ -29,28 +38,67 @@ impl Device {
             None
         };
 
-        let device_type = Self::determine_device_type(&device).await?;
+        let device_type = Self::determine_device_type(device.as_ref()).await?;
 
         let is_paired = device.is_paired().await?;
         let is_trusted = device.is_trusted().await?;
         let is_connected = device.is_connected().await?;
         let battery_percentage = device.battery_percentage().await.ok().flatten();
+        let rssi = device.rssi().await.ok().flatten();
+        let tx_power = device.tx_power().await.ok().flatten();
 
         Ok(Self {
             device,
-            adapter: Arc::new(adapter.clone()),
+            adapter: adapter.clone(),
             addr: *addr,
             icon,
             device_type,
             alias,
+            name,
             is_paired,
             is_trusted,
             is_connected,
             battery_percentage,
+            rssi,
+            tx_power,
         })
     }
 
-    async fn determine_device_type(device: &BluerDevice) -> Result<String> {
+    /// Whether `query` designates this device: its address, or its alias,
+    /// name or address where `*` and `?` match any run of characters and any
+    /// single character, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim();
+        if let Ok(addr) = query.parse::<Address>() {
+            return addr == self.addr;
+        }
+
+        let pattern: Vec<char> = query.to_lowercase().chars().collect();
+        let addr = self.addr.to_string();
+
+        [Some(self.alias.as_str()), self.name.as_deref(), Some(&addr)]
+            .iter()
+            .flatten()
+            .any(|text| {
+                let text: Vec<char> = text.to_lowercase().chars().collect();
+                wildcard_match(&pattern, &text)
+            })
+    }
+
+    pub fn is_audio(&self) -> bool {
+        matches!(
+            self.device_type.as_str(),
+            "audio" | "headphones" | "speaker" | "microphone"
+        )
+    }
+
+    /// Whether the device never sent a name, leaving BlueZ to fall back to
+    /// its address as the alias.
+    pub fn is_unnamed(&self) -> bool {
+        self.name.is_none() && self.alias == self.addr.to_string().replace(':', "-")
+    }
+
+    async fn determine_device_type(device: &dyn DeviceBackend) -> Result<String> {
         if let Ok(Some(class_value)) = device.class().await {
             let major_class = (class_value >> 8) & 0x1F;
             let minor_class = (class_value >> 2) & 0x3F;
@@ -223,8 +271,93 @@ impl Device {
         Ok(())
     }
 
+    /// Whether the device lists the service with assigned number `number`
+    /// among its UUIDs, which BlueZ fills in as it resolves services.
+    pub async fn has_service(&self, number: u16) -> bool {
+        self.device
+            .uuids()
+            .await
+            .ok()
+            .flatten()
+            .is_some_and(|uuids| {
+                uuids
+                    .iter()
+                    .any(|uuid| assigned_number(uuid) == Some(number))
+            })
+    }
+
+    /// Whether the device is connected and exposes GATT services, as LE
+    /// devices do. BlueZ reports classic devices with a public address too,
+    /// so the address type tells nothing. Only checked when a menu offers
+    /// the services, as it lists every object BlueZ exports.
+    pub async fn has_gatt_services(&self) -> bool {
+        self.is_connected && self.device.has_gatt_services().await.unwrap_or(false)
+    }
+
+    /// Primary GATT services, in handle order.
+    pub async fn primary_services(&self) -> Result<Vec<GattService>> {
+        let mut services = self.device.gatt_services().await?;
+        services.retain(|service| service.primary);
+        services.sort_by_key(|service| service.id);
+        Ok(services)
+    }
+
+    pub async fn characteristics(&self, service_id: u16) -> Result<Vec<GattCharacteristic>> {
+        let mut characteristics = self.device.gatt_characteristics(service_id).await?;
+        characteristics.sort_by_key(|characteristic| characteristic.id);
+        Ok(characteristics)
+    }
+
+    pub async fn read_characteristic(&self, service_id: u16, id: u16) -> Result<Vec<u8>> {
+        self.device.read_characteristic(service_id, id).await
+    }
+
+    pub async fn write_characteristic(
+        &self,
+        service_id: u16,
+        id: u16,
+        value: Vec<u8>,
+    ) -> Result<()> {
+        self.device
+            .write_characteristic(service_id, id, value)
+            .await
+    }
+
+    pub async fn subscribe_characteristic(&self, service_id: u16, id: u16) -> Result<ValueStream> {
+        self.device.subscribe_characteristic(service_id, id).await
+    }
+
     pub async fn forget(&self) -> Result<()> {
         self.adapter.remove_device(self.addr).await?;
         Ok(())
     }
 }
+
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character.
+                Some((star, start)) => {
+                    backtrack = Some((star, start + 1));
+                    p = star + 1;
+                    t = start + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,159 @@
+//! The standard Device Information Service, which most LE devices expose
+//! to tell who made them and which firmware they run.
+
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::bz::{advertisement::format_company, device::Device, gatt::assigned_number};
+
+const DEVICE_INFORMATION: u16 = 0x180A;
+
+const MANUFACTURER_NAME: u16 = 0x2A29;
+const MODEL_NUMBER: u16 = 0x2A24;
+const SERIAL_NUMBER: u16 = 0x2A25;
+const FIRMWARE_REVISION: u16 = 0x2A26;
+const HARDWARE_REVISION: u16 = 0x2A27;
+const SOFTWARE_REVISION: u16 = 0x2A28;
+const PNP_ID: u16 = 0x2A50;
+
+const CHARACTERISTICS: &[u16] = &[
+    MANUFACTURER_NAME,
+    MODEL_NUMBER,
+    SERIAL_NUMBER,
+    FIRMWARE_REVISION,
+    HARDWARE_REVISION,
+    SOFTWARE_REVISION,
+    PNP_ID,
+];
+
+/// What a device reports about itself. Each field is only set when the
+/// device has the characteristic and allowed reading it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub serial_number: Option<String>,
+    pub firmware_revision: Option<String>,
+    pub hardware_revision: Option<String>,
+    pub software_revision: Option<String>,
+    pub pnp_id: Option<PnpId>,
+}
+
+/// Identifies the vendor and product, like USB devices do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PnpId {
+    /// 1 when `vendor_id` is a Bluetooth SIG company identifier, 2 when it
+    /// is a USB-IF vendor ID.
+    pub vendor_id_source: u8,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub product_version: u16,
+}
+
+impl PnpId {
+    pub fn parse(value: &[u8]) -> Option<Self> {
+        if value.len() < 7 {
+            return None;
+        }
+        let word = |offset: usize| u16::from_le_bytes([value[offset], value[offset + 1]]);
+
+        Some(Self {
+            vendor_id_source: value[0],
+            vendor_id: word(1),
+            product_id: word(3),
+            product_version: word(5),
+        })
+    }
+}
+
+impl fmt::Display for PnpId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.vendor_id_source {
+            1 => write!(f, "{}", format_company(self.vendor_id))?,
+            2 => write!(f, "USB 0x{:04X}", self.vendor_id)?,
+            _ => write!(f, "0x{:04X}", self.vendor_id)?,
+        }
+        write!(
+            f,
+            ", product 0x{:04X}, version 0x{:04X}",
+            self.product_id, self.product_version
+        )
+    }
+}
+
+impl DeviceInfo {
+    /// Whether `device` is connected and lists the Device Information
+    /// Service, so that reading it can succeed.
+    pub async fn is_offered(device: &Device) -> bool {
+        device.is_connected && device.has_service(DEVICE_INFORMATION).await
+    }
+
+    /// Reads the Device Information Service of a connected device, or
+    /// returns `None` if it has none.
+    pub async fn read(device: &Device) -> Result<Option<Self>> {
+        let services = device.primary_services().await?;
+        let Some(service) = services
+            .iter()
+            .find(|service| assigned_number(&service.uuid) == Some(DEVICE_INFORMATION))
+        else {
+            return Ok(None);
+        };
+
+        let mut info = Self::default();
+        for characteristic in device.characteristics(service.id).await? {
+            let Some(number) = assigned_number(&characteristic.uuid)
+                .filter(|number| CHARACTERISTICS.contains(number))
+            else {
+                continue;
+            };
+            if !characteristic.is_readable() {
+                continue;
+            }
+
+            let value = match device
+                .read_characteristic(service.id, characteristic.id)
+                .await
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    debug!(
+                        "Failed to read {} of {}: {e}",
+                        characteristic.name(),
+                        device.alias
+                    );
+                    continue;
+                }
+            };
+
+            let field = match number {
+                MANUFACTURER_NAME => &mut info.manufacturer,
+                MODEL_NUMBER => &mut info.model,
+                SERIAL_NUMBER => &mut info.serial_number,
+                FIRMWARE_REVISION => &mut info.firmware_revision,
+                HARDWARE_REVISION => &mut info.hardware_revision,
+                SOFTWARE_REVISION => &mut info.software_revision,
+                PNP_ID => {
+                    info.pnp_id = PnpId::parse(&value);
+                    continue;
+                }
+                _ => continue,
+            };
+            *field = decode_string(&value);
+        }
+
+        Ok(Some(info))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// UTF-8 string values, which some devices pad with NULs or spaces.
+fn decode_string(value: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(value);
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
@@ -0,0 +1,176 @@
+use bluer::ErrorKind;
+use rust_i18n::t;
+use std::{borrow::Cow, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BluetoothError {
+    InProgress,
+    AlreadyConnected,
+    AuthenticationFailed,
+    AuthenticationRejected,
+    ProfileUnavailable,
+    NotReady,
+    PageTimeout,
+    /// The link could not be set up, for a reason BlueZ does not detail.
+    ConnectionFailed,
+    TimedOut(u64),
+    Other(String),
+}
+
+impl BluetoothError {
+    pub fn from_bluer(err: &bluer::Error) -> Self {
+        match err.kind {
+            ErrorKind::InProgress => BluetoothError::InProgress,
+            ErrorKind::AlreadyConnected => BluetoothError::AlreadyConnected,
+            ErrorKind::AuthenticationFailed | ErrorKind::AuthenticationTimeout => {
+                BluetoothError::AuthenticationFailed
+            }
+            ErrorKind::AuthenticationRejected | ErrorKind::AuthenticationCanceled => {
+                BluetoothError::AuthenticationRejected
+            }
+            ErrorKind::NotReady => BluetoothError::NotReady,
+            ErrorKind::ConnectionAttemptFailed => BluetoothError::ConnectionFailed,
+            _ => Self::from_message(&err.message)
+                .unwrap_or_else(|| BluetoothError::Other(err.to_string())),
+        }
+    }
+
+    /// Classifies any error returned by the `bz` layer, falling back to the
+    /// BlueZ error strings when the error did not come from bluer directly.
+    pub fn classify(err: &anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<BluetoothError>() {
+            return err.clone();
+        }
+
+        if let Some(err) = err.downcast_ref::<bluer::Error>() {
+            return Self::from_bluer(err);
+        }
+
+        let text = err.to_string();
+        Self::from_message(&text).unwrap_or(BluetoothError::Other(text))
+    }
+
+    fn from_message(message: &str) -> Option<Self> {
+        let message = message.to_lowercase();
+
+        let error = if message.contains("page timeout")
+            || message.contains("page-timeout")
+            || message.contains("host is down")
+        {
+            BluetoothError::PageTimeout
+        } else if message.contains("le-connection-abort-by-local")
+            || message.contains("connection attempt failed")
+        {
+            BluetoothError::ConnectionFailed
+        } else if message.contains("profile-unavailable")
+            || message.contains("protocol not available")
+        {
+            BluetoothError::ProfileUnavailable
+        } else if message.contains("already-connected") || message.contains("already connected") {
+            BluetoothError::AlreadyConnected
+        } else if message.contains("in progress") || message.contains("connection-busy") {
+            BluetoothError::InProgress
+        } else if message.contains("authentication failed") || message.contains("key missing") {
+            BluetoothError::AuthenticationFailed
+        } else if message.contains("authentication rejected")
+            || message.contains("authentication canceled")
+        {
+            BluetoothError::AuthenticationRejected
+        } else if message.contains("not ready") || message.contains("resource not ready") {
+            BluetoothError::NotReady
+        } else {
+            return None;
+        };
+
+        Some(error)
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            BluetoothError::InProgress
+                | BluetoothError::NotReady
+                | BluetoothError::PageTimeout
+                | BluetoothError::ConnectionFailed
+        )
+    }
+
+    pub fn is_authentication_error(&self) -> bool {
+        matches!(
+            self,
+            BluetoothError::AuthenticationFailed | BluetoothError::AuthenticationRejected
+        )
+    }
+
+    pub fn message(&self) -> Cow<'static, str> {
+        match self {
+            BluetoothError::InProgress => t!("notifications.bt.errors.in_progress.message"),
+            BluetoothError::AlreadyConnected => {
+                t!("notifications.bt.errors.already_connected.message")
+            }
+            BluetoothError::AuthenticationFailed => {
+                t!("notifications.bt.errors.authentication_failed.message")
+            }
+            BluetoothError::AuthenticationRejected => {
+                t!("notifications.bt.errors.authentication_rejected.message")
+            }
+            BluetoothError::ProfileUnavailable => {
+                t!("notifications.bt.errors.profile_unavailable.message")
+            }
+            BluetoothError::NotReady => t!("notifications.bt.errors.not_ready.message"),
+            BluetoothError::PageTimeout => t!("notifications.bt.errors.page_timeout.message"),
+            BluetoothError::ConnectionFailed => {
+                t!("notifications.bt.errors.connection_failed.message")
+            }
+            BluetoothError::TimedOut(seconds) => {
+                t!(
+                    "notifications.bt.errors.timed_out.message",
+                    seconds = seconds
+                )
+            }
+            BluetoothError::Other(message) => Cow::Owned(message.clone()),
+        }
+    }
+
+    pub fn recovery(&self) -> Option<Cow<'static, str>> {
+        match self {
+            BluetoothError::InProgress => Some(t!("notifications.bt.errors.in_progress.recovery")),
+            BluetoothError::AlreadyConnected => {
+                Some(t!("notifications.bt.errors.already_connected.recovery"))
+            }
+            BluetoothError::AuthenticationFailed => {
+                Some(t!("notifications.bt.errors.authentication_failed.recovery"))
+            }
+            BluetoothError::AuthenticationRejected => Some(t!(
+                "notifications.bt.errors.authentication_rejected.recovery"
+            )),
+            BluetoothError::ProfileUnavailable => {
+                Some(t!("notifications.bt.errors.profile_unavailable.recovery"))
+            }
+            BluetoothError::NotReady => Some(t!("notifications.bt.errors.not_ready.recovery")),
+            BluetoothError::PageTimeout | BluetoothError::TimedOut(_) => {
+                Some(t!("notifications.bt.errors.page_timeout.recovery"))
+            }
+            BluetoothError::ConnectionFailed => {
+                Some(t!("notifications.bt.errors.connection_failed.recovery"))
+            }
+            BluetoothError::Other(_) => None,
+        }
+    }
+
+    /// Message followed by the suggested recovery action, for notifications.
+    pub fn describe(&self) -> String {
+        match self.recovery() {
+            Some(recovery) => format!("{} {recovery}", self.message()),
+            None => self.message().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for BluetoothError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for BluetoothError {}
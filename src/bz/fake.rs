@@ -0,0 +1,746 @@
+//! In-memory backend for exercising the `bz` layer and `App` without BlueZ.
+//!
+//! Adapters and devices are scripted up front; device operations consume
+//! queued [`FakeOutcome`]s and fall back to succeeding once the queue is
+//! empty.
+
+use anyhow::Result;
+use bluer::{gatt::CharacteristicFlags, AdapterEvent, Address, ErrorKind, Uuid};
+use futures_util::{
+    future::{pending, ready},
+    stream::{self, Stream},
+    FutureExt, StreamExt,
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use crate::bz::{
+    backend::{AdapterBackend, BackendFuture, DeviceBackend, DiscoveryStream, ValueStream},
+    gatt::{GattCharacteristic, GattService},
+};
+
+/// Scripted result of a single device operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FakeOutcome {
+    Succeed,
+    Fail(ErrorKind),
+    /// Never completes, like a device that stopped answering.
+    Hang,
+}
+
+#[derive(Debug, Clone)]
+pub struct FakeAdapter {
+    name: String,
+    state: Arc<Mutex<AdapterState>>,
+}
+
+#[derive(Debug)]
+struct AdapterState {
+    address: Address,
+    alias: String,
+    is_powered: bool,
+    is_pairable: bool,
+    is_discoverable: bool,
+    is_discovering: bool,
+    devices: BTreeMap<Address, Arc<FakeDevice>>,
+    undiscovered: Vec<Arc<FakeDevice>>,
+    discovery_events: VecDeque<AdapterEvent>,
+    discovery_waker: Option<Waker>,
+}
+
+impl AdapterState {
+    /// Reports a change to the running discovery, if any.
+    fn notify_discovery(&mut self, event: AdapterEvent) {
+        if self.is_discovering {
+            self.discovery_events.push_back(event);
+            if let Some(waker) = self.discovery_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl FakeAdapter {
+    pub fn new(name: &str, address: Address) -> Self {
+        Self {
+            name: name.to_string(),
+            state: Arc::new(Mutex::new(AdapterState {
+                address,
+                alias: name.to_string(),
+                is_powered: true,
+                is_pairable: true,
+                is_discoverable: false,
+                is_discovering: false,
+                devices: BTreeMap::new(),
+                undiscovered: Vec::new(),
+                discovery_events: VecDeque::new(),
+                discovery_waker: None,
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, AdapterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn powered(self, powered: bool) -> Self {
+        self.state().is_powered = powered;
+        self
+    }
+
+    /// Adds a device BlueZ already knows about.
+    pub fn with_device(self, device: FakeDevice) -> Self {
+        self.add_device(device);
+        self
+    }
+
+    /// Adds a device that only shows up once discovery is started.
+    pub fn with_discoverable_device(self, device: FakeDevice) -> Self {
+        self.state().undiscovered.push(Arc::new(device));
+        self
+    }
+
+    /// Adds a device, reported to a running discovery as it comes in range.
+    pub fn add_device(&self, device: FakeDevice) -> Arc<FakeDevice> {
+        let device = Arc::new(device);
+        let mut state = self.state();
+        state.devices.insert(device.addr, device.clone());
+        state.notify_discovery(AdapterEvent::DeviceAdded(device.addr));
+        device
+    }
+
+    /// Returns the scripted device, including ones not discovered yet.
+    pub fn fake_device(&self, addr: Address) -> Option<Arc<FakeDevice>> {
+        let state = self.state();
+        state
+            .devices
+            .get(&addr)
+            .or_else(|| state.undiscovered.iter().find(|d| d.addr == addr))
+            .cloned()
+    }
+
+    pub fn is_discovering_now(&self) -> bool {
+        self.state().is_discovering
+    }
+}
+
+impl AdapterBackend for FakeAdapter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn address(&self) -> BackendFuture<'_, Address> {
+        ok(self.state().address)
+    }
+
+    fn alias(&self) -> BackendFuture<'_, String> {
+        ok(self.state().alias.clone())
+    }
+
+    fn is_powered(&self) -> BackendFuture<'_, bool> {
+        ok(self.state().is_powered)
+    }
+
+    fn is_pairable(&self) -> BackendFuture<'_, bool> {
+        ok(self.state().is_pairable)
+    }
+
+    fn is_discoverable(&self) -> BackendFuture<'_, bool> {
+        ok(self.state().is_discoverable)
+    }
+
+    fn is_discovering(&self) -> BackendFuture<'_, bool> {
+        ok(self.state().is_discovering)
+    }
+
+    fn set_powered(&self, powered: bool) -> BackendFuture<'_, ()> {
+        self.state().is_powered = powered;
+        ok(())
+    }
+
+    fn set_pairable(&self, pairable: bool) -> BackendFuture<'_, ()> {
+        self.state().is_pairable = pairable;
+        ok(())
+    }
+
+    fn set_discoverable(&self, discoverable: bool) -> BackendFuture<'_, ()> {
+        self.state().is_discoverable = discoverable;
+        ok(())
+    }
+
+    fn device_addresses(&self) -> BackendFuture<'_, Vec<Address>> {
+        ok(self.state().devices.keys().copied().collect())
+    }
+
+    fn device(&self, addr: Address) -> Result<Arc<dyn DeviceBackend>> {
+        match self.state().devices.get(&addr) {
+            Some(device) => Ok(device.clone() as Arc<dyn DeviceBackend>),
+            None => Err(bluer_error(ErrorKind::NotFound).into()),
+        }
+    }
+
+    fn remove_device(&self, addr: Address) -> BackendFuture<'_, ()> {
+        let mut state = self.state();
+        match state.devices.remove(&addr) {
+            Some(device) => {
+                device.reset_bond();
+                // A forgotten device can be found again by scanning.
+                state.undiscovered.push(device);
+                state.notify_discovery(AdapterEvent::DeviceRemoved(addr));
+                ok(())
+            }
+            None => fail(ErrorKind::DoesNotExist),
+        }
+    }
+
+    fn discover_devices(&self) -> BackendFuture<'_, DiscoveryStream> {
+        let mut state = self.state();
+        if !state.is_powered {
+            return fail(ErrorKind::NotReady);
+        }
+
+        state.is_discovering = true;
+        state.discovery_events.clear();
+
+        for device in std::mem::take(&mut state.undiscovered) {
+            state.devices.insert(device.addr, device.clone());
+            state.notify_discovery(AdapterEvent::DeviceAdded(device.addr));
+        }
+
+        let stream: DiscoveryStream = Box::pin(FakeDiscovery {
+            state: self.state.clone(),
+        });
+        ok(stream)
+    }
+}
+
+/// Keeps the fake adapter discovering until dropped.
+struct FakeDiscovery {
+    state: Arc<Mutex<AdapterState>>,
+}
+
+impl Stream for FakeDiscovery {
+    type Item = AdapterEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.discovery_events.pop_front() {
+            Some(event) => Poll::Ready(Some(event)),
+            None => {
+                state.discovery_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for FakeDiscovery {
+    /// Like BlueZ, forgets signal strengths once discovery stops.
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.is_discovering = false;
+        state.discovery_events.clear();
+        state.discovery_waker = None;
+        for device in state.devices.values() {
+            device.state().rssi = None;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FakeDevice {
+    addr: Address,
+    state: Mutex<DeviceState>,
+}
+
+/// Open notification stream, by service and characteristic handle.
+type Subscriber = ((u16, u16), UnboundedSender<Vec<u8>>);
+
+#[derive(Debug, Default)]
+struct DeviceState {
+    alias: String,
+    name: Option<String>,
+    icon: Option<String>,
+    class: Option<u32>,
+    appearance: Option<u16>,
+    uuids: Option<HashSet<Uuid>>,
+    is_paired: bool,
+    is_trusted: bool,
+    is_connected: bool,
+    battery_percentage: Option<u8>,
+    rssi: Option<i16>,
+    tx_power: Option<i16>,
+    manufacturer_data: Option<HashMap<u16, Vec<u8>>>,
+    service_data: Option<HashMap<Uuid, Vec<u8>>>,
+    advertising_flags: Option<Vec<u8>>,
+    gatt_services: Vec<GattService>,
+    /// Characteristics along with their current value.
+    gatt_characteristics: Vec<(GattCharacteristic, Vec<u8>)>,
+    subscribers: Vec<Subscriber>,
+    subscriptions: u32,
+    pair_outcomes: VecDeque<FakeOutcome>,
+    connect_outcomes: VecDeque<FakeOutcome>,
+    disconnect_outcomes: VecDeque<FakeOutcome>,
+    pair_attempts: u32,
+    connect_attempts: u32,
+    disconnects: u32,
+}
+
+impl FakeDevice {
+    pub fn new(addr: Address, alias: &str) -> Self {
+        Self {
+            addr,
+            state: Mutex::new(DeviceState {
+                alias: alias.to_string(),
+                name: Some(alias.to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, DeviceState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn paired(self, paired: bool) -> Self {
+        self.state().is_paired = paired;
+        self
+    }
+
+    pub fn trusted(self, trusted: bool) -> Self {
+        self.state().is_trusted = trusted;
+        self
+    }
+
+    pub fn connected(self, connected: bool) -> Self {
+        self.state().is_connected = connected;
+        self
+    }
+
+    pub fn icon(self, icon: &str) -> Self {
+        self.state().icon = Some(icon.to_string());
+        self
+    }
+
+    pub fn class(self, class: u32) -> Self {
+        self.state().class = Some(class);
+        self
+    }
+
+    pub fn appearance(self, appearance: u16) -> Self {
+        self.state().appearance = Some(appearance);
+        self
+    }
+
+    pub fn uuids(self, uuids: impl IntoIterator<Item = Uuid>) -> Self {
+        self.state().uuids = Some(uuids.into_iter().collect());
+        self
+    }
+
+    pub fn battery(self, percentage: u8) -> Self {
+        self.state().battery_percentage = Some(percentage);
+        self
+    }
+
+    pub fn rssi(self, rssi: i16) -> Self {
+        self.state().rssi = Some(rssi);
+        self
+    }
+
+    pub fn tx_power(self, tx_power: i16) -> Self {
+        self.state().tx_power = Some(tx_power);
+        self
+    }
+
+    pub fn manufacturer_data(self, company: u16, data: &[u8]) -> Self {
+        self.state()
+            .manufacturer_data
+            .get_or_insert_with(HashMap::new)
+            .insert(company, data.to_vec());
+        self
+    }
+
+    pub fn service_data(self, uuid: Uuid, data: &[u8]) -> Self {
+        self.state()
+            .service_data
+            .get_or_insert_with(HashMap::new)
+            .insert(uuid, data.to_vec());
+        self
+    }
+
+    pub fn advertising_flags(self, flags: u8) -> Self {
+        self.state().advertising_flags = Some(vec![flags]);
+        self
+    }
+
+    /// Adds a primary GATT service, resolved once the device is connected.
+    pub fn gatt_service(self, id: u16, uuid: Uuid) -> Self {
+        self.state().gatt_services.push(GattService {
+            id,
+            uuid,
+            primary: true,
+        });
+        self
+    }
+
+    pub fn gatt_characteristic(
+        self,
+        service_id: u16,
+        id: u16,
+        uuid: Uuid,
+        flags: CharacteristicFlags,
+        value: &[u8],
+    ) -> Self {
+        let characteristic = GattCharacteristic {
+            service_id,
+            id,
+            uuid,
+            flags,
+        };
+        self.state()
+            .gatt_characteristics
+            .push((characteristic, value.to_vec()));
+        self
+    }
+
+    /// Drops the remote name, leaving the alias BlueZ derives from the
+    /// address, as for a beacon that never sent one.
+    pub fn unnamed(self) -> Self {
+        let mut state = self.state();
+        state.name = None;
+        state.alias = self.addr.to_string().replace(':', "-");
+        drop(state);
+        self
+    }
+
+    /// Queues outcomes for the next `pair` calls.
+    pub fn pair_outcomes(self, outcomes: impl IntoIterator<Item = FakeOutcome>) -> Self {
+        self.state().pair_outcomes.extend(outcomes);
+        self
+    }
+
+    /// Queues outcomes for the next `connect` calls.
+    pub fn connect_outcomes(self, outcomes: impl IntoIterator<Item = FakeOutcome>) -> Self {
+        self.state().connect_outcomes.extend(outcomes);
+        self
+    }
+
+    /// Queues outcomes for the next `disconnect` calls.
+    pub fn disconnect_outcomes(self, outcomes: impl IntoIterator<Item = FakeOutcome>) -> Self {
+        self.state().disconnect_outcomes.extend(outcomes);
+        self
+    }
+
+    /// Replaces the manufacturer data of `company`, as a new advertisement
+    /// of a device already known would.
+    pub fn advertise_manufacturer_data(&self, company: u16, data: &[u8]) {
+        self.state()
+            .manufacturer_data
+            .get_or_insert_with(HashMap::new)
+            .insert(company, data.to_vec());
+    }
+
+    pub fn queue_connect_outcome(&self, outcome: FakeOutcome) {
+        self.state().connect_outcomes.push_back(outcome);
+    }
+
+    pub fn pair_attempts(&self) -> u32 {
+        self.state().pair_attempts
+    }
+
+    pub fn connect_attempts(&self) -> u32 {
+        self.state().connect_attempts
+    }
+
+    pub fn disconnects(&self) -> u32 {
+        self.state().disconnects
+    }
+
+    pub fn is_paired_now(&self) -> bool {
+        self.state().is_paired
+    }
+
+    pub fn is_trusted_now(&self) -> bool {
+        self.state().is_trusted
+    }
+
+    pub fn is_connected_now(&self) -> bool {
+        self.state().is_connected
+    }
+
+    /// Current value of a characteristic, as last written.
+    pub fn characteristic_value(&self, service_id: u16, id: u16) -> Option<Vec<u8>> {
+        self.state()
+            .gatt_characteristics
+            .iter()
+            .find(|(c, _)| c.service_id == service_id && c.id == id)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Sends `value` to the subscribers of a characteristic, as the device
+    /// would when it changes.
+    pub fn notify_value(&self, service_id: u16, id: u16, value: &[u8]) {
+        let mut state = self.state();
+        if let Ok((_, current)) = state.characteristic(service_id, id) {
+            *current = value.to_vec();
+        }
+        state.subscribers.retain(|(handle, subscriber)| {
+            *handle != (service_id, id) || subscriber.send(value.to_vec()).is_ok()
+        });
+    }
+
+    /// Whether a notification stream of the characteristic is still alive.
+    pub fn is_subscribed(&self, service_id: u16, id: u16) -> bool {
+        self.state()
+            .subscribers
+            .iter()
+            .any(|(handle, subscriber)| *handle == (service_id, id) && !subscriber.is_closed())
+    }
+
+    pub fn subscriptions(&self) -> u32 {
+        self.state().subscriptions
+    }
+
+    fn reset_bond(&self) {
+        let mut state = self.state();
+        state.is_paired = false;
+        state.is_trusted = false;
+        state.is_connected = false;
+    }
+}
+
+impl DeviceBackend for FakeDevice {
+    fn address(&self) -> Address {
+        self.addr
+    }
+
+    fn alias(&self) -> BackendFuture<'_, String> {
+        ok(self.state().alias.clone())
+    }
+
+    fn name(&self) -> BackendFuture<'_, Option<String>> {
+        ok(self.state().name.clone())
+    }
+
+    fn icon(&self) -> BackendFuture<'_, Option<String>> {
+        ok(self.state().icon.clone())
+    }
+
+    fn class(&self) -> BackendFuture<'_, Option<u32>> {
+        ok(self.state().class)
+    }
+
+    fn appearance(&self) -> BackendFuture<'_, Option<u16>> {
+        ok(self.state().appearance)
+    }
+
+    fn uuids(&self) -> BackendFuture<'_, Option<HashSet<Uuid>>> {
+        ok(self.state().uuids.clone())
+    }
+
+    fn is_paired(&self) -> BackendFuture<'_, bool> {
+        ok(self.state().is_paired)
+    }
+
+    fn is_trusted(&self) -> BackendFuture<'_, bool> {
+        ok(self.state().is_trusted)
+    }
+
+    fn is_connected(&self) -> BackendFuture<'_, bool> {
+        ok(self.state().is_connected)
+    }
+
+    fn battery_percentage(&self) -> BackendFuture<'_, Option<u8>> {
+        ok(self.state().battery_percentage)
+    }
+
+    fn rssi(&self) -> BackendFuture<'_, Option<i16>> {
+        ok(self.state().rssi)
+    }
+
+    fn tx_power(&self) -> BackendFuture<'_, Option<i16>> {
+        ok(self.state().tx_power)
+    }
+
+    fn manufacturer_data(&self) -> BackendFuture<'_, Option<HashMap<u16, Vec<u8>>>> {
+        ok(self.state().manufacturer_data.clone())
+    }
+
+    fn service_data(&self) -> BackendFuture<'_, Option<HashMap<Uuid, Vec<u8>>>> {
+        ok(self.state().service_data.clone())
+    }
+
+    fn advertising_flags(&self) -> BackendFuture<'_, Option<Vec<u8>>> {
+        ok(self.state().advertising_flags.clone())
+    }
+
+    fn connect(&self) -> BackendFuture<'_, ()> {
+        let mut state = self.state();
+        state.connect_attempts += 1;
+
+        let outcome = state
+            .connect_outcomes
+            .pop_front()
+            .unwrap_or(FakeOutcome::Succeed);
+        if outcome == FakeOutcome::Succeed {
+            state.is_connected = true;
+        }
+        resolve(outcome)
+    }
+
+    fn disconnect(&self) -> BackendFuture<'_, ()> {
+        let mut state = self.state();
+        state.disconnects += 1;
+
+        let outcome = state
+            .disconnect_outcomes
+            .pop_front()
+            .unwrap_or(FakeOutcome::Succeed);
+        if outcome == FakeOutcome::Succeed {
+            state.is_connected = false;
+        }
+        resolve(outcome)
+    }
+
+    fn pair(&self) -> BackendFuture<'_, ()> {
+        let mut state = self.state();
+        state.pair_attempts += 1;
+
+        if state.is_paired {
+            return fail(ErrorKind::AlreadyExists);
+        }
+
+        let outcome = state
+            .pair_outcomes
+            .pop_front()
+            .unwrap_or(FakeOutcome::Succeed);
+        if outcome == FakeOutcome::Succeed {
+            state.is_paired = true;
+        }
+        resolve(outcome)
+    }
+
+    fn set_trusted(&self, trusted: bool) -> BackendFuture<'_, ()> {
+        self.state().is_trusted = trusted;
+        ok(())
+    }
+
+    fn has_gatt_services(&self) -> BackendFuture<'_, bool> {
+        let state = self.state();
+        ok(state.is_connected && !state.gatt_services.is_empty())
+    }
+
+    fn gatt_services(&self) -> BackendFuture<'_, Vec<GattService>> {
+        let state = self.state();
+        if !state.is_connected {
+            return fail(ErrorKind::ServicesUnresolved);
+        }
+        ok(state.gatt_services.clone())
+    }
+
+    fn gatt_characteristics(&self, service_id: u16) -> BackendFuture<'_, Vec<GattCharacteristic>> {
+        let state = self.state();
+        if !state.is_connected {
+            return fail(ErrorKind::ServicesUnresolved);
+        }
+        if !state.gatt_services.iter().any(|s| s.id == service_id) {
+            return fail(ErrorKind::NotFound);
+        }
+        ok(state
+            .gatt_characteristics
+            .iter()
+            .filter(|(c, _)| c.service_id == service_id)
+            .map(|(c, _)| c.clone())
+            .collect())
+    }
+
+    fn read_characteristic(&self, service_id: u16, id: u16) -> BackendFuture<'_, Vec<u8>> {
+        let mut state = self.state();
+        match state.characteristic(service_id, id) {
+            Ok((characteristic, value)) if characteristic.is_readable() => ok(value.clone()),
+            Ok(_) => fail(ErrorKind::NotPermitted),
+            Err(kind) => fail(kind),
+        }
+    }
+
+    fn write_characteristic(
+        &self,
+        service_id: u16,
+        id: u16,
+        value: Vec<u8>,
+    ) -> BackendFuture<'_, ()> {
+        let mut state = self.state();
+        match state.characteristic(service_id, id) {
+            Ok((characteristic, current)) if characteristic.is_writable() => {
+                *current = value;
+                ok(())
+            }
+            Ok(_) => fail(ErrorKind::NotPermitted),
+            Err(kind) => fail(kind),
+        }
+    }
+
+    fn subscribe_characteristic(&self, service_id: u16, id: u16) -> BackendFuture<'_, ValueStream> {
+        let mut state = self.state();
+        match state.characteristic(service_id, id) {
+            Ok((characteristic, _)) if !characteristic.is_subscribable() => {
+                return fail(ErrorKind::NotSupported)
+            }
+            Ok(_) => {}
+            Err(kind) => return fail(kind),
+        }
+
+        let (sender, receiver) = unbounded_channel();
+        state.subscribers.push(((service_id, id), sender));
+        state.subscriptions += 1;
+
+        let values = stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|value| (value, receiver))
+        });
+        ok(values.boxed())
+    }
+}
+
+impl DeviceState {
+    fn characteristic(
+        &mut self,
+        service_id: u16,
+        id: u16,
+    ) -> std::result::Result<&mut (GattCharacteristic, Vec<u8>), ErrorKind> {
+        if !self.is_connected {
+            return Err(ErrorKind::ServicesUnresolved);
+        }
+        self.gatt_characteristics
+            .iter_mut()
+            .find(|(c, _)| c.service_id == service_id && c.id == id)
+            .ok_or(ErrorKind::NotFound)
+    }
+}
+
+fn bluer_error(kind: ErrorKind) -> bluer::Error {
+    bluer::Error {
+        message: format!("{kind:?}"),
+        kind,
+    }
+}
+
+fn ok<'a, T: Send + 'a>(value: T) -> BackendFuture<'a, T> {
+    ready(Ok(value)).boxed()
+}
+
+fn fail<'a, T: Send + 'a>(kind: ErrorKind) -> BackendFuture<'a, T> {
+    ready(Err(bluer_error(kind).into())).boxed()
+}
+
+fn resolve<'a>(outcome: FakeOutcome) -> BackendFuture<'a, ()> {
+    match outcome {
+        FakeOutcome::Succeed => ok(()),
+        FakeOutcome::Fail(kind) => fail(kind),
+        FakeOutcome::Hang => pending().boxed(),
+    }
+}
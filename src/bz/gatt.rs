@@ -0,0 +1,234 @@
+//! GATT services and characteristics of connected LE devices, named after
+//! the Bluetooth SIG assigned numbers.
+
+use anyhow::{anyhow, Result};
+use bluer::{gatt::CharacteristicFlags, Uuid};
+use std::convert::TryFrom;
+
+use crate::bz::advertisement::{format_hex, format_uuid, BASE_UUID};
+
+/// Assigned numbers of GATT services, sorted. Only the common ones are
+/// listed; others are shown by UUID.
+const SERVICES: &[(u16, &str)] = &[
+    (0x1800, "Generic Access"),
+    (0x1801, "Generic Attribute"),
+    (0x1802, "Immediate Alert"),
+    (0x1803, "Link Loss"),
+    (0x1804, "Tx Power"),
+    (0x1805, "Current Time"),
+    (0x180A, "Device Information"),
+    (0x180D, "Heart Rate"),
+    (0x180F, "Battery"),
+    (0x1810, "Blood Pressure"),
+    (0x1812, "Human Interface Device"),
+    (0x1814, "Running Speed and Cadence"),
+    (0x1816, "Cycling Speed and Cadence"),
+    (0x1818, "Cycling Power"),
+    (0x1819, "Location and Navigation"),
+    (0x181A, "Environmental Sensing"),
+    (0x181C, "User Data"),
+    (0x181D, "Weight Scale"),
+    (0x1822, "Pulse Oximeter"),
+    (0x1826, "Fitness Machine"),
+    (0xFE59, "Nordic DFU"),
+];
+
+/// Assigned numbers of GATT characteristics, sorted.
+const CHARACTERISTICS: &[(u16, &str)] = &[
+    (0x2A00, "Device Name"),
+    (0x2A01, "Appearance"),
+    (0x2A04, "Peripheral Preferred Connection Parameters"),
+    (0x2A05, "Service Changed"),
+    (0x2A06, "Alert Level"),
+    (0x2A07, "Tx Power Level"),
+    (0x2A19, "Battery Level"),
+    (0x2A23, "System ID"),
+    (0x2A24, "Model Number String"),
+    (0x2A25, "Serial Number String"),
+    (0x2A26, "Firmware Revision String"),
+    (0x2A27, "Hardware Revision String"),
+    (0x2A28, "Software Revision String"),
+    (0x2A29, "Manufacturer Name String"),
+    (0x2A2B, "Current Time"),
+    (0x2A35, "Blood Pressure Measurement"),
+    (0x2A37, "Heart Rate Measurement"),
+    (0x2A38, "Body Sensor Location"),
+    (0x2A39, "Heart Rate Control Point"),
+    (0x2A4A, "HID Information"),
+    (0x2A4B, "Report Map"),
+    (0x2A4D, "Report"),
+    (0x2A4E, "Protocol Mode"),
+    (0x2A50, "PnP ID"),
+    (0x2A53, "RSC Measurement"),
+    (0x2A5B, "CSC Measurement"),
+    (0x2A63, "Cycling Power Measurement"),
+    (0x2A6D, "Pressure"),
+    (0x2A6E, "Temperature"),
+    (0x2A6F, "Humidity"),
+    (0x2A9D, "Weight Measurement"),
+    (0x2AA6, "Central Address Resolution"),
+    (0x2AC9, "Resolvable Private Address Only"),
+    (0x2B29, "Client Supported Features"),
+    (0x2B2A, "Database Hash"),
+    (0x2B3A, "Server Supported Features"),
+];
+
+/// Service exposed by a connected device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GattService {
+    pub id: u16,
+    pub uuid: Uuid,
+    pub primary: bool,
+}
+
+impl GattService {
+    pub fn name(&self) -> String {
+        describe(&self.uuid, SERVICES)
+    }
+}
+
+/// Characteristic of a [`GattService`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GattCharacteristic {
+    pub service_id: u16,
+    pub id: u16,
+    pub uuid: Uuid,
+    pub flags: CharacteristicFlags,
+}
+
+impl GattCharacteristic {
+    pub fn name(&self) -> String {
+        describe(&self.uuid, CHARACTERISTICS)
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.flags.read
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags.write || self.flags.write_without_response
+    }
+
+    /// Whether the device can send changes of the value, by notification or
+    /// indication.
+    pub fn is_subscribable(&self) -> bool {
+        self.flags.notify || self.flags.indicate
+    }
+
+    /// Names of the operations the characteristic supports.
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        let flags = &self.flags;
+        [
+            (flags.read, "read"),
+            (flags.write, "write"),
+            (flags.write_without_response, "write-without-response"),
+            (flags.notify, "notify"),
+            (flags.indicate, "indicate"),
+            (flags.broadcast, "broadcast"),
+            (flags.authenticated_signed_writes, "signed-write"),
+            (flags.reliable_write, "reliable-write"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|&(_, name)| name)
+        .collect()
+    }
+}
+
+/// The 16-bit assigned number of `uuid`, if it derives from the Base UUID.
+pub fn assigned_number(uuid: &Uuid) -> Option<u16> {
+    let value = uuid.as_u128();
+    if value & ((1 << 96) - 1) != BASE_UUID {
+        return None;
+    }
+    u16::try_from(value >> 96).ok()
+}
+
+/// Assigned name followed by the short UUID, such as `Battery (0x180F)`, or
+/// the UUID alone for vendor-specific ones.
+fn describe(uuid: &Uuid, names: &[(u16, &str)]) -> String {
+    let name = assigned_number(uuid).and_then(|number| {
+        names
+            .binary_search_by_key(&number, |&(n, _)| n)
+            .ok()
+            .map(|index| names[index].1)
+    });
+
+    match name {
+        Some(name) => format!("{name} ({})", format_uuid(uuid)),
+        None => format_uuid(uuid),
+    }
+}
+
+/// A characteristic value, shown in the ways it is likely meant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedValue {
+    pub hex: String,
+    /// Set when the value is printable UTF-8.
+    pub text: Option<String>,
+    /// Little-endian unsigned integer, for values of 1, 2, 4 or 8 bytes.
+    pub integer: Option<u64>,
+}
+
+impl DecodedValue {
+    /// One-line form, such as `64 (100)` or `48 69 "Hi"`.
+    pub fn summary(&self) -> String {
+        let mut summary = self.hex.clone();
+        if let Some(text) = &self.text {
+            summary.push_str(&format!(" \"{text}\""));
+        }
+        if let Some(integer) = self.integer {
+            summary.push_str(&format!(" ({integer})"));
+        }
+        summary
+    }
+}
+
+pub fn decode_value(value: &[u8]) -> DecodedValue {
+    let text = std::str::from_utf8(value)
+        .ok()
+        .map(|text| text.trim_end_matches('\0'))
+        .filter(|text| !text.is_empty() && !text.chars().any(char::is_control))
+        .map(str::to_string);
+
+    let integer = match value.len() {
+        1 | 2 | 4 | 8 => {
+            let mut bytes = [0; 8];
+            bytes[..value.len()].copy_from_slice(value);
+            Some(u64::from_le_bytes(bytes))
+        }
+        _ => None,
+    };
+
+    DecodedValue {
+        hex: format_hex(value, " "),
+        text,
+        integer,
+    }
+}
+
+/// Parses a value entered by the user: bytes in hex when prefixed with `0x`,
+/// such as `0x01 ff`, and UTF-8 text otherwise.
+pub fn parse_value(input: &str) -> Result<Vec<u8>> {
+    let input = input.trim();
+    let Some(hex) = input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+    else {
+        return Ok(input.as_bytes().to_vec());
+    };
+
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        return Err(anyhow!("'{input}' is not a valid hex value"));
+    }
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(anyhow!("'{input}' is not a whole number of hex bytes"));
+    }
+
+    let nibble = |digit: u8| (digit as char).to_digit(16).unwrap_or_default() as u8;
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (nibble(pair[0]) << 4) | nibble(pair[1]))
+        .collect())
+}
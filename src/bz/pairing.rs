@@ -1,23 +1,61 @@
 use anyhow::Result;
-use bluer::Adapter;
-use log::{debug, info};
-use std::sync::Arc;
+use log::{debug, info, warn};
+use std::{sync::Arc, time::Duration};
+use tokio::time::{sleep, timeout};
 
-use crate::bz::device::Device;
+use crate::bz::{backend::AdapterBackend, device::Device, error::BluetoothError};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionPolicy {
+    pub retries: u32,
+    pub retry_delay: Duration,
+    pub timeout: Duration,
+}
+
+impl ConnectionPolicy {
+    pub fn max_attempts(&self) -> u32 {
+        self.retries.saturating_add(1)
+    }
+
+    /// Delay before the given attempt, doubling after each failed one. Delays
+    /// too long to represent saturate, as the overall timeout cuts them short.
+    pub fn delay_before(&self, attempt: u32) -> Duration {
+        2u32.checked_pow(attempt.saturating_sub(2))
+            .and_then(|factor| self.retry_delay.checked_mul(factor))
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+impl Default for ConnectionPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            retry_delay: Duration::from_secs(2),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
 
 pub struct PairingManager {
-    adapter: Arc<Adapter>,
+    adapter: Arc<dyn AdapterBackend>,
+    policy: ConnectionPolicy,
 }
 
 impl PairingManager {
-    pub fn adapter(&self) -> &Arc<Adapter> {
+    pub fn adapter(&self) -> &Arc<dyn AdapterBackend> {
         &self.adapter
     }
 
-    pub fn new(adapter: Arc<Adapter>) -> Self {
-        Self { adapter }
+    pub fn new(adapter: Arc<dyn AdapterBackend>, policy: ConnectionPolicy) -> Self {
+        Self { adapter, policy }
+    }
+
+    pub fn policy(&self) -> &ConnectionPolicy {
+        &self.policy
     }
 
+    /// Dropping the returned future before it completes makes bluer issue
+    /// `CancelPairing` to BlueZ.
     pub async fn pair_device(&self, device: &Device) -> Result<()> {
         debug!("Initiating pairing with {}: {}", device.addr, device.alias);
         device.pair().await?;
@@ -25,9 +63,57 @@ impl PairingManager {
         Ok(())
     }
 
-    pub async fn connect_device(&self, device: &Device) -> Result<()> {
-        debug!("Connecting to {}: {}", device.addr, device.alias);
-        device.connect().await?;
+    /// Connects according to the retry policy. `on_attempt` is called with
+    /// the attempt number and the maximum attempt count before each try.
+    pub async fn connect_device(
+        &self,
+        device: &Device,
+        on_attempt: impl Fn(u32, u32),
+    ) -> Result<()> {
+        let max_attempts = self.policy.max_attempts();
+
+        let attempts = async {
+            let mut attempt = 1;
+            loop {
+                if attempt > 1 {
+                    sleep(self.policy.delay_before(attempt)).await;
+                }
+
+                on_attempt(attempt, max_attempts);
+                debug!(
+                    "Connecting to {}: {} (attempt {attempt}/{max_attempts})",
+                    device.addr, device.alias
+                );
+
+                let err = match device.connect().await {
+                    Ok(()) => return Ok(()),
+                    Err(err) => BluetoothError::classify(&err),
+                };
+
+                match err {
+                    BluetoothError::AlreadyConnected => return Ok(()),
+                    err if attempt < max_attempts && err.is_retryable() => {
+                        warn!(
+                            "Attempt {attempt}/{max_attempts} to connect to {} failed: {err}",
+                            device.alias
+                        );
+                        attempt += 1;
+                    }
+                    err => return Err(err),
+                }
+            }
+        };
+
+        match timeout(self.policy.timeout, attempts).await {
+            Ok(result) => result?,
+            Err(_) => {
+                if let Err(err) = device.disconnect().await {
+                    debug!("Failed to abort timed out connection: {err}");
+                }
+                return Err(BluetoothError::TimedOut(self.policy.timeout.as_secs()).into());
+            }
+        }
+
         info!(
             "Successfully connected to {}: {}",
             device.addr, device.alias
@@ -35,6 +121,15 @@ impl PairingManager {
         Ok(())
     }
 
+    /// Aborts a pending `connect_device` call. BlueZ treats a disconnect
+    /// request issued before the connect reply as a cancellation.
+    pub async fn cancel_connection(&self, device: &Device) -> Result<()> {
+        debug!("Cancelling connection to {}: {}", device.addr, device.alias);
+        device.disconnect().await?;
+        info!("Connection to {}: {} cancelled", device.addr, device.alias);
+        Ok(())
+    }
+
     pub async fn disconnect_device(&self, device: &Device) -> Result<()> {
         debug!("Disconnecting from {}: {}", device.addr, device.alias);
         device.disconnect().await?;
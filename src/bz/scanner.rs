@@ -1,23 +1,142 @@
 use anyhow::Result;
-use bluer::Adapter;
+use bluer::{AdapterEvent, Address};
+use futures_util::StreamExt;
 use log::{debug, info, warn};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::{spawn, sync::Mutex, task::JoinHandle, time::Duration};
+use tokio::{
+    spawn,
+    sync::{broadcast, watch, Mutex},
+    task::JoinHandle,
+    time::{interval, sleep, Duration},
+};
+
+use crate::bz::{advertisement::Advertisement, backend::AdapterBackend, device::Device};
+
+/// How often signal strengths are sampled while discovering.
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often devices are checked against a query while waiting for one, as
+/// names may only arrive after the device was added.
+const MATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Signal of a device as last reported during discovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signal {
+    pub rssi: i16,
+    pub tx_power: Option<i16>,
+}
+
+type Signals = Arc<std::sync::Mutex<HashMap<Address, Signal>>>;
+
+/// Where the latest discovery run stands, as published by
+/// [`Scanner::subscribe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanState {
+    Idle,
+    Scanning,
+    /// Ran for the whole duration.
+    Completed,
+    Cancelled,
+}
+
+/// Change to the adapter's devices seen during a discovery run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanEvent {
+    DeviceAdded(Address),
+    DeviceRemoved(Address),
+}
+
+/// Devices added during the latest discovery run, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct ScanTally {
+    devices: Vec<Device>,
+}
+
+impl ScanTally {
+    fn record(&mut self, device: Device) {
+        self.remove(device.addr);
+        self.devices.push(device);
+    }
+
+    fn remove(&mut self, addr: Address) {
+        self.devices.retain(|device| device.addr != addr);
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub fn audio(&self) -> usize {
+        self.devices
+            .iter()
+            .filter(|device| device.is_audio())
+            .count()
+    }
+
+    /// Up to `count` of the latest devices that have a name, newest first.
+    pub fn newest_named(&self, count: usize) -> Vec<&Device> {
+        self.devices
+            .iter()
+            .rev()
+            .filter(|device| !device.is_unnamed())
+            .take(count)
+            .collect()
+    }
+}
+
+/// Stops a discovery run. Cancelling is synchronous, so it works from any
+/// thread, including notification callbacks running outside the runtime.
+#[derive(Debug, Clone)]
+pub struct CancellationToken(Arc<watch::Sender<bool>>);
+
+impl CancellationToken {
+    fn new() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.0.subscribe();
+        let _ = cancelled.wait_for(|&cancelled| cancelled).await;
+    }
+}
 
 #[derive(Clone)]
 pub struct Scanner {
-    adapter: Arc<Adapter>,
+    adapter: Arc<dyn AdapterBackend>,
     is_scanning: Arc<AtomicBool>,
     scan_task: Arc<Mutex<Option<JoinHandle<()>>>>,
+    token: Arc<std::sync::Mutex<CancellationToken>>,
+    state: Arc<watch::Sender<ScanState>>,
+    events: broadcast::Sender<ScanEvent>,
+    tally: Arc<std::sync::Mutex<ScanTally>>,
+    signals: Signals,
 }
 
 impl Scanner {
-    pub fn new(adapter: Arc<Adapter>, is_scanning: Arc<AtomicBool>) -> Self {
+    pub fn new(adapter: Arc<dyn AdapterBackend>, is_scanning: Arc<AtomicBool>) -> Self {
         Self {
             adapter,
             is_scanning,
             scan_task: Arc::new(Mutex::new(None)),
+            token: Arc::new(std::sync::Mutex::new(CancellationToken::new())),
+            state: Arc::new(watch::channel(ScanState::Idle).0),
+            events: broadcast::channel(64).0,
+            tally: Arc::default(),
+            signals: Signals::default(),
         }
     }
 
@@ -29,18 +148,61 @@ impl Scanner {
 
         info!("Starting Bluetooth discovery for {timeout_sec} seconds...");
 
-        let discovery_stream = self.adapter.discover_devices().await?;
+        let mut discovery_stream = self.adapter.discover_devices().await?;
         self.is_scanning.store(true, Ordering::Relaxed);
 
+        let token = CancellationToken::new();
+        *self.token.lock().unwrap() = token.clone();
+        self.signals.lock().unwrap().clear();
+        *self.tally.lock().unwrap() = ScanTally::default();
+        self.state.send_replace(ScanState::Scanning);
+
         let is_scanning = self.is_scanning.clone();
+        let adapter = self.adapter.clone();
+        let signals = self.signals.clone();
+        let state = self.state.clone();
+        let events = self.events.clone();
+        let tally = self.tally.clone();
 
         let task = spawn(async move {
-            tokio::time::sleep(Duration::from_secs(timeout_sec)).await;
+            let deadline = sleep(Duration::from_secs(timeout_sec));
+            tokio::pin!(deadline);
+            let mut sample = interval(SIGNAL_INTERVAL);
 
-            is_scanning.store(false, Ordering::Relaxed);
-            info!("Discovery completed after {timeout_sec} seconds");
+            let outcome = loop {
+                tokio::select! {
+                    _ = &mut deadline => break ScanState::Completed,
+                    _ = token.cancelled() => break ScanState::Cancelled,
+                    _ = sample.tick() => sample_signals(adapter.as_ref(), &signals).await,
+                    event = discovery_stream.next() => {
+                        let event = match event {
+                            Some(AdapterEvent::DeviceAdded(addr)) => {
+                                if let Ok(device) = Device::new(&adapter, &addr).await {
+                                    tally.lock().unwrap().record(device);
+                                }
+                                ScanEvent::DeviceAdded(addr)
+                            }
+                            Some(AdapterEvent::DeviceRemoved(addr)) => {
+                                tally.lock().unwrap().remove(addr);
+                                ScanEvent::DeviceRemoved(addr)
+                            }
+                            Some(AdapterEvent::PropertyChanged(_)) => continue,
+                            None => break ScanState::Completed,
+                        };
+                        // Nobody listening is fine.
+                        let _ = events.send(event);
+                    }
+                }
+            };
 
             drop(discovery_stream);
+            is_scanning.store(false, Ordering::Relaxed);
+            state.send_replace(outcome);
+
+            match outcome {
+                ScanState::Cancelled => info!("Discovery cancelled"),
+                _ => info!("Discovery completed after {timeout_sec} seconds"),
+            }
         });
 
         let mut scan_task_guard = self.scan_task.lock().await;
@@ -49,6 +211,18 @@ impl Scanner {
         Ok(())
     }
 
+    /// Cancels the running discovery without waiting for it to stop.
+    pub fn cancel(&self) {
+        self.token.lock().unwrap().cancel();
+    }
+
+    /// Token cancelling the current discovery run, or the latest one.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.lock().unwrap().clone()
+    }
+
+    /// Cancels the running discovery and waits for the discovery session
+    /// to be released.
     pub async fn stop_discovery(&self) -> Result<()> {
         if !self.is_scanning.load(Ordering::Relaxed) {
             warn!("No Bluetooth discovery in progress to stop");
@@ -57,33 +231,190 @@ impl Scanner {
 
         info!("Stopping Bluetooth discovery...");
 
-        self.is_scanning.store(false, Ordering::Relaxed);
+        self.cancel();
 
         let mut scan_task_guard = self.scan_task.lock().await;
         if let Some(task) = scan_task_guard.take() {
-            task.abort();
-            debug!("Discovery task aborted");
+            let _ = task.await;
+            debug!("Discovery task stopped");
         }
 
         Ok(())
     }
 
+    /// Follows the state of discovery runs. The current state is seen first.
+    pub fn subscribe(&self) -> watch::Receiver<ScanState> {
+        self.state.subscribe()
+    }
+
+    /// Waits until the advertisement of `addr` differs from `shown`, which
+    /// is checked as often as signals are sampled, or until the running
+    /// discovery ends. Never returns when no discovery is running, as
+    /// advertisements are only received while discovering.
+    pub async fn wait_for_advertisement_change(&self, addr: Address, shown: &Advertisement) {
+        let mut state = self.subscribe();
+        let device = match self.adapter.device(addr) {
+            Ok(device) if *state.borrow_and_update() == ScanState::Scanning => device,
+            _ => return std::future::pending().await,
+        };
+
+        let mut sample = interval(SIGNAL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = state.changed() => return,
+                _ = sample.tick() => {
+                    if Advertisement::read(device.as_ref()).await != *shown {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Devices added or removed while discovering, from now on.
+    pub fn events(&self) -> broadcast::Receiver<ScanEvent> {
+        self.events.subscribe()
+    }
+
+    /// Devices added by the running discovery so far, or by the latest one.
+    pub fn tally(&self) -> ScanTally {
+        self.tally.lock().unwrap().clone()
+    }
+
+    /// Runs discovery until `addr` shows up in the adapter's device list or
+    /// `timeout_sec` elapses. Returns whether the device was found. A
+    /// discovery already running is waited on instead, and left running.
+    pub async fn discover_device(&self, addr: Address, timeout_sec: u64) -> Result<bool> {
+        let mut events = self.events();
+        let started = !self.is_scanning.load(Ordering::Relaxed);
+        self.start_discovery(timeout_sec).await?;
+        let mut state = self.subscribe();
+
+        debug!("Waiting for device {addr} to appear...");
+
+        let mut found = self.adapter.device_addresses().await?.contains(&addr);
+        while !found {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(ScanEvent::DeviceAdded(added)) => found = added == addr,
+                    Ok(ScanEvent::DeviceRemoved(_)) => {}
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        found = self.adapter.device_addresses().await?.contains(&addr);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = state.wait_for(|&state| state != ScanState::Scanning) => break,
+            }
+        }
+
+        if started {
+            self.stop_discovery().await?;
+        }
+
+        if found {
+            info!("Device {addr} found");
+        }
+        Ok(found)
+    }
+
+    /// Runs discovery until a device matching `query` (see
+    /// [`Device::matches`]) comes in range or `timeout_sec` elapses, and
+    /// returns it. Devices BlueZ already knew about only count once they
+    /// report a signal, so a stale entry is not taken for the target. As
+    /// with [`Scanner::discover_device`], a discovery already running is
+    /// left running.
+    pub async fn discover_matching(&self, query: &str, timeout_sec: u64) -> Result<Option<Device>> {
+        let known = self.adapter.device_addresses().await?;
+        let mut events = self.events();
+        let started = !self.is_scanning.load(Ordering::Relaxed);
+        self.start_discovery(timeout_sec).await?;
+        let mut state = self.subscribe();
+        let mut check = interval(MATCH_INTERVAL);
+
+        debug!("Waiting for a device matching '{query}' to appear...");
+
+        let mut found = None;
+        while found.is_none() {
+            tokio::select! {
+                _ = check.tick() => {}
+                event = events.recv() => {
+                    if let Err(broadcast::error::RecvError::Closed) = event {
+                        break;
+                    }
+                }
+                _ = state.wait_for(|&state| state != ScanState::Scanning) => break,
+            }
+
+            for addr in self.adapter.device_addresses().await? {
+                let Ok(device) = Device::new(&self.adapter, &addr).await else {
+                    continue;
+                };
+                if (device.rssi.is_some() || !known.contains(&addr)) && device.matches(query) {
+                    found = Some(device);
+                    break;
+                }
+            }
+        }
+
+        if started {
+            self.stop_discovery().await?;
+        }
+
+        if let Some(device) = &found {
+            info!("Device {} ({}) found", device.alias, device.addr);
+        }
+        Ok(found)
+    }
+
+    /// Fills in the signal of devices that no longer report one, as BlueZ
+    /// drops it once discovery stops, with the last one seen by the latest
+    /// discovery.
+    pub fn apply_signals(&self, devices: &mut [Device]) {
+        let signals = self.signals.lock().unwrap();
+        for device in devices.iter_mut().filter(|device| device.rssi.is_none()) {
+            if let Some(signal) = signals.get(&device.addr) {
+                device.rssi = Some(signal.rssi);
+                device.tx_power = device.tx_power.or(signal.tx_power);
+            }
+        }
+    }
+
     pub async fn is_discovery_completed(&self) -> bool {
         !self.is_scanning.load(Ordering::Relaxed)
     }
 
-    pub async fn wait_for_discovery_completion(&self) -> Result<()> {
-        if !self.is_scanning.load(Ordering::Relaxed) {
-            return Ok(());
-        }
+    /// Waits for the running discovery to complete or be cancelled, and
+    /// returns how it ended.
+    pub async fn wait_for_discovery_completion(&self) -> Result<ScanState> {
+        let mut state = self.subscribe();
 
         debug!("Waiting for discovery to complete...");
 
-        while self.is_scanning.load(Ordering::Relaxed) {
-            tokio::time::sleep(Duration::from_millis(500)).await;
-        }
+        let outcome = *state
+            .wait_for(|&state| state != ScanState::Scanning)
+            .await?;
 
         debug!("Discovery process completed");
-        Ok(())
+        Ok(outcome)
+    }
+}
+
+/// Records the signal of every device currently in range.
+async fn sample_signals(adapter: &dyn AdapterBackend, signals: &Signals) {
+    let Ok(addrs) = adapter.device_addresses().await else {
+        return;
+    };
+
+    for addr in addrs {
+        let Ok(device) = adapter.device(addr) else {
+            continue;
+        };
+        if let Ok(Some(rssi)) = device.rssi().await {
+            let tx_power = device.tx_power().await.ok().flatten();
+            signals
+                .lock()
+                .unwrap()
+                .insert(addr, Signal { rssi, tx_power });
+        }
     }
 }
@@ -0,0 +1,89 @@
+use clap::ValueEnum;
+use futures_util::StreamExt;
+use tokio::{
+    spawn,
+    task::JoinHandle,
+    time::{sleep_until, Duration, Instant},
+};
+
+use crate::bz::backend::ValueStream;
+
+/// Where values of a subscribed characteristic are shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SubscriptionOutput {
+    /// One line per value on stdout.
+    #[default]
+    Stdout,
+    /// A desktop notification updated in place, at most once a second.
+    Notification,
+}
+
+impl SubscriptionOutput {
+    /// Shortest time between two values shown.
+    pub fn interval(self) -> Duration {
+        match self {
+            Self::Stdout => Duration::ZERO,
+            Self::Notification => Duration::from_secs(1),
+        }
+    }
+}
+
+/// Notifications of a characteristic being watched. They stay enabled until
+/// [`Subscription::stop`] is called or the subscription is dropped.
+pub struct Subscription {
+    task: JoinHandle<()>,
+}
+
+impl Subscription {
+    /// Hands values from `stream` to `on_value`, no more often than once per
+    /// `interval`. Values arriving in between replace each other, so only
+    /// the latest one is shown once the interval elapses.
+    pub fn start(
+        mut stream: ValueStream,
+        interval: Duration,
+        mut on_value: impl FnMut(Vec<u8>) + Send + 'static,
+    ) -> Self {
+        let task = spawn(async move {
+            let mut pending = None;
+            let mut ready_at = Instant::now();
+
+            loop {
+                tokio::select! {
+                    value = stream.next() => match value {
+                        Some(value) if Instant::now() >= ready_at => {
+                            on_value(value);
+                            ready_at = Instant::now() + interval;
+                        }
+                        Some(value) => pending = Some(value),
+                        None => break,
+                    },
+                    _ = sleep_until(ready_at), if pending.is_some() => {
+                        if let Some(value) = pending.take() {
+                            on_value(value);
+                        }
+                        ready_at = Instant::now() + interval;
+                    }
+                }
+            }
+
+            if let Some(value) = pending {
+                on_value(value);
+            }
+        });
+
+        Self { task }
+    }
+
+    /// Disables notifications, waiting for any value being shown.
+    pub async fn stop(mut self) {
+        self.task.abort();
+        // Being cancelled is the expected outcome.
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,322 @@
+use anyhow::{anyhow, Context, Result};
+use bluer::Address;
+use log::{debug, info};
+use std::{convert::TryInto, fs, io, path::PathBuf};
+
+use crate::{
+    bz::advertisement::format_hex,
+    hive::{Hive, Key, REG_BINARY},
+};
+
+const BTHPORT_KEYS: &str = "Services\\BTHPORT\\Parameters\\Keys";
+const BLUEZ_STORAGE: &str = "/var/lib/bluetooth";
+
+#[derive(Debug, Clone)]
+pub struct WindowsAdapterKeys {
+    pub adapter: Address,
+    pub devices: Vec<WindowsBondKeys>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowsBondKeys {
+    pub addr: Address,
+    pub link_key: Option<[u8; 16]>,
+    pub le: Option<LeKeys>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeKeys {
+    pub ltk: Option<[u8; 16]>,
+    pub key_length: u32,
+    pub erand: u64,
+    pub ediv: u32,
+    pub irk: Option<[u8; 16]>,
+    pub authenticated: bool,
+}
+
+impl WindowsBondKeys {
+    pub fn key_types(&self) -> Vec<&'static str> {
+        let mut types = Vec::new();
+        if self.link_key.is_some() {
+            types.push("LinkKey");
+        }
+        if let Some(le) = &self.le {
+            if le.ltk.is_some() {
+                types.push("LTK");
+            }
+            if le.irk.is_some() {
+                types.push("IRK");
+            }
+            if le.ltk.is_some() && (le.erand != 0 || le.ediv != 0) {
+                types.push("ERand/EDIV");
+            }
+        }
+        types
+    }
+}
+
+/// Reads the Bluetooth bond keys stored by Windows in an offline SYSTEM hive.
+pub fn read_windows_keys(hive: &Hive) -> Result<Vec<WindowsAdapterKeys>> {
+    let root = hive.root();
+    let control_set = current_control_set(&root)?;
+
+    let keys = root
+        .subpath(&format!("{control_set}\\{BTHPORT_KEYS}"))?
+        .ok_or_else(|| anyhow!("No Bluetooth keys found under {control_set}\\{BTHPORT_KEYS}"))?;
+
+    let mut adapters = Vec::new();
+
+    for adapter_key in keys.subkeys()? {
+        let Some(adapter) = parse_windows_address(&adapter_key.name()) else {
+            debug!("Skipping unexpected BTHPORT key {}", adapter_key.name());
+            continue;
+        };
+
+        let mut devices: Vec<WindowsBondKeys> = Vec::new();
+
+        for value in adapter_key.values()? {
+            let Some(addr) = parse_windows_address(&value.name) else {
+                continue;
+            };
+            if value.data_type != REG_BINARY {
+                continue;
+            }
+            if let Some(link_key) = to_key(&value.data) {
+                devices.push(WindowsBondKeys {
+                    addr,
+                    link_key: Some(link_key),
+                    le: None,
+                });
+            }
+        }
+
+        for device_key in adapter_key.subkeys()? {
+            let Some(addr) = parse_windows_address(&device_key.name()) else {
+                continue;
+            };
+            let le = read_le_keys(&device_key)?;
+
+            match devices.iter_mut().find(|d| d.addr == addr) {
+                Some(device) => device.le = Some(le),
+                None => devices.push(WindowsBondKeys {
+                    addr,
+                    link_key: None,
+                    le: Some(le),
+                }),
+            }
+        }
+
+        devices.sort_by_key(|d| d.addr);
+        adapters.push(WindowsAdapterKeys { adapter, devices });
+    }
+
+    Ok(adapters)
+}
+
+fn current_control_set(root: &Key) -> Result<String> {
+    let current = root
+        .subpath("Select")?
+        .map(|select| select.value("Current"))
+        .transpose()?
+        .flatten()
+        .and_then(|value| value.as_u32())
+        .unwrap_or(1);
+
+    Ok(format!("ControlSet{current:03}"))
+}
+
+fn read_le_keys(key: &Key) -> Result<LeKeys> {
+    let binary = |name: &str| -> Result<Option<[u8; 16]>> {
+        Ok(key.value(name)?.and_then(|value| to_key(&value.data)))
+    };
+    let number = |name: &str| -> Result<Option<u64>> {
+        Ok(key.value(name)?.and_then(|value| value.as_u64()))
+    };
+
+    Ok(LeKeys {
+        ltk: binary("LTK")?,
+        key_length: number("KeyLength")?.unwrap_or(16) as u32,
+        erand: number("ERand")?.unwrap_or(0),
+        ediv: number("EDIV")?.unwrap_or(0) as u32,
+        irk: binary("IRK")?,
+        authenticated: number("AuthReq")?.is_some_and(|auth_req| auth_req & 0x04 != 0),
+    })
+}
+
+/// Parses the 12 hex digit addresses Windows uses as key and value names.
+pub fn parse_windows_address(name: &str) -> Option<Address> {
+    if name.len() != 12 || !name.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let mut bytes = [0u8; 6];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&name[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(Address::new(bytes))
+}
+
+fn to_key(data: &[u8]) -> Option<[u8; 16]> {
+    data.try_into().ok()
+}
+
+/// BlueZ's on-disk pairing storage, `/var/lib/bluetooth` by default.
+#[derive(Debug, Clone)]
+pub struct BlueZKeyStore {
+    root: PathBuf,
+}
+
+impl BlueZKeyStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn system() -> Self {
+        Self::new(BLUEZ_STORAGE)
+    }
+
+    pub fn info_path(&self, adapter: Address, device: Address) -> PathBuf {
+        self.root
+            .join(adapter.to_string())
+            .join(device.to_string())
+            .join("info")
+    }
+
+    /// Writes the Windows keys into an existing BlueZ `info` file, keeping
+    /// every other entry untouched.
+    pub fn apply(&self, adapter: Address, keys: &WindowsBondKeys) -> Result<PathBuf> {
+        let path = self.info_path(adapter, keys.addr);
+
+        let existing = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let updated = merge_info(&existing, keys);
+
+        fs::write(&path, updated).with_context(|| format!("Failed to write {}", path.display()))?;
+        info!("Imported {} for {}", keys.key_types().join(", "), keys.addr);
+
+        Ok(path)
+    }
+
+    /// Whether BlueZ paired `device` on `adapter`. The storage is only
+    /// readable by root, so other users get a permission error rather than
+    /// `false`.
+    pub fn has_device(&self, adapter: Address, device: Address) -> io::Result<bool> {
+        self.info_path(adapter, device).try_exists()
+    }
+}
+
+/// Returns `info` with the link key and LE key sections replaced by `keys`.
+pub fn merge_info(info: &str, keys: &WindowsBondKeys) -> String {
+    let mut file = IniFile::parse(info);
+
+    if let Some(link_key) = keys.link_key {
+        file.set("LinkKey", "Key", &format_hex(&link_key, ""));
+        file.set_default("LinkKey", "Type", "4");
+        file.set_default("LinkKey", "PINLength", "0");
+    }
+
+    if let Some(le) = &keys.le {
+        if let Some(ltk) = le.ltk {
+            file.set("LongTermKey", "Key", &format_hex(&ltk, ""));
+            file.set(
+                "LongTermKey",
+                "Authenticated",
+                if le.authenticated { "1" } else { "0" },
+            );
+            file.set("LongTermKey", "EncSize", &le.key_length.to_string());
+            file.set("LongTermKey", "EDiv", &le.ediv.to_string());
+            file.set("LongTermKey", "Rand", &le.erand.to_string());
+        }
+
+        if let Some(irk) = le.irk {
+            // Windows stores the IRK in the opposite byte order.
+            let mut reversed = irk;
+            reversed.reverse();
+            file.set("IdentityResolvingKey", "Key", &format_hex(&reversed, ""));
+        }
+    }
+
+    file.to_string()
+}
+
+struct IniFile {
+    sections: Vec<(String, Vec<String>)>,
+}
+
+impl IniFile {
+    fn parse(content: &str) -> Self {
+        let mut sections: Vec<(String, Vec<String>)> = vec![(String::new(), Vec::new())];
+
+        for line in content.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                sections.push((trimmed[1..trimmed.len() - 1].to_string(), Vec::new()));
+            } else if let Some((_, lines)) = sections.last_mut() {
+                lines.push(line.to_string());
+            }
+        }
+
+        Self { sections }
+    }
+
+    fn section_mut(&mut self, section: &str) -> &mut Vec<String> {
+        let index = match self.sections.iter().position(|(name, _)| name == section) {
+            Some(index) => index,
+            None => {
+                if let Some((_, lines)) = self.sections.last_mut() {
+                    if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                        lines.push(String::new());
+                    }
+                }
+                self.sections.push((section.to_string(), Vec::new()));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[index].1
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: &str) {
+        let lines = self.section_mut(section);
+        let entry = format!("{key}={value}");
+        let prefix = format!("{key}=");
+
+        match lines
+            .iter()
+            .position(|l| l.trim_start().starts_with(&prefix))
+        {
+            Some(index) => lines[index] = entry,
+            None => {
+                let insert_at = lines
+                    .iter()
+                    .rposition(|l| !l.trim().is_empty())
+                    .map_or(0, |i| i + 1);
+                lines.insert(insert_at, entry);
+            }
+        }
+    }
+
+    fn set_default(&mut self, section: &str, key: &str, value: &str) {
+        let prefix = format!("{key}=");
+        if !self
+            .section_mut(section)
+            .iter()
+            .any(|l| l.trim_start().starts_with(&prefix))
+        {
+            self.set(section, key, value);
+        }
+    }
+}
+
+impl std::fmt::Display for IniFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, lines) in &self.sections {
+            if !name.is_empty() {
+                writeln!(f, "[{name}]")?;
+            }
+            for line in lines {
+                writeln!(f, "{line}")?;
+            }
+        }
+        Ok(())
+    }
+}
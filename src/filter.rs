@@ -0,0 +1,49 @@
+use crate::bz::device::Device;
+
+/// Rules hiding noisy entries from the discovered devices. Paired and
+/// connected devices are always listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceFilter {
+    /// Hide devices that never sent a name, listed under their address.
+    pub hide_unnamed: bool,
+    /// Hide devices whose signal is weaker than this, in dBm. Devices whose
+    /// signal is unknown are kept.
+    pub min_rssi: Option<i16>,
+    /// Device types to hide, as shown by the menu icons (e.g. `phone`).
+    pub hidden_types: Vec<String>,
+    /// Address prefixes to hide, such as a vendor OUI (e.g. `AC:23:3F`).
+    pub hidden_prefixes: Vec<String>,
+}
+
+impl DeviceFilter {
+    pub fn is_active(&self) -> bool {
+        self.hide_unnamed
+            || self.min_rssi.is_some()
+            || !self.hidden_types.is_empty()
+            || !self.hidden_prefixes.is_empty()
+    }
+
+    pub fn hides(&self, device: &Device) -> bool {
+        if device.is_paired || device.is_connected {
+            return false;
+        }
+
+        let addr = device.addr.to_string();
+
+        (self.hide_unnamed && device.is_unnamed())
+            || matches!((self.min_rssi, device.rssi), (Some(floor), Some(rssi)) if rssi < floor)
+            || self
+                .hidden_types
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(&device.device_type))
+            || self
+                .hidden_prefixes
+                .iter()
+                .any(|prefix| addr.starts_with(&prefix.to_uppercase().replace('-', ":")))
+    }
+
+    /// Splits `devices` into the ones to list and the ones to hide.
+    pub fn split(&self, devices: Vec<Device>) -> (Vec<Device>, Vec<Device>) {
+        devices.into_iter().partition(|device| !self.hides(device))
+    }
+}
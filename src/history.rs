@@ -0,0 +1,164 @@
+use bluer::Address;
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::bz::{device::Device, device_info::DeviceInfo};
+
+/// Shortest time between two recorded sightings of a device, in seconds.
+/// Elapsed times are never shown more precisely.
+pub const SEEN_RESOLUTION: u64 = 60;
+
+/// What bzmenu has observed of a device across runs. Times are Unix
+/// timestamps in seconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceHistory {
+    #[serde(default)]
+    pub alias: String,
+    pub first_seen: Option<u64>,
+    pub last_seen: Option<u64>,
+    pub last_connected: Option<u64>,
+    pub last_disconnected: Option<u64>,
+    pub last_battery: Option<u8>,
+    /// Connection state at the last observation, to spot disconnections that
+    /// happened while bzmenu was not looking.
+    #[serde(default)]
+    pub connected: bool,
+    /// Device Information Service values as last read.
+    pub device_info: Option<DeviceInfo>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    #[serde(default)]
+    pub devices: BTreeMap<Address, DeviceHistory>,
+}
+
+impl History {
+    pub fn get(&self, addr: Address) -> Option<&DeviceHistory> {
+        self.devices.get(&addr)
+    }
+
+    pub fn last_connected(&self, addr: Address) -> Option<u64> {
+        self.get(addr).and_then(|entry| entry.last_connected)
+    }
+
+    /// Records the current state of `devices`, all the ones BlueZ lists. A
+    /// device counts as seen when it is connected or reports a signal
+    /// strength, which BlueZ only does while it is in range during discovery.
+    /// BlueZ keeps listing devices long after they went away, so an unpaired
+    /// device is only taken as seen without a signal the first time it shows
+    /// up.
+    ///
+    /// Connection times only change when the connection state does, and
+    /// sightings less than [`SEEN_RESOLUTION`] apart are not recorded, so
+    /// that refreshing an unchanged list leaves the history as it was.
+    ///
+    /// Devices no longer listed are dropped unless they were ever connected,
+    /// so that the addresses BLE devices rotate through do not pile up.
+    pub fn observe<'a>(&mut self, devices: impl IntoIterator<Item = &'a Device>, now: u64) {
+        let mut listed = HashSet::new();
+        for device in devices {
+            listed.insert(device.addr);
+            let first_sighting = !device.is_paired && !self.devices.contains_key(&device.addr);
+            let entry = self.devices.entry(device.addr).or_default();
+            entry.alias = device.alias.clone();
+
+            if device.is_connected || device.rssi.is_some() || first_sighting {
+                entry.first_seen.get_or_insert(now);
+                if entry
+                    .last_seen
+                    .is_none_or(|seen| now.saturating_sub(seen) >= SEEN_RESOLUTION)
+                {
+                    entry.last_seen = Some(now);
+                }
+            }
+
+            if device.is_connected && !entry.connected {
+                entry.last_connected = Some(now);
+            } else if !device.is_connected && entry.connected {
+                entry.last_disconnected = Some(now);
+            }
+            entry.connected = device.is_connected;
+
+            if let Some(battery) = device.battery_percentage {
+                entry.last_battery = Some(battery);
+            }
+        }
+
+        self.devices
+            .retain(|addr, entry| entry.last_connected.is_some() || listed.contains(addr));
+    }
+
+    pub fn record_connected(&mut self, device: &Device, now: u64) {
+        let entry = self.devices.entry(device.addr).or_default();
+        entry.alias = device.alias.clone();
+        entry.first_seen.get_or_insert(now);
+        entry.last_seen = Some(now);
+        entry.last_connected = Some(now);
+        entry.connected = true;
+    }
+
+    pub fn record_disconnected(&mut self, device: &Device, now: u64) {
+        let entry = self.devices.entry(device.addr).or_default();
+        entry.alias = device.alias.clone();
+        entry.last_disconnected = Some(now);
+        entry.connected = false;
+    }
+
+    pub fn record_device_info(&mut self, device: &Device, info: DeviceInfo) {
+        let entry = self.devices.entry(device.addr).or_default();
+        entry.alias = device.alias.clone();
+        entry.device_info = Some(info);
+    }
+
+    /// Whether the device was last seen more than `max_age` seconds ago.
+    /// Devices never seen are not considered stale.
+    pub fn is_stale(&self, addr: Address, now: u64, max_age: u64) -> bool {
+        self.get(addr)
+            .and_then(|entry| entry.last_seen)
+            .is_some_and(|seen| now.saturating_sub(seen) > max_age)
+    }
+
+    /// Drops a device removed from BlueZ, unless it was ever connected and
+    /// its history is still worth showing.
+    pub fn forget(&mut self, addr: Address) {
+        if self.last_connected(addr).is_none() {
+            self.devices.remove(&addr);
+        }
+    }
+
+    /// Devices ordered by most recent connection, then most recently seen.
+    pub fn recent(&self) -> Vec<(Address, &DeviceHistory)> {
+        let mut entries: Vec<_> = self
+            .devices
+            .iter()
+            .map(|(&addr, entry)| (addr, entry))
+            .collect();
+        entries.sort_by_key(|(addr, entry)| {
+            (
+                std::cmp::Reverse(entry.last_connected),
+                std::cmp::Reverse(entry.last_seen),
+                *addr,
+            )
+        });
+        entries
+    }
+}
+
+/// Describes how long ago `then` was, e.g. "3 days ago".
+pub fn format_elapsed(then: u64, now: u64) -> String {
+    let elapsed = now.saturating_sub(then);
+
+    let text = match elapsed {
+        0..=59 => t!("menus.details.time.just_now"),
+        60..=119 => t!("menus.details.time.minute_ago"),
+        120..=3599 => t!("menus.details.time.minutes_ago", count = elapsed / 60),
+        3600..=7199 => t!("menus.details.time.hour_ago"),
+        7200..=86399 => t!("menus.details.time.hours_ago", count = elapsed / 3600),
+        86400..=172799 => t!("menus.details.time.day_ago"),
+        _ => t!("menus.details.time.days_ago", count = elapsed / 86400),
+    };
+
+    text.to_string()
+}
@@ -0,0 +1,295 @@
+//! Minimal read-only parser for offline Windows registry hive (regf) files.
+//!
+//! Only what is needed to walk keys and read small values is supported:
+//! `lf`/`lh`/`li`/`ri` subkey lists and resident or inline value data.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::{convert::TryInto, fs, path::Path};
+
+const BASE_BLOCK_SIZE: usize = 4096;
+const KEY_COMP_NAME: u16 = 0x0020;
+const VALUE_COMP_NAME: u16 = 0x0001;
+const DATA_INLINE: u32 = 0x8000_0000;
+/// Index roots only point to leaf lists in practice; the limit keeps a
+/// crafted hive from recursing endlessly.
+const MAX_LIST_DEPTH: usize = 8;
+
+pub const REG_SZ: u32 = 1;
+pub const REG_BINARY: u32 = 3;
+pub const REG_DWORD: u32 = 4;
+pub const REG_QWORD: u32 = 11;
+
+pub struct Hive {
+    data: Vec<u8>,
+    root_offset: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Key<'a> {
+    hive: &'a Hive,
+    offset: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub name: String,
+    pub data_type: u32,
+    pub data: Vec<u8>,
+}
+
+impl std::fmt::Debug for Hive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hive")
+            .field("size", &self.data.len())
+            .field("root_offset", &self.root_offset)
+            .finish()
+    }
+}
+
+impl Hive {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data =
+            fs::read(path).with_context(|| format!("Failed to read hive {}", path.display()))?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        if data.len() < BASE_BLOCK_SIZE || &data[0..4] != b"regf" {
+            bail!("Not a Windows registry hive");
+        }
+
+        let root_offset = read_u32(&data, 0x24)?;
+        let hive = Self { data, root_offset };
+        hive.key(root_offset)?;
+
+        Ok(hive)
+    }
+
+    pub fn root(&self) -> Key<'_> {
+        Key {
+            hive: self,
+            offset: self.root_offset,
+        }
+    }
+
+    fn key(&self, offset: u32) -> Result<Key<'_>> {
+        let cell = self.cell(offset)?;
+        if cell.len() < 76 || &cell[0..2] != b"nk" {
+            bail!("Invalid key node at offset {offset:#x}");
+        }
+        Ok(Key { hive: self, offset })
+    }
+
+    /// Returns the payload of the cell at `offset`, relative to the first hive bin.
+    fn cell(&self, offset: u32) -> Result<&[u8]> {
+        let start = BASE_BLOCK_SIZE + offset as usize;
+        let size = read_i32(&self.data, start)?;
+        let size = size.unsigned_abs() as usize;
+
+        if size < 4 || start + size > self.data.len() {
+            bail!("Cell at offset {offset:#x} is out of bounds");
+        }
+
+        Ok(&self.data[start + 4..start + size])
+    }
+
+    fn subkey_offsets(&self, list_offset: u32, depth: usize, offsets: &mut Vec<u32>) -> Result<()> {
+        if depth > MAX_LIST_DEPTH {
+            bail!("Subkey lists nested too deeply at offset {list_offset:#x}");
+        }
+
+        let list = self.cell(list_offset)?;
+        if list.len() < 4 {
+            bail!("Invalid subkey list at offset {list_offset:#x}");
+        }
+
+        let count = read_u16(list, 2)? as usize;
+
+        match &list[0..2] {
+            b"lf" | b"lh" => {
+                for i in 0..count {
+                    offsets.push(read_u32(list, 4 + i * 8)?);
+                }
+            }
+            b"li" => {
+                for i in 0..count {
+                    offsets.push(read_u32(list, 4 + i * 4)?);
+                }
+            }
+            b"ri" => {
+                for i in 0..count {
+                    self.subkey_offsets(read_u32(list, 4 + i * 4)?, depth + 1, offsets)?;
+                }
+            }
+            _ => bail!("Unknown subkey list at offset {list_offset:#x}"),
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Key<'a> {
+    fn node(&self) -> &'a [u8] {
+        // Validated when the key was created.
+        self.hive.cell(self.offset).unwrap_or_default()
+    }
+
+    pub fn name(&self) -> String {
+        let node = self.node();
+        let flags = read_u16(node, 2).unwrap_or_default();
+        let len = read_u16(node, 72).unwrap_or_default() as usize;
+        let raw = node.get(76..76 + len).unwrap_or_default();
+
+        decode_name(raw, flags & KEY_COMP_NAME != 0)
+    }
+
+    pub fn subkeys(&self) -> Result<Vec<Key<'a>>> {
+        let node = self.node();
+        let count = read_u32(node, 20)?;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut offsets = Vec::new();
+        self.hive
+            .subkey_offsets(read_u32(node, 28)?, 0, &mut offsets)?;
+
+        offsets
+            .into_iter()
+            .map(|offset| self.hive.key(offset))
+            .collect()
+    }
+
+    pub fn subkey(&self, name: &str) -> Result<Option<Key<'a>>> {
+        Ok(self
+            .subkeys()?
+            .into_iter()
+            .find(|key| key.name().eq_ignore_ascii_case(name)))
+    }
+
+    /// Resolves a backslash separated path relative to this key.
+    pub fn subpath(&self, path: &str) -> Result<Option<Key<'a>>> {
+        let mut key = *self;
+        for component in path.split('\\').filter(|c| !c.is_empty()) {
+            match key.subkey(component)? {
+                Some(next) => key = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(key))
+    }
+
+    pub fn values(&self) -> Result<Vec<Value>> {
+        let node = self.node();
+        let count = read_u32(node, 36)? as usize;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let list = self.hive.cell(read_u32(node, 40)?)?;
+
+        (0..count)
+            .map(|i| self.read_value(read_u32(list, i * 4)?))
+            .collect()
+    }
+
+    pub fn value(&self, name: &str) -> Result<Option<Value>> {
+        Ok(self
+            .values()?
+            .into_iter()
+            .find(|value| value.name.eq_ignore_ascii_case(name)))
+    }
+
+    fn read_value(&self, offset: u32) -> Result<Value> {
+        let vk = self.hive.cell(offset)?;
+        if vk.len() < 20 || &vk[0..2] != b"vk" {
+            bail!("Invalid value node at offset {offset:#x}");
+        }
+
+        let name_len = read_u16(vk, 2)? as usize;
+        let data_size = read_u32(vk, 4)?;
+        let data_type = read_u32(vk, 12)?;
+        let flags = read_u16(vk, 16)?;
+        let raw_name = vk
+            .get(20..20 + name_len)
+            .ok_or_else(|| anyhow!("Value name at offset {offset:#x} is out of bounds"))?;
+
+        let data = if data_size & DATA_INLINE != 0 {
+            let len = (data_size & !DATA_INLINE).min(4) as usize;
+            vk[8..8 + len].to_vec()
+        } else {
+            let len = data_size as usize;
+            let cell = self.hive.cell(read_u32(vk, 8)?)?;
+            cell.get(..len)
+                .ok_or_else(|| anyhow!("Value data at offset {offset:#x} is not resident"))?
+                .to_vec()
+        };
+
+        Ok(Value {
+            name: decode_name(raw_name, flags & VALUE_COMP_NAME != 0),
+            data_type,
+            data,
+        })
+    }
+}
+
+impl Value {
+    pub fn as_u32(&self) -> Option<u32> {
+        if self.data_type != REG_DWORD {
+            return None;
+        }
+        self.data.get(..4)?.try_into().ok().map(u32::from_le_bytes)
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.data_type {
+            REG_QWORD => self.data.get(..8)?.try_into().ok().map(u64::from_le_bytes),
+            REG_BINARY if self.data.len() == 8 => {
+                self.data[..8].try_into().ok().map(u64::from_le_bytes)
+            }
+            _ => self.as_u32().map(u64::from),
+        }
+    }
+
+    pub fn as_string(&self) -> Option<String> {
+        if self.data_type != REG_SZ {
+            return None;
+        }
+
+        let units: Vec<u16> = self
+            .data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&u| u != 0)
+            .collect();
+        Some(String::from_utf16_lossy(&units))
+    }
+}
+
+fn decode_name(raw: &[u8], compressed: bool) -> String {
+    if compressed {
+        raw.iter().map(|&b| b as char).collect()
+    } else {
+        let units: Vec<u16> = raw
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| anyhow!("Unexpected end of hive data"))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| anyhow!("Unexpected end of hive data"))
+}
+
+fn read_i32(data: &[u8], at: usize) -> Result<i32> {
+    read_u32(data, at).map(|v| v as i32)
+}
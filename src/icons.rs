@@ -49,12 +49,24 @@ impl Icons {
         font_icons.insert("connect", '\u{f0337}');
         font_icons.insert("disconnect", '\u{f0338}');
         font_icons.insert("scan", '\u{f46a}');
+        font_icons.insert("scan_in_progress", '\u{f0437}');
         font_icons.insert("settings", '\u{f08bb}');
         font_icons.insert("disable_adapter", '\u{f00b2}');
+        font_icons.insert("clear_devices", '\u{f05e9}');
         font_icons.insert("power_on_device", '\u{f0425}');
         font_icons.insert("trust", '\u{f0cc8}');
         font_icons.insert("revoke_trust", '\u{f099c}');
         font_icons.insert("forget", '\u{f0377}');
+        font_icons.insert("pin", '\u{f0403}');
+        font_icons.insert("unpin", '\u{f0404}');
+        font_icons.insert("details", '\u{f02fd}');
+        font_icons.insert("show_hidden", '\u{f0208}');
+        font_icons.insert("advertisement", '\u{f1720}');
+        font_icons.insert("services", '\u{f0493}');
+        font_icons.insert("read_again", '\u{f0450}');
+        font_icons.insert("write_value", '\u{f03eb}');
+        font_icons.insert("subscribe", '\u{f009e}');
+        font_icons.insert("stop", '\u{f04db}');
 
         font_icons.insert("enable_pairable", '\u{f0339}');
         font_icons.insert("disable_pairable", '\u{f033a}');
@@ -87,6 +99,11 @@ impl Icons {
         font_icons.insert("battery_20", '\u{f007b}');
         font_icons.insert("battery_10", '\u{f007a}');
 
+        font_icons.insert("signal_excellent", '\u{f0928}');
+        font_icons.insert("signal_good", '\u{f0925}');
+        font_icons.insert("signal_fair", '\u{f0922}');
+        font_icons.insert("signal_weak", '\u{f091f}');
+
         font_icons.insert("back", '\u{f004d}');
         font_icons.insert("ok", '\u{f05e1}');
         font_icons.insert("error", '\u{f05d6}');
@@ -140,6 +157,26 @@ impl Icons {
             "settings",
             IconDefinition::simple("preferences-system-symbolic"),
         );
+        xdg_icons.insert(
+            "signal_excellent",
+            IconDefinition::simple("network-cellular-signal-excellent-symbolic"),
+        );
+        xdg_icons.insert(
+            "signal_good",
+            IconDefinition::simple("network-cellular-signal-good-symbolic"),
+        );
+        xdg_icons.insert(
+            "signal_fair",
+            IconDefinition::simple("network-cellular-signal-ok-symbolic"),
+        );
+        xdg_icons.insert(
+            "signal_weak",
+            IconDefinition::simple("network-cellular-signal-weak-symbolic"),
+        );
+        xdg_icons.insert(
+            "clear_devices",
+            IconDefinition::simple("edit-clear-all-symbolic"),
+        );
         xdg_icons.insert(
             "disable_adapter",
             IconDefinition::with_fallbacks(
@@ -157,6 +194,37 @@ impl Icons {
             IconDefinition::simple("action-unavailable-symbolic"),
         );
         xdg_icons.insert("forget", IconDefinition::simple("list-remove-symbolic"));
+        xdg_icons.insert("pin", IconDefinition::simple("view-pin-symbolic"));
+        xdg_icons.insert("unpin", IconDefinition::simple("window-unpin-symbolic"));
+        xdg_icons.insert(
+            "details",
+            IconDefinition::simple("dialog-information-symbolic"),
+        );
+        xdg_icons.insert(
+            "show_hidden",
+            IconDefinition::simple("view-reveal-symbolic"),
+        );
+        xdg_icons.insert(
+            "advertisement",
+            IconDefinition::simple("network-transmit-receive-symbolic"),
+        );
+        xdg_icons.insert("services", IconDefinition::simple("view-list-symbolic"));
+        xdg_icons.insert(
+            "read_again",
+            IconDefinition::simple("view-refresh-symbolic"),
+        );
+        xdg_icons.insert(
+            "write_value",
+            IconDefinition::simple("document-edit-symbolic"),
+        );
+        xdg_icons.insert(
+            "subscribe",
+            IconDefinition::simple("preferences-system-notifications-symbolic"),
+        );
+        xdg_icons.insert(
+            "stop",
+            IconDefinition::simple("media-playback-stop-symbolic"),
+        );
 
         xdg_icons.insert(
             "enable_pairable",
@@ -378,6 +446,19 @@ impl Icons {
             .join("\n")
     }
 
+    /// Text of a menu entry without the font icon put in front of it. Font
+    /// icons are private use characters, so names are left intact whatever
+    /// letter they start with.
+    pub fn strip_font_icon(entry: &str) -> &str {
+        let entry = entry.trim();
+        match entry.chars().next() {
+            Some(icon @ ('\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{10FFFD}')) => {
+                entry[icon.len_utf8()..].trim_start()
+            }
+            _ => entry,
+        }
+    }
+
     pub fn format_with_spacing(icon: char, spaces: usize, before: bool) -> String {
         if before {
             format!("{}{}", " ".repeat(spaces), icon)
@@ -436,6 +517,18 @@ impl Icons {
 
         Some(self.get_icon(icon_key, icon_type))
     }
+
+    /// Signal bars for a signal strength in dBm.
+    pub fn get_signal_icon(&self, rssi: i16, icon_type: &str) -> String {
+        let icon_key = match rssi {
+            -55.. => "signal_excellent",
+            -67..=-56 => "signal_good",
+            -80..=-68 => "signal_fair",
+            _ => "signal_weak",
+        };
+
+        self.get_icon(icon_key, icon_type)
+    }
 }
 
 impl Default for Icons {
@@ -8,15 +8,20 @@ use nix::{
 use process_wrap::std::{CommandWrap, ProcessGroup};
 use signal_hook::iterator::Signals;
 use std::{
+    collections::VecDeque,
+    fmt, fs,
     io::Write,
+    path::Path,
     process::{exit, Command, Stdio},
     sync::{
-        atomic::{AtomicI32, Ordering},
-        Once,
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Mutex, Once,
     },
     thread,
 };
 
+use crate::icons::Icons;
+
 #[derive(Debug, Clone, ValueEnum)]
 pub enum LauncherType {
     Fuzzel,
@@ -24,6 +29,7 @@ pub enum LauncherType {
     Dmenu,
     Bemenu,
     Custom,
+    Script,
 }
 
 #[derive(Debug, Clone)]
@@ -50,6 +56,8 @@ pub enum LauncherCommand {
 
 static CURRENT_LAUNCHER_PID: AtomicI32 = AtomicI32::new(-1);
 static SIGNAL_HANDLER_INIT: Once = Once::new();
+static CATCH_SIGNALS: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
 pub struct Launcher;
 
@@ -109,6 +117,25 @@ impl Launcher {
         Self::run_command(command, input)
     }
 
+    /// Closes the launcher currently shown, if any, as if it was dismissed.
+    pub fn close_current() {
+        let pid = CURRENT_LAUNCHER_PID.load(Ordering::Relaxed);
+        if pid > 0 {
+            let _ = killpg(Pid::from_raw(pid), Signal::SIGTERM);
+        }
+    }
+
+    /// Runs `f` with SIGINT and SIGTERM only closing the launcher instead of
+    /// exiting, so that the caller can clean up. Also returns whether such a
+    /// signal was received.
+    pub fn catch_signals<T>(f: impl FnOnce() -> T) -> (T, bool) {
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        CATCH_SIGNALS.store(true, Ordering::Relaxed);
+        let result = f();
+        CATCH_SIGNALS.store(false, Ordering::Relaxed);
+        (result, INTERRUPTED.swap(false, Ordering::Relaxed))
+    }
+
     fn substitute_placeholders(template: &str, hint: Option<&str>) -> Result<String> {
         if !template.contains('{') {
             return Ok(template.to_string());
@@ -158,11 +185,15 @@ impl Launcher {
         SIGNAL_HANDLER_INIT.call_once(|| {
             thread::spawn(|| {
                 let mut signals = Signals::new([libc::SIGTERM, libc::SIGINT]).unwrap();
-                if let Some(_signal) = signals.forever().next() {
+                for _signal in signals.forever() {
                     let current_pid = CURRENT_LAUNCHER_PID.load(Ordering::Relaxed);
                     if current_pid > 0 && kill(Pid::from_raw(current_pid), None).is_ok() {
                         let _ = killpg(Pid::from_raw(current_pid), Signal::SIGTERM);
                     }
+                    if CATCH_SIGNALS.load(Ordering::Relaxed) {
+                        INTERRUPTED.store(true, Ordering::Relaxed);
+                        continue;
+                    }
                     exit(0);
                 }
             });
@@ -217,6 +248,127 @@ impl Launcher {
                     Err(anyhow!("No custom launcher command provided"))
                 }
             }
+            LauncherType::Script => Err(anyhow!("No launcher script provided")),
         }
     }
 }
+
+/// Script line standing for the user dismissing the menu.
+pub const SCRIPT_ESCAPE: &str = "<escape>";
+
+/// A menu rendered by [`ScriptedLauncher`], along with what was picked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptedPrompt {
+    pub entries: Vec<String>,
+    pub hint: Option<String>,
+    pub selection: Option<String>,
+}
+
+impl fmt::Display for ScriptedPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "hint: {}", self.hint.as_deref().unwrap_or("-"))?;
+        for entry in &self.entries {
+            writeln!(f, "  {entry}")?;
+        }
+        write!(
+            f,
+            "-> {}",
+            self.selection.as_deref().unwrap_or(SCRIPT_ESCAPE)
+        )
+    }
+}
+
+/// Launcher replaying a fixed sequence of selections instead of spawning a
+/// process, recording every menu it is shown.
+///
+/// Each step is matched against the entry labels with icons stripped, first
+/// exactly and then as a prefix. [`SCRIPT_ESCAPE`] dismisses the menu, and so
+/// does running out of steps. Prompts without entries, which ask for free
+/// text, take the step as typed.
+#[derive(Debug, Default)]
+pub struct ScriptedLauncher {
+    steps: Mutex<VecDeque<String>>,
+    transcript: Mutex<Vec<ScriptedPrompt>>,
+}
+
+impl ScriptedLauncher {
+    pub fn new<I, S>(steps: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            steps: Mutex::new(steps.into_iter().map(Into::into).collect()),
+            transcript: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Reads one step per line, skipping blank lines and `#` comments.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read launcher script {}", path.display()))?;
+
+        Ok(Self::new(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#')),
+        ))
+    }
+
+    pub fn run(&self, input: Option<&str>, hint: Option<&str>) -> Result<Option<String>> {
+        let entries: Vec<String> = input
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(Self::entry_text)
+            .collect();
+
+        let step = self.steps.lock().unwrap().pop_front();
+
+        let selection = match step.as_deref() {
+            None | Some(SCRIPT_ESCAPE) => None,
+            Some(step) if entries.is_empty() => Some(step.to_string()),
+            Some(step) => Some(Self::select(&entries, step).ok_or_else(|| {
+                anyhow!("Scripted selection '{step}' not found in menu: {entries:?}")
+            })?),
+        };
+
+        self.transcript.lock().unwrap().push(ScriptedPrompt {
+            entries,
+            hint: hint.filter(|h| !h.is_empty()).map(str::to_string),
+            selection: selection.clone(),
+        });
+
+        Ok(selection)
+    }
+
+    pub fn transcript(&self) -> Vec<ScriptedPrompt> {
+        self.transcript.lock().unwrap().clone()
+    }
+
+    pub fn remaining_steps(&self) -> usize {
+        self.steps.lock().unwrap().len()
+    }
+
+    /// Drops the xdg icon metadata, which real launchers never echo back.
+    fn entry_text(line: &str) -> String {
+        line.split('\0').next().unwrap_or_default().to_string()
+    }
+
+    fn label(entry: &str) -> &str {
+        Icons::strip_font_icon(entry)
+    }
+
+    fn select(entries: &[String], step: &str) -> Option<String> {
+        entries
+            .iter()
+            .find(|entry| Self::label(entry) == step)
+            .or_else(|| {
+                entries
+                    .iter()
+                    .find(|entry| Self::label(entry).starts_with(step))
+            })
+            .cloned()
+    }
+}
@@ -5,14 +5,30 @@ mod macros;
 i18n!("locales", fallback = "en");
 
 pub mod app;
+pub mod filter;
+pub mod history;
+pub mod hive;
 pub mod icons;
 pub mod launcher;
 pub mod menu;
+pub mod navigation;
 pub mod notification;
+pub mod state;
+pub mod status;
 pub mod bz {
+    pub mod advertisement;
     pub mod agent;
+    pub mod backend;
+    pub mod beacon;
     pub mod controller;
     pub mod device;
+    pub mod device_info;
+    pub mod error;
+    #[cfg(any(test, feature = "fake"))]
+    pub mod fake;
+    pub mod gatt;
     pub mod pairing;
     pub mod scanner;
+    pub mod subscription;
+    pub mod windows_keys;
 }
@@ -1,8 +1,31 @@
 use anyhow::{anyhow, Result};
-use bzmenu::{app::App, icons::Icons, launcher::LauncherType, menu::Menu};
+use bluer::Session;
+use bzmenu::{
+    app::{App, AppConfig},
+    bz::{
+        controller::Controller,
+        pairing::ConnectionPolicy,
+        subscription::SubscriptionOutput,
+        windows_keys::{read_windows_keys, BlueZKeyStore},
+    },
+    filter::DeviceFilter,
+    history::format_elapsed,
+    hive::Hive,
+    icons::Icons,
+    launcher::{LauncherType, ScriptedLauncher},
+    menu::Menu,
+    navigation::OpenTarget,
+    state::{now, DeviceOrder, StateStore},
+    status::Status,
+};
 use clap::{Arg, Command};
 use rust_i18n::{i18n, set_locale};
-use std::{env, sync::Arc};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 use sys_locale::get_locale;
 
 i18n!("locales", fallback = "en");
@@ -29,6 +52,7 @@ async fn main() -> Result<()> {
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
+        .subcommand_negates_reqs(true)
         .arg(
             Arg::new("launcher")
                 .short('l')
@@ -62,6 +86,13 @@ async fn main() -> Result<()> {
                 .value_parser(validate_launcher_command)
                 .help("DEPRECATED: use --launcher-command instead"),
         )
+        .arg(
+            Arg::new("script")
+                .long("script")
+                .required_if_eq("launcher", "script")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("File of menu selections to replay when --launcher is set to script"),
+        )
         .arg(
             Arg::new("icon")
                 .short('i')
@@ -83,14 +114,195 @@ async fn main() -> Result<()> {
                 .default_value("10")
                 .help("Duration of Bluetooth device discovery in seconds"),
         )
+        .arg(
+            Arg::new("connect_retries")
+                .long("connect-retries")
+                .default_value("2")
+                .value_parser(clap::value_parser!(u32))
+                .help("Number of times to retry a failed connection attempt"),
+        )
+        .arg(
+            Arg::new("connect_retry_delay")
+                .long("connect-retry-delay")
+                .default_value("2")
+                .help("Delay in seconds before the first retry, doubled after each attempt"),
+        )
+        .arg(
+            Arg::new("connect_timeout")
+                .long("connect-timeout")
+                .default_value("30")
+                .help("Overall timeout in seconds for a connection, including retries"),
+        )
         .arg(
             Arg::new("interactive")
                 .long("interactive")
                 .action(clap::ArgAction::SetTrue)
                 .help("Stay in menus after actions and return to previous menu on escape"),
         )
+        .arg(
+            Arg::new("device_order")
+                .long("device-order")
+                .value_parser(clap::value_parser!(DeviceOrder))
+                .default_value("recent")
+                .help("Order of paired devices after pinned ones"),
+        )
+        .arg(
+            Arg::new("new_device_order")
+                .long("new-device-order")
+                .value_parser(clap::value_parser!(DeviceOrder))
+                .default_value("address")
+                .help("Order of discovered devices"),
+        )
+        .arg(
+            Arg::new("quick_connect")
+                .long("quick-connect")
+                .action(clap::ArgAction::SetTrue)
+                .help("Connect or disconnect paired devices directly from the main menu"),
+        )
+        .arg(
+            Arg::new("auto_scan")
+                .long("auto-scan")
+                .action(clap::ArgAction::SetTrue)
+                .help("Scan for devices in the background whenever the main menu opens"),
+        )
+        .arg(
+            Arg::new("hide_unnamed")
+                .long("hide-unnamed")
+                .action(clap::ArgAction::SetTrue)
+                .help("Hide discovered devices that have no name"),
+        )
+        .arg(
+            Arg::new("min_rssi")
+                .long("min-rssi")
+                .value_name("DBM")
+                .allow_negative_numbers(true)
+                .value_parser(clap::value_parser!(i16))
+                .help("Hide discovered devices with a weaker signal, e.g. -80"),
+        )
+        .arg(
+            Arg::new("hide_type")
+                .long("hide-type")
+                .value_name("TYPE")
+                .action(clap::ArgAction::Append)
+                .value_delimiter(',')
+                .help("Hide discovered devices of this type, e.g. phone or tag"),
+        )
+        .arg(
+            Arg::new("hide_address")
+                .long("hide-address")
+                .value_name("PREFIX")
+                .action(clap::ArgAction::Append)
+                .value_delimiter(',')
+                .help("Hide discovered devices whose address starts with this prefix"),
+        )
+        .arg(
+            Arg::new("prune_after")
+                .long("prune-after")
+                .value_name("MINUTES")
+                .help("Remove discovered devices not seen for this many minutes"),
+        )
+        .arg(
+            Arg::new("subscription_output")
+                .long("subscription-output")
+                .value_parser(clap::value_parser!(SubscriptionOutput))
+                .default_value("stdout")
+                .help("Where values of a subscribed characteristic are shown"),
+        )
+        .arg(
+            Arg::new("open")
+                .long("open")
+                .value_name("MENU")
+                .value_parser(clap::value_parser!(OpenTarget))
+                .help("Menu to start in: main, settings, scan or device:<addr|alias>"),
+        )
+        .subcommand(
+            Command::new("toggle")
+                .about("Connect a device if it is disconnected, disconnect it otherwise")
+                .arg(
+                    Arg::new("device")
+                        .required(true)
+                        .help("Address or alias of a known device"),
+                ),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Show the Device Information a device reports, such as its firmware")
+                .arg(
+                    Arg::new("device")
+                        .required(true)
+                        .help("Address or alias of a known device"),
+                ),
+        )
+        .subcommand(
+            Command::new("scan")
+                .about("Scan until a device shows up, and optionally connect to it")
+                .arg(
+                    Arg::new("until")
+                        .long("until")
+                        .required(true)
+                        .value_name("DEVICE")
+                        .help("Address, name or pattern such as 'JBL*' of the device to wait for"),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .default_value("30")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .help("Seconds to scan before giving up"),
+                )
+                .arg(
+                    Arg::new("connect")
+                        .long("connect")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Pair and connect the device once found"),
+                ),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show the adapter and its devices, with decoded beacon data")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Print the status as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("List devices bzmenu has seen, most recently connected first"),
+        )
+        .subcommand(
+            Command::new("import-windows-keys")
+                .about("Import Bluetooth pairing keys from an offline Windows SYSTEM hive")
+                .arg(
+                    Arg::new("hive")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("Path to the Windows SYSTEM hive (Windows/System32/config/SYSTEM)"),
+                )
+                .arg(
+                    Arg::new("dry_run")
+                        .long("dry-run")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Only show the matching devices without writing any keys"),
+                ),
+        )
         .get_matches();
 
+    if let Some(("history", _)) = matches.subcommand() {
+        print_history();
+        return Ok(());
+    }
+
+    if let Some(("status", sub_matches)) = matches.subcommand() {
+        return print_status(sub_matches.get_flag("json")).await;
+    }
+
+    if let Some(("import-windows-keys", sub_matches)) = matches.subcommand() {
+        let hive = sub_matches.get_one::<PathBuf>("hive").unwrap();
+        return import_windows_keys(hive, sub_matches.get_flag("dry_run")).await;
+    }
+
     let launcher_type: LauncherType = if matches.contains_id("launcher") {
         matches.get_one::<LauncherType>("launcher").unwrap().clone()
     } else if matches.contains_id("menu") {
@@ -100,6 +312,10 @@ async fn main() -> Result<()> {
         LauncherType::Dmenu
     };
 
+    if matches.contains_id("script") && !matches!(launcher_type, LauncherType::Script) {
+        return Err(anyhow!("--script can only be used with --launcher script"));
+    }
+
     let command_str = if matches.contains_id("launcher_command") {
         matches.get_one::<String>("launcher_command").cloned()
     } else if matches.contains_id("menu_command") {
@@ -113,7 +329,15 @@ async fn main() -> Result<()> {
 
     let icon_type = matches.get_one::<String>("icon").unwrap().clone();
     let icons = Arc::new(Icons::new());
-    let menu = Menu::new(launcher_type, icons.clone());
+    let script = matches
+        .get_one::<PathBuf>("script")
+        .map(|path| ScriptedLauncher::from_file(path))
+        .transpose()?
+        .map(Arc::new);
+    let menu = match &script {
+        Some(script) => Menu::with_script(script.clone(), icons.clone()),
+        None => Menu::new(launcher_type, icons.clone()),
+    };
 
     let spaces = matches
         .get_one::<String>("spaces")
@@ -125,52 +349,277 @@ async fn main() -> Result<()> {
         .and_then(|s| s.parse::<u64>().ok())
         .unwrap_or(10);
 
+    let connect_retries = *matches.get_one::<u32>("connect_retries").unwrap();
+
+    let connect_retry_delay = matches
+        .get_one::<String>("connect_retry_delay")
+        .and_then(|s| s.parse::<u64>().ok())
+        .ok_or_else(|| {
+            anyhow!("Invalid value for --connect-retry-delay. Must be a positive integer.")
+        })?;
+
+    let connect_timeout = matches
+        .get_one::<String>("connect_timeout")
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&s| s > 0)
+        .ok_or_else(|| {
+            anyhow!("Invalid value for --connect-timeout. Must be a positive integer.")
+        })?;
+
+    let prune_after = matches
+        .get_one::<String>("prune_after")
+        .map(|s| {
+            s.parse::<u64>()
+                .ok()
+                .filter(|&m| m > 0)
+                .and_then(|m| m.checked_mul(60))
+                .map(Duration::from_secs)
+                .ok_or_else(|| {
+                    anyhow!("Invalid value for --prune-after. Must be a positive integer.")
+                })
+        })
+        .transpose()?;
+
     let interactive = matches.get_flag("interactive");
+    let quick_connect = matches.get_flag("quick_connect");
+
+    let values = |id: &str| -> Vec<String> {
+        matches
+            .get_many::<String>(id)
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default()
+    };
+    let filter = DeviceFilter {
+        hide_unnamed: matches.get_flag("hide_unnamed"),
+        min_rssi: matches.get_one::<i16>("min_rssi").copied(),
+        hidden_types: values("hide_type"),
+        hidden_prefixes: values("hide_address"),
+    };
 
-    run_app_loop(
-        &menu,
-        &command_str,
-        &icon_type,
-        spaces,
-        icons,
+    let config = AppConfig {
         scan_duration,
         interactive,
-    )
-    .await?;
+        quick_connect,
+        auto_scan: matches.get_flag("auto_scan"),
+        device_order: *matches.get_one::<DeviceOrder>("device_order").unwrap(),
+        new_device_order: *matches.get_one::<DeviceOrder>("new_device_order").unwrap(),
+        filter,
+        prune_after,
+        state_dir: StateStore::default_dir(),
+        connection_policy: ConnectionPolicy {
+            retries: connect_retries,
+            retry_delay: Duration::from_secs(connect_retry_delay),
+            timeout: Duration::from_secs(connect_timeout),
+        },
+        subscription_output: *matches
+            .get_one::<SubscriptionOutput>("subscription_output")
+            .unwrap(),
+    };
+
+    if let Some(("toggle", sub_matches)) = matches.subcommand() {
+        let device = sub_matches.get_one::<String>("device").unwrap();
+        let app = App::new(icons, config).await?;
+        return app.toggle_device(device).await;
+    }
+
+    if let Some(("info", sub_matches)) = matches.subcommand() {
+        let query = sub_matches.get_one::<String>("device").unwrap();
+        let mut app = App::new(icons, config).await?;
+        let (device, info) = app.device_info(query).await?;
+        let info = info.ok_or_else(|| {
+            anyhow!(
+                "No device information known for {}. Connect it to read it, if it has any.",
+                device.alias
+            )
+        })?;
+
+        println!("{}  {}", device.addr, device.alias);
+        for line in menu.get_device_info_details(&info) {
+            println!("  {line}");
+        }
+        return Ok(());
+    }
+
+    if let Some(("scan", sub_matches)) = matches.subcommand() {
+        let query = sub_matches.get_one::<String>("until").unwrap();
+        let timeout = *sub_matches.get_one::<u64>("timeout").unwrap();
+        let mut app = App::new(icons, config).await?;
+        let device = app
+            .scan_until(query, timeout, sub_matches.get_flag("connect"))
+            .await?;
+        println!("{}  {}", device.addr, device.alias);
+        return Ok(());
+    }
+
+    let open = matches.get_one::<OpenTarget>("open");
+
+    let result = async {
+        let mut app = App::new(icons, config).await?;
+        if let Some(target) = open {
+            app.start_in(target)?;
+        }
+        app.run(&menu, &command_str, &icon_type, spaces).await
+    }
+    .await;
+
+    if let Some(script) = script {
+        for prompt in script.transcript() {
+            eprintln!("{prompt}");
+        }
+    }
+
+    result
+}
+
+fn print_history() {
+    let history = StateStore::new(StateStore::default_dir()).load_history();
+    let now = now();
+    let describe = |time: Option<u64>| time.map_or("-".to_string(), |t| format_elapsed(t, now));
+
+    println!(
+        "{:<17}  {:<24}  {:<16}  {:<17}  {:<16}  BATTERY",
+        "ADDRESS", "ALIAS", "LAST CONNECTED", "LAST DISCONNECTED", "LAST SEEN"
+    );
+    for (addr, entry) in history.recent() {
+        println!(
+            "{addr}  {:<24}  {:<16}  {:<17}  {:<16}  {}",
+            entry.alias,
+            describe(entry.last_connected),
+            describe(entry.last_disconnected),
+            describe(entry.last_seen),
+            entry
+                .last_battery
+                .map_or("-".to_string(), |battery| format!("{battery}%"))
+        );
+    }
+}
+
+async fn print_status(json: bool) -> Result<()> {
+    let session = Arc::new(Session::new().await?);
+    let controller = Controller::new(session).await?;
+    let status = Status::read(&controller).await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
+    let adapter = &status.adapter;
+    println!(
+        "Adapter {} ({}), {}",
+        adapter.address,
+        adapter.name,
+        if adapter.powered { "powered" } else { "off" }
+    );
+    for device in &status.devices {
+        let state = if device.connected {
+            "connected"
+        } else if device.paired {
+            "paired"
+        } else {
+            "discovered"
+        };
+        println!("  {}  {:<24}  {state}", device.address, device.alias);
+        for beacon in &device.beacons {
+            println!("      {}", serde_json::to_string(beacon)?);
+        }
+    }
+
     Ok(())
 }
 
-async fn run_app_loop(
-    menu: &Menu,
-    command_str: &Option<String>,
-    icon_type: &str,
-    spaces: usize,
-    icons: Arc<Icons>,
-    scan_duration: u64,
-    interactive: bool,
-) -> Result<()> {
-    let mut app = App::new(icons.clone(), scan_duration, interactive).await?;
-
-    loop {
-        match app.run(menu, command_str, icon_type, spaces).await {
-            Ok(_) => {
-                if !app.reset_mode {
-                    break;
-                }
+async fn import_windows_keys(hive_path: &Path, dry_run: bool) -> Result<()> {
+    let hive = Hive::open(hive_path)?;
+    let adapters = read_windows_keys(&hive)?;
+
+    let session = Arc::new(Session::new().await?);
+    let controller = Controller::new(session).await?;
+
+    let Some(windows_adapter) = adapters.iter().find(|a| a.adapter == controller.address) else {
+        let found = adapters
+            .iter()
+            .map(|a| a.adapter.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(anyhow!(
+            "No keys for adapter {} in hive (found: {})",
+            controller.address,
+            if found.is_empty() { "none" } else { &found }
+        ));
+    };
+
+    let store = BlueZKeyStore::system();
+    let mut matched = Vec::new();
+
+    println!("Adapter {} ({})", controller.address, controller.name);
+    for keys in &windows_adapter.devices {
+        let paired = controller
+            .paired_devices
+            .iter()
+            .find(|device| device.addr == keys.addr);
+
+        let status = match paired {
+            Some(_) => {
+                matched.push(keys);
+                "paired in BlueZ"
             }
-            Err(err) => {
-                eprintln!("Error during app execution: {err:?}");
-                if !app.reset_mode {
-                    return Err(anyhow!("Fatal error in application: {err}"));
-                }
+            None => "not paired in BlueZ, pair it once from Linux first",
+        };
+
+        println!(
+            "  {}  {:<24}  {:<24}  {status}",
+            keys.addr,
+            paired.map_or("-", |device| device.alias.as_str()),
+            keys.key_types().join(", ")
+        );
+    }
+
+    if dry_run || matched.is_empty() {
+        return Ok(());
+    }
+
+    let mut written = 0;
+    for keys in matched {
+        let info_path = store.info_path(controller.address, keys.addr);
+        let applied = match store.has_device(controller.address, keys.addr) {
+            Ok(true) => store.apply(controller.address, keys),
+            Ok(false) => {
+                eprintln!(
+                    "Skipping {}: {} does not exist",
+                    keys.addr,
+                    info_path.display()
+                );
+                continue;
             }
-        }
+            Err(err) => Err(anyhow::Error::new(err)
+                .context(format!("Failed to access {}", info_path.display()))),
+        };
 
-        if app.reset_mode {
-            app = App::new(icons.clone(), scan_duration, interactive).await?;
-            app.reset_mode = false;
+        match applied {
+            Ok(path) => {
+                println!("Updated {}", path.display());
+                written += 1;
+            }
+            Err(err) if is_permission_denied(&err) => {
+                return Err(anyhow!(
+                    "{err:#}. Run this command as root to write keys to BlueZ storage."
+                ));
+            }
+            Err(err) => eprintln!("Failed to import keys for {}: {err:#}", keys.addr),
         }
     }
 
+    if written > 0 {
+        println!("Imported keys for {written} device(s). Restart bluetoothd to apply them.");
+    }
+
     Ok(())
 }
+
+fn is_permission_denied(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied)
+    })
+}
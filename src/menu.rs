@@ -1,15 +1,30 @@
-use crate::bz::{controller::Controller, device::Device};
+use crate::bz::{
+    advertisement::{flag_names, format_company, format_hex, format_uuid, Advertisement},
+    beacon::{Beacon, SensorReading},
+    controller::Controller,
+    device::Device,
+    device_info::DeviceInfo,
+    gatt::{decode_value, GattCharacteristic, GattService},
+};
+use crate::history::{format_elapsed, DeviceHistory};
 use crate::icons::Icons;
-use crate::launcher::{Launcher, LauncherType};
+use crate::launcher::{Launcher, LauncherType, ScriptedLauncher};
 use anyhow::Result;
 use rust_i18n::t;
 use std::borrow::Cow;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub enum MainMenuOptions {
     Scan,
+    /// Shown instead of [`MainMenuOptions::Scan`] while discovering; picking
+    /// it shows the menu again with the devices found since.
+    Scanning,
     Settings,
+    ManageDevices,
+    /// Lists the discovered devices the filters hid, given their count.
+    ShowHidden(usize),
     Device(String),
 }
 
@@ -17,7 +32,11 @@ impl MainMenuOptions {
     pub fn from_string(option: &str) -> Option<Self> {
         match option {
             s if s == t!("menus.main.options.scan.name") => Some(MainMenuOptions::Scan),
+            s if s == t!("menus.main.options.scanning.name") => Some(MainMenuOptions::Scanning),
             s if s == t!("menus.main.options.settings.name") => Some(MainMenuOptions::Settings),
+            s if s == t!("menus.main.options.manage_devices.name") => {
+                Some(MainMenuOptions::ManageDevices)
+            }
             other => Some(MainMenuOptions::Device(other.to_string())),
         }
     }
@@ -25,18 +44,33 @@ impl MainMenuOptions {
     pub fn to_str(&self) -> Cow<'static, str> {
         match self {
             MainMenuOptions::Scan => t!("menus.main.options.scan.name"),
+            MainMenuOptions::Scanning => t!("menus.main.options.scanning.name"),
             MainMenuOptions::Settings => t!("menus.main.options.settings.name"),
+            MainMenuOptions::ManageDevices => t!("menus.main.options.manage_devices.name"),
+            MainMenuOptions::ShowHidden(count) => {
+                t!("menus.main.options.show_hidden.name", count = count)
+            }
             MainMenuOptions::Device(_) => t!("menus.main.options.device.name"),
         }
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum DeviceListOptions {
+    Device(String),
+    Back,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DeviceMenuOptions {
     Connect,
     Disconnect,
     Trust,
     RevokeTrust,
+    Pin,
+    Unpin,
+    Details,
+    Services,
     Forget,
     Back,
 }
@@ -52,6 +86,10 @@ impl DeviceMenuOptions {
             s if s == t!("menus.device.options.revoke_trust.name") => {
                 Some(DeviceMenuOptions::RevokeTrust)
             }
+            s if s == t!("menus.device.options.pin.name") => Some(DeviceMenuOptions::Pin),
+            s if s == t!("menus.device.options.unpin.name") => Some(DeviceMenuOptions::Unpin),
+            s if s == t!("menus.device.options.details.name") => Some(DeviceMenuOptions::Details),
+            s if s == t!("menus.device.options.services.name") => Some(DeviceMenuOptions::Services),
             s if s == t!("menus.device.options.forget.name") => Some(DeviceMenuOptions::Forget),
             s if s == t!("menus.common.back") => Some(DeviceMenuOptions::Back),
             _ => None,
@@ -64,16 +102,43 @@ impl DeviceMenuOptions {
             DeviceMenuOptions::Disconnect => t!("menus.device.options.disconnect.name"),
             DeviceMenuOptions::Trust => t!("menus.device.options.trust.name"),
             DeviceMenuOptions::RevokeTrust => t!("menus.device.options.revoke_trust.name"),
+            DeviceMenuOptions::Pin => t!("menus.device.options.pin.name"),
+            DeviceMenuOptions::Unpin => t!("menus.device.options.unpin.name"),
+            DeviceMenuOptions::Details => t!("menus.device.options.details.name"),
+            DeviceMenuOptions::Services => t!("menus.device.options.services.name"),
             DeviceMenuOptions::Forget => t!("menus.device.options.forget.name"),
             DeviceMenuOptions::Back => t!("menus.common.back"),
         }
     }
 }
 
+/// Picks in the read-only views of a device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DetailsMenuOptions {
+    Advertisement,
+    /// One of the lines of information.
+    Entry,
+    Back,
+}
+
+/// Picks in the GATT services browser.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GattMenuOptions {
+    /// A service or characteristic, by handle.
+    Open(u16),
+    ReadAgain,
+    WriteValue,
+    Subscribe,
+    /// One of the lines describing a value.
+    Entry,
+    Back,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum SettingsMenuOptions {
     ToggleDiscoverable,
     TogglePairable,
+    ClearDevices,
     DisableAdapter,
     Back,
 }
@@ -87,6 +152,9 @@ impl SettingsMenuOptions {
             s if s == t!("menus.settings.options.toggle_pairable.name") => {
                 Some(SettingsMenuOptions::TogglePairable)
             }
+            s if s == t!("menus.settings.options.clear_devices.name") => {
+                Some(SettingsMenuOptions::ClearDevices)
+            }
             s if s == t!("menus.settings.options.disable_adapter.name") => {
                 Some(SettingsMenuOptions::DisableAdapter)
             }
@@ -103,6 +171,7 @@ impl SettingsMenuOptions {
             SettingsMenuOptions::TogglePairable => {
                 t!("menus.settings.options.toggle_pairable.name")
             }
+            SettingsMenuOptions::ClearDevices => t!("menus.settings.options.clear_devices.name"),
             SettingsMenuOptions::DisableAdapter => {
                 t!("menus.settings.options.disable_adapter.name")
             }
@@ -181,6 +250,7 @@ impl BluetoothMenuOptions {
 pub struct Menu {
     pub launcher_type: LauncherType,
     pub icons: Arc<Icons>,
+    script: Option<Arc<ScriptedLauncher>>,
 }
 
 impl Menu {
@@ -188,6 +258,16 @@ impl Menu {
         Self {
             launcher_type,
             icons,
+            script: None,
+        }
+    }
+
+    /// Builds a menu answered by `script` instead of a launcher process.
+    pub fn with_script(script: Arc<ScriptedLauncher>, icons: Arc<Icons>) -> Self {
+        Self {
+            launcher_type: LauncherType::Script,
+            icons,
+            script: Some(script),
         }
     }
 
@@ -198,6 +278,10 @@ impl Menu {
         icon_type: &str,
         hint: Option<&str>,
     ) -> Result<Option<String>> {
+        if let Some(script) = &self.script {
+            return script.run(input, hint);
+        }
+
         let cmd = Launcher::create_command(&self.launcher_type, launcher_command, icon_type, hint)?;
 
         Launcher::run(cmd, input)
@@ -249,6 +333,15 @@ impl Menu {
 
         let mut status_indicators = String::new();
 
+        if let Some(rssi) = device.rssi {
+            if icon_type == "font" {
+                let signal_icon = self.icons.get_signal_icon(rssi, icon_type);
+                status_indicators.push_str(&format!(" [{signal_icon}]"));
+            } else if icon_type == "xdg" {
+                status_indicators.push_str(&format!(" [{rssi} dBm]"));
+            }
+        }
+
         if let Some(battery) = device.battery_percentage {
             if icon_type == "font" {
                 if let Some(battery_icon) = self.icons.get_battery_icon(battery, icon_type) {
@@ -282,11 +375,21 @@ impl Menu {
         controller: &Controller,
         icon_type: &str,
         spaces: usize,
+        quick_connect: bool,
+        hidden_devices: usize,
     ) -> Result<Option<MainMenuOptions>> {
-        let scan_text = MainMenuOptions::Scan.to_str();
+        let scanning = controller.is_scanning.load(Ordering::Relaxed);
+        let (scan_option, scan_icon) = if scanning {
+            (MainMenuOptions::Scanning, "scan_in_progress")
+        } else {
+            (MainMenuOptions::Scan, "scan")
+        };
+        let scan_text = scan_option.to_str();
         let settings_text = MainMenuOptions::Settings.to_str();
+        let manage_devices_text = MainMenuOptions::ManageDevices.to_str();
+        let show_hidden_text = MainMenuOptions::ShowHidden(hidden_devices).to_str();
 
-        let options_start = vec![("scan", scan_text.as_ref())];
+        let options_start = vec![(scan_icon, scan_text.as_ref())];
         let mut input = self.get_icon_text(options_start, icon_type, spaces);
 
         for device in &controller.paired_devices {
@@ -299,7 +402,19 @@ impl Menu {
             input.push_str(&format!("\n{device_display}"));
         }
 
-        let options_end = vec![("settings", settings_text.as_ref())];
+        if hidden_devices > 0 {
+            let show_hidden_input = self.get_icon_text(
+                vec![("show_hidden", show_hidden_text.as_ref())],
+                icon_type,
+                spaces,
+            );
+            input.push_str(&format!("\n{show_hidden_input}"));
+        }
+
+        let mut options_end = vec![("settings", settings_text.as_ref())];
+        if quick_connect {
+            options_end.insert(0, ("device", manage_devices_text.as_ref()));
+        }
         let settings_input = self.get_icon_text(options_end, icon_type, spaces);
         input.push_str(&format!("\n{settings_input}"));
 
@@ -309,9 +424,13 @@ impl Menu {
             let cleaned_output = self.clean_menu_output(&output, icon_type);
 
             if cleaned_output == scan_text.as_ref() {
-                return Ok(Some(MainMenuOptions::Scan));
+                return Ok(Some(scan_option));
             } else if cleaned_output == settings_text.as_ref() {
                 return Ok(Some(MainMenuOptions::Settings));
+            } else if quick_connect && cleaned_output == manage_devices_text.as_ref() {
+                return Ok(Some(MainMenuOptions::ManageDevices));
+            } else if hidden_devices > 0 && cleaned_output == show_hidden_text.as_ref() {
+                return Ok(Some(MainMenuOptions::ShowHidden(hidden_devices)));
             } else {
                 return Ok(Some(MainMenuOptions::Device(cleaned_output)));
             }
@@ -320,6 +439,45 @@ impl Menu {
         Ok(None)
     }
 
+    pub async fn show_device_list(
+        &self,
+        launcher_command: &Option<String>,
+        controller: &Controller,
+        icon_type: &str,
+        spaces: usize,
+        interactive: bool,
+    ) -> Result<Option<DeviceListOptions>> {
+        let mut input = controller
+            .paired_devices
+            .iter()
+            .chain(&controller.new_devices)
+            .map(|device| self.format_device_display(device, icon_type, spaces))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let back_text = t!("menus.common.back");
+        if !interactive {
+            let back_input = self.get_icon_text(vec![("back", &back_text)], icon_type, spaces);
+            input.push_str(&format!("\n{back_input}"));
+        }
+
+        let hint = MainMenuOptions::ManageDevices.to_str();
+
+        let menu_output =
+            self.run_launcher(launcher_command, Some(&input), icon_type, Some(&hint))?;
+
+        if let Some(output) = menu_output {
+            let cleaned_output = self.clean_menu_output(&output, icon_type);
+
+            if !interactive && cleaned_output == back_text.as_ref() {
+                return Ok(Some(DeviceListOptions::Back));
+            }
+            return Ok(Some(DeviceListOptions::Device(cleaned_output)));
+        }
+
+        Ok(None)
+    }
+
     pub async fn show_device_options(
         &self,
         launcher_command: &Option<String>,
@@ -337,6 +495,10 @@ impl Menu {
                 DeviceMenuOptions::Disconnect => "disconnect",
                 DeviceMenuOptions::Trust => "trust",
                 DeviceMenuOptions::RevokeTrust => "revoke_trust",
+                DeviceMenuOptions::Pin => "pin",
+                DeviceMenuOptions::Unpin => "unpin",
+                DeviceMenuOptions::Details => "details",
+                DeviceMenuOptions::Services => "services",
                 DeviceMenuOptions::Forget => "forget",
                 DeviceMenuOptions::Back => "back",
             };
@@ -365,7 +527,12 @@ impl Menu {
         Ok(None)
     }
 
-    pub fn get_paired_device_options(&self, device: &Device) -> Vec<DeviceMenuOptions> {
+    pub fn get_paired_device_options(
+        &self,
+        device: &Device,
+        pinned: bool,
+        has_gatt_services: bool,
+    ) -> Vec<DeviceMenuOptions> {
         let mut options = Vec::new();
 
         if device.is_connected {
@@ -380,11 +547,451 @@ impl Menu {
             options.push(DeviceMenuOptions::Trust);
         }
 
+        if pinned {
+            options.push(DeviceMenuOptions::Unpin);
+        } else {
+            options.push(DeviceMenuOptions::Pin);
+        }
+
+        options.push(DeviceMenuOptions::Details);
+        if has_gatt_services {
+            options.push(DeviceMenuOptions::Services);
+        }
         options.push(DeviceMenuOptions::Forget);
 
         options
     }
 
+    pub fn get_device_details(
+        &self,
+        device: &Device,
+        history: Option<&DeviceHistory>,
+        beacons: &[Beacon],
+        now: u64,
+    ) -> Vec<String> {
+        let history = history.cloned().unwrap_or_default();
+        let mut details = vec![t!("menus.details.address", address = device.addr).to_string()];
+
+        let connection = if device.is_connected {
+            t!("menus.details.connected")
+        } else if let Some(then) = history.last_connected {
+            t!(
+                "menus.details.last_connected",
+                when = format_elapsed(then, now)
+            )
+        } else {
+            t!("menus.details.never_connected")
+        };
+        details.push(connection.to_string());
+
+        if let Some(battery) = device.battery_percentage {
+            details.push(t!("menus.details.battery", percentage = battery).to_string());
+        } else if let Some(battery) = history.last_battery {
+            details.push(t!("menus.details.last_battery", percentage = battery).to_string());
+        }
+
+        if let Some(rssi) = device.rssi {
+            details.push(t!("menus.details.signal", rssi = rssi).to_string());
+        }
+
+        if let Some(tx_power) = device.tx_power {
+            details.push(t!("menus.details.tx_power", tx_power = tx_power).to_string());
+        }
+
+        details.extend(beacons.iter().map(format_beacon));
+
+        if let Some(info) = &history.device_info {
+            details.extend(self.get_device_info_details(info));
+        }
+
+        if let Some(then) = history.first_seen {
+            details
+                .push(t!("menus.details.first_seen", when = format_elapsed(then, now)).to_string());
+        }
+
+        if let Some(then) = history.last_seen {
+            details
+                .push(t!("menus.details.last_seen", when = format_elapsed(then, now)).to_string());
+        }
+
+        details
+    }
+
+    /// Lines for each Device Information value a device reported.
+    pub fn get_device_info_details(&self, info: &DeviceInfo) -> Vec<String> {
+        let pnp_id = info.pnp_id.map(|id| id.to_string());
+        let values = [
+            ("menus.details.manufacturer", info.manufacturer.as_deref()),
+            ("menus.details.model", info.model.as_deref()),
+            ("menus.details.serial_number", info.serial_number.as_deref()),
+            (
+                "menus.details.firmware_revision",
+                info.firmware_revision.as_deref(),
+            ),
+            (
+                "menus.details.hardware_revision",
+                info.hardware_revision.as_deref(),
+            ),
+            (
+                "menus.details.software_revision",
+                info.software_revision.as_deref(),
+            ),
+            ("menus.details.pnp_id", pnp_id.as_deref()),
+        ];
+
+        values
+            .iter()
+            .filter_map(|(key, value)| value.map(|value| t!(*key, value = value).to_string()))
+            .collect()
+    }
+
+    /// Lines describing the latest advertisement of a device.
+    pub fn get_advertisement_details(&self, advertisement: &Advertisement) -> Vec<String> {
+        if advertisement.is_empty() {
+            return vec![t!("menus.advertisement.empty").to_string()];
+        }
+
+        let mut details = Vec::new();
+
+        for (company, data) in &advertisement.manufacturer_data {
+            details.push(
+                t!(
+                    "menus.advertisement.manufacturer",
+                    company = format_company(*company),
+                    data = format_hex(data, " ")
+                )
+                .to_string(),
+            );
+        }
+
+        for (uuid, data) in &advertisement.service_data {
+            details.push(
+                t!(
+                    "menus.advertisement.service",
+                    uuid = format_uuid(uuid),
+                    data = format_hex(data, " ")
+                )
+                .to_string(),
+            );
+        }
+
+        if let Some(flags) = advertisement.flags {
+            let mut names = flag_names(flags).join(", ");
+            if names.is_empty() {
+                names = format!("0x{flags:02X}");
+            }
+            details.push(t!("menus.advertisement.flags", flags = names).to_string());
+        }
+
+        if let Some(tx_power) = advertisement.tx_power {
+            details.push(t!("menus.details.tx_power", tx_power = tx_power).to_string());
+        }
+
+        details
+    }
+
+    /// Shows `details` as a read-only list, followed by an entry opening the
+    /// advertisement of the device. Picking a line goes back.
+    pub fn show_device_details(
+        &self,
+        launcher_command: &Option<String>,
+        details: &[String],
+        device_name: &str,
+        icon_type: &str,
+        spaces: usize,
+        interactive: bool,
+    ) -> Result<Option<DetailsMenuOptions>> {
+        let advertisement_text = t!("menus.details.advertisement");
+        let back_text = t!("menus.common.back");
+
+        let mut options = vec![("advertisement", advertisement_text.as_ref())];
+        if !interactive {
+            options.push(("back", back_text.as_ref()));
+        }
+        let input = format!(
+            "{}\n{}",
+            details.join("\n"),
+            self.get_icon_text(options, icon_type, spaces)
+        );
+
+        let hint = t!("menus.details.hint", device_name = device_name);
+
+        let menu_output =
+            self.run_launcher(launcher_command, Some(&input), icon_type, Some(&hint))?;
+
+        Ok(menu_output.map(|output| {
+            if self.clean_menu_output(&output, icon_type) == advertisement_text.as_ref() {
+                DetailsMenuOptions::Advertisement
+            } else {
+                DetailsMenuOptions::Back
+            }
+        }))
+    }
+
+    /// Shows the advertisement `details` of a device. Picking a line shows
+    /// them again, updated.
+    pub fn show_advertisement(
+        &self,
+        launcher_command: &Option<String>,
+        details: &[String],
+        device_name: &str,
+        icon_type: &str,
+        spaces: usize,
+        interactive: bool,
+    ) -> Result<Option<DetailsMenuOptions>> {
+        let mut input = details.join("\n");
+
+        let back_text = t!("menus.common.back");
+        if !interactive {
+            let back_input = self.get_icon_text(vec![("back", &back_text)], icon_type, spaces);
+            input.push_str(&format!("\n{back_input}"));
+        }
+
+        let hint = t!("menus.advertisement.hint", device_name = device_name);
+
+        let menu_output =
+            self.run_launcher(launcher_command, Some(&input), icon_type, Some(&hint))?;
+
+        Ok(menu_output.map(|output| {
+            if !interactive && self.clean_menu_output(&output, icon_type) == back_text.as_ref() {
+                DetailsMenuOptions::Back
+            } else {
+                DetailsMenuOptions::Entry
+            }
+        }))
+    }
+
+    /// Lists the primary services of a device by name.
+    pub fn show_services(
+        &self,
+        launcher_command: &Option<String>,
+        services: &[GattService],
+        device_name: &str,
+        icon_type: &str,
+        spaces: usize,
+        interactive: bool,
+    ) -> Result<Option<GattMenuOptions>> {
+        let entries = services
+            .iter()
+            .map(|service| (service.id, service.name()))
+            .collect();
+        let hint = t!("menus.services.hint", device_name = device_name);
+
+        self.show_gatt_list(
+            launcher_command,
+            unique_labels(entries, t!("menus.services.empty")),
+            &hint,
+            icon_type,
+            spaces,
+            interactive,
+        )
+    }
+
+    /// Lists the characteristics of a service along with their flags.
+    pub fn show_characteristics(
+        &self,
+        launcher_command: &Option<String>,
+        characteristics: &[GattCharacteristic],
+        service_name: &str,
+        icon_type: &str,
+        spaces: usize,
+        interactive: bool,
+    ) -> Result<Option<GattMenuOptions>> {
+        let entries = characteristics
+            .iter()
+            .map(|characteristic| {
+                let label = format!(
+                    "{}: {}",
+                    characteristic.name(),
+                    characteristic.flag_names().join(", ")
+                );
+                (characteristic.id, label)
+            })
+            .collect();
+        let hint = t!("menus.service.hint", service = service_name);
+
+        self.show_gatt_list(
+            launcher_command,
+            unique_labels(entries, t!("menus.service.empty")),
+            &hint,
+            icon_type,
+            spaces,
+            interactive,
+        )
+    }
+
+    /// Shows `entries`, where picking one with a handle opens it.
+    fn show_gatt_list(
+        &self,
+        launcher_command: &Option<String>,
+        entries: Vec<(Option<u16>, String)>,
+        hint: &str,
+        icon_type: &str,
+        spaces: usize,
+        interactive: bool,
+    ) -> Result<Option<GattMenuOptions>> {
+        let mut input = entries
+            .iter()
+            .map(|(_, label)| label.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let back_text = t!("menus.common.back");
+        if !interactive {
+            let back_input = self.get_icon_text(vec![("back", &back_text)], icon_type, spaces);
+            input.push_str(&format!("\n{back_input}"));
+        }
+
+        let menu_output =
+            self.run_launcher(launcher_command, Some(&input), icon_type, Some(hint))?;
+
+        Ok(menu_output.map(|output| {
+            let cleaned_output = self.clean_menu_output(&output, icon_type);
+            if !interactive && cleaned_output == back_text.as_ref() {
+                return GattMenuOptions::Back;
+            }
+            entries
+                .iter()
+                .find(|(_, label)| self.clean_menu_output(label, icon_type) == cleaned_output)
+                .and_then(|(id, _)| *id)
+                .map_or(GattMenuOptions::Entry, GattMenuOptions::Open)
+        }))
+    }
+
+    /// Lines describing a characteristic: its value, when it was read, in
+    /// each of the ways it decodes, then its flags.
+    pub fn get_characteristic_details(
+        &self,
+        characteristic: &GattCharacteristic,
+        value: Option<&[u8]>,
+    ) -> Vec<String> {
+        let mut details = Vec::new();
+
+        if let Some(value) = value {
+            if value.is_empty() {
+                details.push(t!("menus.characteristic.empty_value").to_string());
+            } else {
+                let decoded = decode_value(value);
+                details.push(t!("menus.characteristic.hex", value = decoded.hex).to_string());
+                if let Some(text) = decoded.text {
+                    details.push(t!("menus.characteristic.text", value = text).to_string());
+                }
+                if let Some(integer) = decoded.integer {
+                    details.push(t!("menus.characteristic.integer", value = integer).to_string());
+                }
+            }
+        }
+
+        details.push(
+            t!(
+                "menus.characteristic.flags",
+                flags = characteristic.flag_names().join(", ")
+            )
+            .to_string(),
+        );
+
+        details
+    }
+
+    /// Shows the `details` of a characteristic, followed by the operations
+    /// it supports.
+    pub fn show_characteristic(
+        &self,
+        launcher_command: &Option<String>,
+        details: &[String],
+        characteristic: &GattCharacteristic,
+        icon_type: &str,
+        spaces: usize,
+        interactive: bool,
+    ) -> Result<Option<GattMenuOptions>> {
+        let read_again_text = t!("menus.characteristic.options.read_again.name");
+        let write_value_text = t!("menus.characteristic.options.write_value.name");
+        let subscribe_text = t!("menus.characteristic.options.subscribe.name");
+        let back_text = t!("menus.common.back");
+
+        let mut options = Vec::new();
+        if characteristic.is_readable() {
+            options.push(("read_again", read_again_text.as_ref()));
+        }
+        if characteristic.is_writable() {
+            options.push(("write_value", write_value_text.as_ref()));
+        }
+        if characteristic.is_subscribable() {
+            options.push(("subscribe", subscribe_text.as_ref()));
+        }
+        if !interactive {
+            options.push(("back", back_text.as_ref()));
+        }
+
+        let mut input = details.join("\n");
+        if !options.is_empty() {
+            input.push_str(&format!(
+                "\n{}",
+                self.get_icon_text(options, icon_type, spaces)
+            ));
+        }
+
+        let hint = characteristic.name();
+
+        let menu_output =
+            self.run_launcher(launcher_command, Some(&input), icon_type, Some(&hint))?;
+
+        Ok(menu_output.map(|output| {
+            let cleaned_output = self.clean_menu_output(&output, icon_type);
+            if cleaned_output == read_again_text.as_ref() {
+                GattMenuOptions::ReadAgain
+            } else if cleaned_output == write_value_text.as_ref() {
+                GattMenuOptions::WriteValue
+            } else if cleaned_output == subscribe_text.as_ref() {
+                GattMenuOptions::Subscribe
+            } else if !interactive && cleaned_output == back_text.as_ref() {
+                GattMenuOptions::Back
+            } else {
+                GattMenuOptions::Entry
+            }
+        }))
+    }
+
+    /// Asks for a value to write to a characteristic, typed into the
+    /// launcher.
+    pub fn prompt_value(
+        &self,
+        launcher_command: &Option<String>,
+        characteristic_name: &str,
+        icon_type: &str,
+    ) -> Result<Option<String>> {
+        let hint = t!(
+            "menus.characteristic.write_hint",
+            characteristic = characteristic_name
+        );
+
+        let menu_output = self.run_launcher(launcher_command, None, icon_type, Some(&hint))?;
+
+        Ok(menu_output
+            .map(|output| output.trim().to_string())
+            .filter(|value| !value.is_empty()))
+    }
+
+    /// Shown while values of a characteristic are streamed, until the user
+    /// stops watching it or dismisses the launcher.
+    pub fn show_subscription(
+        &self,
+        launcher_command: &Option<String>,
+        characteristic_name: &str,
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<()> {
+        let stop_text = t!("menus.subscription.stop");
+        let input = self.get_icon_text(vec![("stop", &stop_text)], icon_type, spaces);
+        let hint = t!(
+            "menus.subscription.hint",
+            characteristic = characteristic_name
+        );
+
+        self.run_launcher(launcher_command, Some(&input), icon_type, Some(&hint))?;
+        Ok(())
+    }
+
     pub async fn show_settings_menu(
         &self,
         launcher_command: &Option<String>,
@@ -417,12 +1024,14 @@ impl Menu {
             )
         };
 
+        let clear_devices_text = SettingsMenuOptions::ClearDevices.to_str();
         let disable_adapter_text = t!("menus.settings.options.disable_adapter.name");
         let back_text = t!("menus.common.back");
 
         let mut options = vec![
             (discoverable_icon, discoverable_text.as_ref()),
             (pairable_icon, pairable_text.as_ref()),
+            ("clear_devices", clear_devices_text.as_ref()),
             ("disable_adapter", disable_adapter_text.as_ref()),
         ];
 
@@ -441,6 +1050,8 @@ impl Menu {
                 return Ok(Some(SettingsMenuOptions::ToggleDiscoverable));
             } else if cleaned_output == pairable_text.as_ref() {
                 return Ok(Some(SettingsMenuOptions::TogglePairable));
+            } else if cleaned_output == clear_devices_text.as_ref() {
+                return Ok(Some(SettingsMenuOptions::ClearDevices));
             } else if cleaned_output == disable_adapter_text.as_ref() {
                 return Ok(Some(SettingsMenuOptions::DisableAdapter));
             } else if cleaned_output == back_text.as_ref() {
@@ -473,6 +1084,34 @@ impl Menu {
         None
     }
 
+    pub fn prompt_repair(
+        &self,
+        launcher_command: &Option<String>,
+        device_name: &str,
+        icon_type: &str,
+        spaces: usize,
+    ) -> Result<bool> {
+        let hint = t!("menus.device.repair.hint", device_name = device_name);
+        let repair_text = t!("menus.device.repair.confirm");
+
+        let options = vec![
+            ("forget", repair_text.clone()),
+            ("back", t!("menus.bluetooth.cancel")),
+        ];
+
+        let input = self.get_icon_text(options, icon_type, spaces);
+
+        let menu_output =
+            self.run_launcher(launcher_command, Some(&input), icon_type, Some(&hint))?;
+
+        if let Some(output) = menu_output {
+            let cleaned_output = self.clean_menu_output(&output, icon_type);
+            return Ok(cleaned_output == repair_text);
+        }
+
+        Ok(false)
+    }
+
     pub fn prompt_passkey_confirmation(
         &self,
         launcher_command: &Option<String>,
@@ -504,3 +1143,94 @@ impl Menu {
         Ok(false)
     }
 }
+
+/// Pairs each label with its handle, telling apart identical labels by their
+/// handle, or stands `empty` in when there are none.
+fn unique_labels(
+    entries: Vec<(u16, String)>,
+    empty: Cow<'static, str>,
+) -> Vec<(Option<u16>, String)> {
+    if entries.is_empty() {
+        return vec![(None, empty.to_string())];
+    }
+
+    entries
+        .iter()
+        .map(|(id, label)| {
+            let duplicated = entries.iter().filter(|(_, other)| other == label).count() > 1;
+            let label = if duplicated {
+                format!("{label} [0x{id:04X}]")
+            } else {
+                label.clone()
+            };
+            (Some(*id), label)
+        })
+        .collect()
+}
+
+fn format_beacon(beacon: &Beacon) -> String {
+    match beacon {
+        Beacon::IBeacon {
+            uuid, major, minor, ..
+        } => t!(
+            "menus.details.ibeacon",
+            uuid = uuid,
+            major = major,
+            minor = minor
+        ),
+        Beacon::EddystoneUid {
+            namespace,
+            instance,
+            ..
+        } => t!(
+            "menus.details.eddystone_uid",
+            namespace = namespace,
+            instance = instance
+        ),
+        Beacon::EddystoneUrl { url, .. } => t!("menus.details.eddystone_url", url = url),
+        Beacon::EddystoneTlm {
+            battery_mv,
+            temperature,
+            ..
+        } => {
+            let reading = SensorReading {
+                temperature: *temperature,
+                battery_mv: *battery_mv,
+                ..Default::default()
+            };
+            t!(
+                "menus.details.eddystone_tlm",
+                readings = format_readings(&reading)
+            )
+        }
+        Beacon::Sensor { source, reading } => t!(
+            "menus.details.sensor",
+            source = source.name(),
+            readings = format_readings(reading)
+        ),
+    }
+    .to_string()
+}
+
+fn format_readings(reading: &SensorReading) -> String {
+    let mut readings = Vec::new();
+    if let Some(value) = reading.temperature {
+        readings.push(t!(
+            "menus.details.readings.temperature",
+            value = format!("{value:.1}")
+        ));
+    }
+    if let Some(value) = reading.humidity {
+        readings.push(t!(
+            "menus.details.readings.humidity",
+            value = format!("{value:.1}")
+        ));
+    }
+    if let Some(value) = reading.battery {
+        readings.push(t!("menus.details.readings.battery", value = value));
+    }
+    if let Some(value) = reading.battery_mv {
+        readings.push(t!("menus.details.readings.battery_mv", value = value));
+    }
+    readings.join(", ")
+}
@@ -0,0 +1,118 @@
+use bluer::Address;
+use std::str::FromStr;
+
+/// A menu the app can show. The top of the [`Navigation`] stack is the one on
+/// screen; going back reveals the menu it was opened from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuState {
+    Main,
+    Settings,
+    /// Every known device, each opening its submenu. Reached from the main
+    /// menu in quick-connect mode, where selecting a device toggles it.
+    Devices,
+    Device(Address),
+    /// Read-only information about a device, including its history.
+    Details(Address),
+    /// Latest advertisement of a device, read again each time it is shown.
+    Advertisement(Address),
+    /// Primary GATT services of a connected LE device.
+    Services(Address),
+    /// Characteristics of a service, by handle.
+    Service(Address, u16),
+    /// A characteristic, by service and characteristic handle.
+    Characteristic(Address, u16, u16),
+    Adapter,
+    /// Runs a scan, then shows the main menu in its place.
+    Scan,
+}
+
+/// Menu to start in, as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpenTarget {
+    Main,
+    Settings,
+    Scan,
+    /// Device address or alias, resolved once devices are known.
+    Device(String),
+}
+
+impl FromStr for OpenTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "main" => Ok(OpenTarget::Main),
+            "settings" => Ok(OpenTarget::Settings),
+            "scan" => Ok(OpenTarget::Scan),
+            _ => match s.strip_prefix("device:") {
+                Some(device) if !device.trim().is_empty() => {
+                    Ok(OpenTarget::Device(device.trim().to_string()))
+                }
+                _ => Err(format!(
+                    "expected main, settings, scan or device:<addr|alias>, got '{s}'"
+                )),
+            },
+        }
+    }
+}
+
+/// What a menu asks the navigation stack to do once it returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transition {
+    /// Show the same menu again.
+    Stay,
+    /// Open a submenu on top of the current one.
+    Push(MenuState),
+    /// Swap the current menu for another at the same depth.
+    Replace(MenuState),
+    /// Return to the previous menu, exiting once the stack is empty.
+    Back,
+    /// Leave the app from any depth.
+    Exit,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Navigation {
+    stack: Vec<MenuState>,
+}
+
+impl Navigation {
+    pub fn new(root: MenuState) -> Self {
+        Self { stack: vec![root] }
+    }
+
+    pub fn current(&self) -> Option<&MenuState> {
+        self.stack.last()
+    }
+
+    pub fn stack(&self) -> &[MenuState] {
+        &self.stack
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    pub fn push(&mut self, state: MenuState) {
+        self.stack.push(state);
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    pub fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::Stay => {}
+            Transition::Push(state) => self.stack.push(state),
+            Transition::Replace(state) => {
+                self.stack.pop();
+                self.stack.push(state);
+            }
+            Transition::Back => {
+                self.stack.pop();
+            }
+            Transition::Exit => self.stack.clear(),
+        }
+    }
+}
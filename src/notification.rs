@@ -1,5 +1,7 @@
 use anyhow::{anyhow, Result};
-use notify_rust::{CloseReason, Hint, Notification, NotificationHandle, Timeout};
+use notify_rust::{
+    handle_action, ActionResponse, CloseReason, Hint, Notification, NotificationHandle, Timeout,
+};
 use std::{
     collections::HashMap,
     sync::{
@@ -15,6 +17,9 @@ use crate::icons::Icons;
 pub struct NotificationManager {
     icons: Arc<Icons>,
     handles: Arc<Mutex<HashMap<u32, NotificationHandle>>>,
+    /// Cancellable notifications a thread is still waiting on, closed
+    /// before being replaced so that the thread ends.
+    cancellable: Arc<Mutex<HashMap<u32, NotificationHandle>>>,
 }
 
 impl PairingConfirmationHandler for NotificationManager {
@@ -34,6 +39,7 @@ impl Clone for NotificationManager {
         Self {
             icons: Arc::clone(&self.icons),
             handles: Arc::clone(&self.handles),
+            cancellable: Arc::clone(&self.cancellable),
         }
     }
 }
@@ -43,6 +49,7 @@ impl NotificationManager {
         Self {
             icons,
             handles: Arc::new(Mutex::new(HashMap::new())),
+            cancellable: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -67,7 +74,9 @@ impl NotificationManager {
             .icon(&icon_name)
             .timeout(timeout.unwrap_or(Timeout::Milliseconds(3000)));
 
-        if let Some(notification_id) = id {
+        // A cancellable notification is closed instead, as replacing it
+        // would leave its thread waiting.
+        if let Some(notification_id) = id.filter(|&id| !self.close_cancellable(id)) {
             notification.id(notification_id);
         }
 
@@ -140,6 +149,140 @@ impl NotificationManager {
         }
     }
 
+    /// Shows a notification offering `actions`, given as `(key, label)`
+    /// pairs, and calls `on_action` with the key of the one picked.
+    pub fn send_action_notification(
+        &self,
+        body: String,
+        icon: Option<&str>,
+        actions: &[(String, String)],
+        id: Option<u32>,
+        on_action: impl FnOnce(&str) + Send + 'static,
+    ) -> Result<u32> {
+        let mut notification = Notification::new();
+        notification
+            .summary("BlueZ Menu")
+            .body(&body)
+            .icon(&self.icons.get_xdg_icon(icon.unwrap_or("bluetooth")))
+            .timeout(Timeout::Milliseconds(10000));
+
+        for (key, label) in actions {
+            notification.action(key, label);
+        }
+
+        if let Some(notification_id) = id {
+            notification.id(notification_id);
+        }
+
+        let handle = notification.show()?;
+        let id = handle.id();
+
+        spawn(move || {
+            handle.wait_for_action(|action| {
+                if action != "__closed" {
+                    on_action(action);
+                }
+            });
+        });
+
+        Ok(id)
+    }
+
+    pub fn send_cancellable_notification(
+        &self,
+        body: String,
+        icon: Option<&str>,
+        id: Option<u32>,
+        on_cancel: impl FnOnce() + Send + 'static,
+    ) -> Result<u32> {
+        let mut notification = self.build_cancellable_notification(&body, icon);
+        if let Some(notification_id) = id {
+            notification.id(notification_id);
+        }
+
+        let handle = notification.show()?;
+        let id = handle.id();
+        self.track_cancellable(handle);
+
+        let cancellable = self.cancellable.clone();
+        spawn(move || {
+            // Already replaced before the thread got to run.
+            if !cancellable
+                .lock()
+                .is_ok_and(|cancellable| cancellable.contains_key(&id))
+            {
+                return;
+            }
+
+            // Ends once the action is invoked or the notification closed,
+            // including by `close_cancellable`.
+            handle_action(id, |response| {
+                // Closing the notification leaves the operation running.
+                if let ActionResponse::Custom("cancel") = response {
+                    on_cancel();
+                }
+            });
+            if let Ok(mut cancellable) = cancellable.lock() {
+                cancellable.remove(&id);
+            }
+        });
+
+        Ok(id)
+    }
+
+    pub fn update_cancellable_notification(
+        &self,
+        id: u32,
+        body: String,
+        icon: Option<&str>,
+    ) -> Result<()> {
+        let handle = self
+            .build_cancellable_notification(&body, icon)
+            .id(id)
+            .show()?;
+        self.track_cancellable(handle);
+        Ok(())
+    }
+
+    fn track_cancellable(&self, handle: NotificationHandle) {
+        if let Ok(mut cancellable) = self.cancellable.lock() {
+            cancellable.insert(handle.id(), handle);
+        }
+    }
+
+    /// Closes the cancellable notification `id` if one is still waited on,
+    /// and returns whether it did.
+    fn close_cancellable(&self, id: u32) -> bool {
+        let handle = self
+            .cancellable
+            .lock()
+            .ok()
+            .and_then(|mut cancellable| cancellable.remove(&id));
+
+        match handle {
+            Some(handle) => {
+                handle.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn build_cancellable_notification(&self, body: &str, icon: Option<&str>) -> Notification {
+        let cancel_text = t!("menus.bluetooth.cancel");
+
+        let mut notification = Notification::new();
+        notification
+            .summary("BlueZ Menu")
+            .body(body)
+            .icon(&self.icons.get_xdg_icon(icon.unwrap_or("bluetooth")))
+            .timeout(Timeout::Never)
+            .hint(Hint::Transient(true))
+            .hint(Hint::Category("progress".to_string()))
+            .action("cancel", &cancel_text);
+        notification
+    }
+
     // TODO: Follow https://github.com/hoodie/notify-rust/issues/199
     // "Allow an on_close handler without consuming the NotificationHandle"
     // This would simplify our implementation by avoiding the need for a separate thread
@@ -148,12 +291,12 @@ impl NotificationManager {
         &self,
         duration_sec: u64,
         on_cancel: impl FnOnce() + Send + 'static,
-        progress_body: String,
+        progress_body: impl Fn() -> String + Send + 'static,
         progress_icon: Option<&str>,
     ) -> Result<u32> {
         let notification_handle = Notification::new()
             .summary("BlueZ Menu")
-            .body(&progress_body)
+            .body(&progress_body())
             .icon(
                 &self
                     .icons
@@ -168,7 +311,6 @@ impl NotificationManager {
         let id = notification_handle.id();
 
         let notification_manager = self.clone();
-        let progress_body_clone = progress_body.clone();
         let progress_icon_str = progress_icon.map(String::from);
 
         spawn(move || {
@@ -177,7 +319,7 @@ impl NotificationManager {
                 duration_sec,
                 notification_handle,
                 on_cancel,
-                progress_body_clone,
+                progress_body,
                 progress_icon_str.as_deref(),
             );
         });
@@ -191,7 +333,7 @@ impl NotificationManager {
         duration_sec: u64,
         notification_handle: NotificationHandle,
         on_cancel: impl FnOnce() + Send + 'static,
-        progress_body: String,
+        progress_body: impl Fn() -> String,
         progress_icon: Option<&str>,
     ) {
         let start_time = std::time::Instant::now();
@@ -229,7 +371,7 @@ impl NotificationManager {
             let update_result = Notification::new()
                 .id(id)
                 .summary("BlueZ Menu")
-                .body(&progress_body)
+                .body(&progress_body())
                 .icon(
                     &self
                         .icons
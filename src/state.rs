@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use bluer::Address;
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{bz::device::Device, history::History};
+
+/// How devices are ordered in the menus. Pinned devices always come first,
+/// in the order they were pinned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DeviceOrder {
+    /// Connected devices, then the most recently connected ones.
+    #[default]
+    Recent,
+    /// Alphabetically by alias.
+    Name,
+    /// By hardware address.
+    Address,
+    /// Strongest signal first, as last reported during discovery.
+    Signal,
+}
+
+/// Persistent per-user state, kept as JSON under `$XDG_STATE_HOME/bzmenu`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub pinned: Vec<Address>,
+}
+
+impl State {
+    pub fn is_pinned(&self, addr: Address) -> bool {
+        self.pinned.contains(&addr)
+    }
+
+    pub fn set_pinned(&mut self, addr: Address, pinned: bool) {
+        self.pinned.retain(|&a| a != addr);
+        if pinned {
+            self.pinned.push(addr);
+        }
+    }
+
+    pub fn sort_devices(&self, devices: &mut [Device], order: DeviceOrder, history: &History) {
+        let pin_rank = |device: &Device| {
+            self.pinned
+                .iter()
+                .position(|&a| a == device.addr)
+                .unwrap_or(usize::MAX)
+        };
+
+        match order {
+            DeviceOrder::Recent => devices.sort_by_key(|device| {
+                (
+                    pin_rank(device),
+                    !device.is_connected,
+                    Reverse(history.last_connected(device.addr)),
+                    device.addr,
+                )
+            }),
+            DeviceOrder::Name => devices
+                .sort_by_key(|device| (pin_rank(device), device.alias.to_lowercase(), device.addr)),
+            DeviceOrder::Address => devices.sort_by_key(|device| (pin_rank(device), device.addr)),
+            DeviceOrder::Signal => {
+                devices.sort_by_key(|device| (pin_rank(device), Reverse(device.rssi), device.addr))
+            }
+        }
+    }
+}
+
+/// Loads and saves the JSON files kept in the state directory. Without a
+/// directory, everything only lives in memory.
+#[derive(Debug, Clone, Default)]
+pub struct StateStore {
+    dir: Option<PathBuf>,
+}
+
+impl StateStore {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    /// `$XDG_STATE_HOME/bzmenu`, falling back to `~/.local/state/bzmenu`.
+    pub fn default_dir() -> Option<PathBuf> {
+        env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+            .map(|dir| dir.join("bzmenu"))
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn load_state(&self) -> State {
+        self.load("state.json")
+    }
+
+    pub fn save_state(&self, state: &State) -> Result<()> {
+        self.save("state.json", state)
+    }
+
+    pub fn load_history(&self) -> History {
+        self.load("history.json")
+    }
+
+    pub fn save_history(&self, history: &History) -> Result<()> {
+        self.save("history.json", history)
+    }
+
+    /// Missing or unreadable files yield an empty value rather than an
+    /// error, so a corrupt file never keeps the menu from opening.
+    fn load<T: DeserializeOwned + Default>(&self, name: &str) -> T {
+        let Some(dir) = &self.dir else {
+            return T::default();
+        };
+        let path = dir.join(name);
+
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("Ignoring invalid state file {}: {e}", path.display());
+                T::default()
+            }),
+            Err(_) => T::default(),
+        }
+    }
+
+    fn save<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let path = dir.join(name);
+        let tmp = path.with_extension("json.tmp");
+        let content = serde_json::to_string_pretty(value)?;
+        fs::write(&tmp, content).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(())
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,92 @@
+use bluer::Address;
+use serde::Serialize;
+
+use crate::bz::{
+    advertisement::Advertisement,
+    beacon::{decode, Beacon},
+    controller::Controller,
+    device::Device,
+};
+
+/// Snapshot of the adapter and its devices, as printed by `bzmenu status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub adapter: AdapterStatus,
+    pub devices: Vec<DeviceStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdapterStatus {
+    pub name: String,
+    pub address: Address,
+    pub alias: String,
+    pub powered: bool,
+    pub pairable: bool,
+    pub discoverable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceStatus {
+    pub address: Address,
+    pub alias: String,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub device_type: String,
+    pub paired: bool,
+    pub trusted: bool,
+    pub connected: bool,
+    pub battery: Option<u8>,
+    pub rssi: Option<i16>,
+    pub tx_power: Option<i16>,
+    /// Broadcasts decoded from the latest advertisement.
+    pub beacons: Vec<Beacon>,
+}
+
+impl Status {
+    /// Reads the advertisement of every device known to `controller`,
+    /// paired ones first.
+    pub async fn read(controller: &Controller) -> Self {
+        let mut devices = Vec::new();
+        for device in controller
+            .paired_devices
+            .iter()
+            .chain(&controller.new_devices)
+        {
+            let beacons = match controller.adapter.device(device.addr) {
+                Ok(backend) => decode(&Advertisement::read(backend.as_ref()).await),
+                Err(_) => Vec::new(),
+            };
+            devices.push(DeviceStatus::new(device, beacons));
+        }
+
+        Self {
+            adapter: AdapterStatus {
+                name: controller.name.clone(),
+                address: controller.address,
+                alias: controller.alias.clone(),
+                powered: controller.is_powered,
+                pairable: controller.is_pairable,
+                discoverable: controller.is_discoverable,
+            },
+            devices,
+        }
+    }
+}
+
+impl DeviceStatus {
+    fn new(device: &Device, beacons: Vec<Beacon>) -> Self {
+        Self {
+            address: device.addr,
+            alias: device.alias.clone(),
+            name: device.name.clone(),
+            device_type: device.device_type.clone(),
+            paired: device.is_paired,
+            trusted: device.is_trusted,
+            connected: device.is_connected,
+            battery: device.battery_percentage,
+            rssi: device.rssi,
+            tx_power: device.tx_power,
+            beacons,
+        }
+    }
+}
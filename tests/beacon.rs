@@ -0,0 +1,278 @@
+use bluer::{Address, Uuid};
+use bzmenu::{
+    bz::{
+        advertisement::Advertisement,
+        backend::AdapterBackend,
+        beacon::{
+            decode, decode_bthome, decode_eddystone, decode_ibeacon, decode_mibeacon,
+            decode_thermometer, Beacon, SensorReading, SensorSource,
+        },
+        controller::Controller,
+        fake::{FakeAdapter, FakeDevice},
+    },
+    status::Status,
+};
+use std::sync::Arc;
+
+const ADAPTER: Address = Address::new([0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]);
+const THERMOMETER: Address = Address::new([0xa4, 0xc1, 0x38, 0x8e, 0x3f, 0x4b]);
+const BEACON: Address = Address::new([0xf0, 0x00, 0x00, 0x00, 0x00, 0x03]);
+
+const IBEACON: [u8; 23] = [
+    0x02, 0x15, 0xe2, 0xc5, 0x6d, 0xb5, 0xdf, 0xfb, 0x48, 0xd2, 0xb0, 0x60, 0xd0, 0xf5, 0xa7, 0x10,
+    0x96, 0xe0, 0x00, 0x01, 0x00, 0x02, 0xc5,
+];
+/// BTHome v2: packet id, battery 100%, 25.06 °C and 50.55% humidity.
+const BTHOME: [u8; 11] = [
+    0x40, 0x00, 0x5a, 0x01, 0x64, 0x02, 0xca, 0x09, 0x03, 0xbf, 0x13,
+];
+/// LYWSDCGQ broadcasting 22.3 °C and 54.6% humidity.
+const MIBEACON: [u8; 18] = [
+    0x50, 0x20, 0xaa, 0x01, 0xda, 0x8e, 0x3f, 0x4b, 0x34, 0x2d, 0x58, 0x0d, 0x10, 0x04, 0xdf, 0x00,
+    0x22, 0x02,
+];
+
+fn uuid(short: u16) -> Uuid {
+    format!("0000{short:04x}-0000-1000-8000-00805f9b34fb")
+        .parse()
+        .unwrap()
+}
+
+fn sensor(source: SensorSource, reading: SensorReading) -> Option<Beacon> {
+    Some(Beacon::Sensor { source, reading })
+}
+
+#[test]
+fn decodes_ibeacon() {
+    assert_eq!(
+        decode_ibeacon(&IBEACON),
+        Some(Beacon::IBeacon {
+            uuid: "e2c56db5-dffb-48d2-b060-d0f5a71096e0".parse().unwrap(),
+            major: 1,
+            minor: 2,
+            measured_power: -59,
+        })
+    );
+    assert_eq!(decode_ibeacon(&IBEACON[..22]), None);
+    // Other Apple broadcasts, such as Continuity ones, are not beacons.
+    assert_eq!(
+        decode_ibeacon(&[0x10, 0x05, 0x01, 0x18, 0x2b, 0x4e, 0x1c]),
+        None
+    );
+}
+
+#[test]
+fn decodes_eddystone_frames() {
+    let uid = [
+        0x00, 0xe7, 0xed, 0xd1, 0xeb, 0xea, 0xc0, 0x4e, 0x5d, 0xef, 0xa0, 0x17, 0x0b, 0xdb, 0x87,
+        0x53, 0x9b, 0x67, 0x00, 0x00,
+    ];
+    assert_eq!(
+        decode_eddystone(&uid),
+        Some(Beacon::EddystoneUid {
+            namespace: "EDD1EBEAC04E5DEFA017".to_string(),
+            instance: "0BDB87539B67".to_string(),
+            tx_power: -25,
+        })
+    );
+
+    let url = [
+        0x10, 0xeb, 0x01, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x00, 0x62, 0x7a,
+    ];
+    assert_eq!(
+        decode_eddystone(&url),
+        Some(Beacon::EddystoneUrl {
+            url: "https://www.example.com/bz".to_string(),
+            tx_power: -21,
+        })
+    );
+    assert_eq!(decode_eddystone(&[0x10, 0xeb, 0x04, 0x61]), None);
+
+    let tlm = [
+        0x20, 0x00, 0x0b, 0xb8, 0x17, 0x80, 0x00, 0x00, 0x01, 0xf4, 0x00, 0x00, 0x27, 0x10,
+    ];
+    assert_eq!(
+        decode_eddystone(&tlm),
+        Some(Beacon::EddystoneTlm {
+            battery_mv: Some(3000),
+            temperature: Some(23.5),
+            advertisements: 500,
+            uptime: 1000,
+        })
+    );
+
+    // Unsupported battery and temperature readings.
+    let tlm = [
+        0x20, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x0a,
+    ];
+    assert!(matches!(
+        decode_eddystone(&tlm),
+        Some(Beacon::EddystoneTlm {
+            battery_mv: None,
+            temperature: None,
+            ..
+        })
+    ));
+    // Encrypted telemetry.
+    assert_eq!(decode_eddystone(&[0x20, 0x01, 0x00, 0x00]), None);
+}
+
+#[test]
+fn decodes_bthome() {
+    assert_eq!(
+        decode_bthome(&BTHOME),
+        sensor(
+            SensorSource::BtHome,
+            SensorReading {
+                temperature: Some(25.06),
+                humidity: Some(50.55),
+                battery: Some(100),
+                battery_mv: None,
+            }
+        )
+    );
+
+    // Objects past one of unknown size are skipped.
+    let reading = [0x40, 0x01, 0x55, 0x7f, 0x00, 0x02, 0xca, 0x09];
+    assert_eq!(
+        decode_bthome(&reading),
+        sensor(
+            SensorSource::BtHome,
+            SensorReading {
+                battery: Some(0x55),
+                ..Default::default()
+            }
+        )
+    );
+
+    let encrypted = [0x41, 0x01, 0x64];
+    assert_eq!(decode_bthome(&encrypted), None);
+}
+
+#[test]
+fn decodes_xiaomi_thermometers() {
+    assert_eq!(
+        decode_mibeacon(&MIBEACON),
+        sensor(
+            SensorSource::MiBeacon,
+            SensorReading {
+                temperature: Some(22.3),
+                humidity: Some(54.6),
+                ..Default::default()
+            }
+        )
+    );
+
+    let battery = [
+        0x50, 0x20, 0xaa, 0x01, 0xdb, 0x8e, 0x3f, 0x4b, 0x34, 0x2d, 0x58, 0x0a, 0x10, 0x01, 0x5d,
+    ];
+    assert_eq!(
+        decode_mibeacon(&battery),
+        sensor(
+            SensorSource::MiBeacon,
+            SensorReading {
+                battery: Some(93),
+                ..Default::default()
+            }
+        )
+    );
+
+    let encrypted = [
+        0x58, 0x20, 0xaa, 0x01, 0xdc, 0x0d, 0x10, 0x04, 0xdf, 0x00, 0x22, 0x02,
+    ];
+    assert_eq!(decode_mibeacon(&encrypted), None);
+
+    let atc1441 = [
+        0xa4, 0xc1, 0x38, 0x8e, 0x3f, 0x4b, 0x00, 0xea, 0x33, 0x5c, 0x0b, 0x8a, 0x2f,
+    ];
+    assert_eq!(
+        decode_thermometer(&atc1441),
+        sensor(
+            SensorSource::Atc1441,
+            SensorReading {
+                temperature: Some(23.4),
+                humidity: Some(51.0),
+                battery: Some(92),
+                battery_mv: Some(2954),
+            }
+        )
+    );
+
+    let pvvx = [
+        0x4b, 0x3f, 0x8e, 0x38, 0xc1, 0xa4, 0x58, 0x09, 0x88, 0x13, 0x86, 0x0b, 0x55, 0x10, 0x04,
+    ];
+    assert_eq!(
+        decode_thermometer(&pvvx),
+        sensor(
+            SensorSource::Pvvx,
+            SensorReading {
+                temperature: Some(23.92),
+                humidity: Some(50.0),
+                battery: Some(85),
+                battery_mv: Some(2950),
+            }
+        )
+    );
+}
+
+#[test]
+fn decodes_every_known_broadcast() {
+    let mut advertisement = Advertisement::default();
+    advertisement
+        .manufacturer_data
+        .insert(0x004c, IBEACON.to_vec());
+    advertisement
+        .manufacturer_data
+        .insert(0x0059, vec![0x01, 0x02]);
+    advertisement
+        .service_data
+        .insert(uuid(0xfcd2), BTHOME.to_vec());
+    advertisement.service_data.insert(uuid(0x180f), vec![0x64]);
+
+    let beacons = decode(&advertisement);
+    assert_eq!(beacons.len(), 2);
+    assert!(matches!(beacons[0], Beacon::IBeacon { major: 1, .. }));
+    assert!(matches!(
+        beacons[1],
+        Beacon::Sensor {
+            source: SensorSource::BtHome,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn status_lists_decoded_beacons_as_json() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(THERMOMETER, "LYWSDCGQ").service_data(uuid(0xfe95), &MIBEACON))
+        .with_device(FakeDevice::new(BEACON, "Beacon").manufacturer_data(0x004c, &IBEACON));
+    let backend: Arc<dyn AdapterBackend> = Arc::new(adapter);
+    let controller = Controller::with_backend(backend).await.unwrap();
+
+    let status = serde_json::to_value(Status::read(&controller).await).unwrap();
+
+    assert_eq!(status["adapter"]["address"], "00:1A:7D:DA:71:13");
+    let devices = status["devices"].as_array().unwrap();
+    let thermometer = devices
+        .iter()
+        .find(|device| device["alias"] == "LYWSDCGQ")
+        .unwrap();
+    assert_eq!(
+        thermometer["beacons"],
+        serde_json::json!([{
+            "format": "sensor",
+            "source": "mibeacon",
+            "temperature": 22.3f32,
+            "humidity": 54.6f32,
+        }])
+    );
+    let beacon = devices
+        .iter()
+        .find(|device| device["alias"] == "Beacon")
+        .unwrap();
+    assert_eq!(beacon["beacons"][0]["format"], "ibeacon");
+    assert_eq!(
+        beacon["beacons"][0]["uuid"],
+        "e2c56db5-dffb-48d2-b060-d0f5a71096e0"
+    );
+}
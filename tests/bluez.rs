@@ -0,0 +1,311 @@
+mod support;
+
+use anyhow::Result;
+use bluer::{Address, Session};
+use bzmenu::bz::{
+    agent::AgentManager,
+    controller::Controller,
+    device::Device,
+    error::BluetoothError,
+    pairing::{ConnectionPolicy, PairingConfirmationHandler, PairingManager},
+    scanner::Scanner,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use support::bluez::{self, Bluez, DeviceState, State};
+
+const ADAPTER: &str = "00:1A:7D:DA:71:13";
+const HEADPHONES: &str = "C0:FF:EE:12:34:56";
+const KEYBOARD: &str = "D0:C0:FF:EE:00:01";
+
+fn addr(s: &str) -> Address {
+    s.parse().unwrap()
+}
+
+fn state() -> State {
+    let mut headphones = DeviceState::new(HEADPHONES, "Headphones");
+    headphones.paired = true;
+    headphones.trusted = true;
+    headphones.class = Some(0x240418);
+    headphones.battery = Some(70);
+
+    let mut keyboard = DeviceState::new(KEYBOARD, "Keyboard");
+    keyboard.icon = Some("input-keyboard".to_string());
+
+    State::new(ADAPTER)
+        .with_device(headphones)
+        .with_device(keyboard)
+}
+
+async fn controller() -> (Arc<Session>, Controller) {
+    let session = Arc::new(Session::new().await.unwrap());
+    let controller = Controller::new(session.clone()).await.unwrap();
+    (session, controller)
+}
+
+fn pairing_manager(controller: &Controller) -> PairingManager {
+    PairingManager::new(
+        controller.adapter.clone(),
+        ConnectionPolicy {
+            retries: 2,
+            retry_delay: Duration::from_millis(10),
+            timeout: Duration::from_secs(10),
+        },
+    )
+}
+
+async fn wait_until(bluez: &Bluez, condition: impl Fn(&State) -> bool) {
+    for _ in 0..200 {
+        if condition(&bluez.state()) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("stand-in never reached the expected state");
+}
+
+/// Answers every confirmation request, from another thread like the
+/// notification based handler does.
+struct AutoConfirm {
+    accept: bool,
+    passkeys: Mutex<Vec<String>>,
+}
+
+impl PairingConfirmationHandler for AutoConfirm {
+    fn request_confirmation(
+        &self,
+        _device_address: &str,
+        passkey: &str,
+        on_confirm: Box<dyn FnOnce() + Send>,
+        on_reject: Box<dyn FnOnce() + Send>,
+    ) -> Result<()> {
+        self.passkeys.lock().unwrap().push(passkey.to_string());
+        let accept = self.accept;
+        std::thread::spawn(move || if accept { on_confirm() } else { on_reject() });
+        Ok(())
+    }
+}
+
+#[test]
+fn controller_reads_adapter_and_devices() {
+    bluez::run(state(), |_| async {
+        let (_session, controller) = controller().await;
+
+        assert_eq!(controller.name, "hci0");
+        assert_eq!(controller.address, addr(ADAPTER));
+        assert_eq!(controller.alias, "stand-in");
+        assert!(controller.is_powered);
+
+        assert_eq!(controller.paired_devices.len(), 1);
+        let headphones = &controller.paired_devices[0];
+        assert_eq!(headphones.addr, addr(HEADPHONES));
+        assert_eq!(headphones.device_type, "headphones");
+        assert_eq!(headphones.battery_percentage, Some(70));
+        assert!(headphones.is_trusted);
+
+        assert_eq!(controller.new_devices.len(), 1);
+        let keyboard = &controller.new_devices[0];
+        assert_eq!(keyboard.device_type, "keyboard");
+        assert_eq!(keyboard.battery_percentage, None);
+    });
+}
+
+#[test]
+fn classic_devices_have_no_gatt_services() {
+    let mut headphones = DeviceState::new(HEADPHONES, "Headphones");
+    headphones.paired = true;
+    headphones.connected = true;
+
+    // BlueZ reports a public address type for classic devices too.
+    bluez::run(State::new(ADAPTER).with_device(headphones), |_| async {
+        let (_session, controller) = controller().await;
+
+        let headphones = &controller.paired_devices[0];
+        assert!(headphones.is_connected);
+        assert!(!headphones.has_gatt_services().await);
+    });
+}
+
+#[test]
+fn controller_changes_adapter_settings() {
+    bluez::run(state(), |bluez| async move {
+        let (_session, mut controller) = controller().await;
+
+        controller.set_discoverable(true).await.unwrap();
+        controller.set_pairable(false).await.unwrap();
+        controller.power_off().await.unwrap();
+        controller.refresh().await.unwrap();
+
+        assert!(controller.is_discoverable);
+        assert!(!controller.is_pairable);
+        assert!(!controller.is_powered);
+        assert!(!bluez.state().adapter.powered);
+    });
+}
+
+#[test]
+fn scanner_discovers_new_devices() {
+    let mut speaker = DeviceState::new("E0:00:00:00:00:02", "Speaker");
+    speaker.hidden = true;
+
+    bluez::run(state().with_device(speaker), |bluez| async move {
+        let (_session, mut controller) = controller().await;
+        assert_eq!(controller.new_devices.len(), 1);
+
+        let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+        scanner.start_discovery(1).await.unwrap();
+
+        assert!(bluez.state().discovery_filter_set);
+        assert!(bluez.state().adapter.discovering);
+
+        scanner.wait_for_discovery_completion().await.unwrap();
+        wait_until(&bluez, |state| !state.adapter.discovering).await;
+
+        controller.refresh().await.unwrap();
+        assert_eq!(controller.new_devices.len(), 2);
+        assert!(controller.new_devices.iter().any(|d| d.alias == "Speaker"));
+    });
+}
+
+#[test]
+fn agent_confirms_passkey_while_pairing() {
+    let mut state = state();
+    state.devices.get_mut(KEYBOARD).unwrap().passkey = Some(42);
+
+    bluez::run(state, |bluez| async move {
+        let (session, controller) = controller().await;
+        let handler = Arc::new(AutoConfirm {
+            accept: true,
+            passkeys: Mutex::new(Vec::new()),
+        });
+        let _agent = AgentManager::new(session, handler.clone()).await.unwrap();
+
+        {
+            let state = bluez.state();
+            assert_eq!(state.agents.len(), 1);
+            assert_eq!(state.default_agent.as_ref(), Some(&state.agents[0]));
+        }
+
+        let keyboard = Device::new(&controller.adapter, &addr(KEYBOARD))
+            .await
+            .unwrap();
+        pairing_manager(&controller)
+            .pair_device(&keyboard)
+            .await
+            .unwrap();
+
+        assert_eq!(*handler.passkeys.lock().unwrap(), vec!["000042"]);
+        assert!(bluez.device(KEYBOARD).unwrap().paired);
+    });
+}
+
+#[test]
+fn rejected_passkey_fails_pairing() {
+    let mut state = state();
+    state.devices.get_mut(KEYBOARD).unwrap().passkey = Some(42);
+
+    bluez::run(state, |bluez| async move {
+        let (session, controller) = controller().await;
+        let handler = Arc::new(AutoConfirm {
+            accept: false,
+            passkeys: Mutex::new(Vec::new()),
+        });
+        let _agent = AgentManager::new(session, handler).await.unwrap();
+
+        let keyboard = Device::new(&controller.adapter, &addr(KEYBOARD))
+            .await
+            .unwrap();
+        let err = pairing_manager(&controller)
+            .pair_device(&keyboard)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            BluetoothError::classify(&err),
+            BluetoothError::AuthenticationRejected
+        );
+        assert!(!bluez.device(KEYBOARD).unwrap().paired);
+    });
+}
+
+#[test]
+fn connect_retries_page_timeouts() {
+    let mut state = state();
+    state
+        .devices
+        .get_mut(HEADPHONES)
+        .unwrap()
+        .connect_errors
+        .push_back(("Failed", "br-connection-page-timeout"));
+
+    bluez::run(state, |bluez| async move {
+        let (_session, controller) = controller().await;
+        let headphones = Device::new(&controller.adapter, &addr(HEADPHONES))
+            .await
+            .unwrap();
+
+        pairing_manager(&controller)
+            .connect_device(&headphones, |_, _| {})
+            .await
+            .unwrap();
+
+        let device = bluez.device(HEADPHONES).unwrap();
+        assert_eq!(device.connect_calls, 2);
+        assert!(device.connected);
+    });
+}
+
+#[test]
+fn connect_reports_authentication_errors() {
+    let mut state = state();
+    state
+        .devices
+        .get_mut(HEADPHONES)
+        .unwrap()
+        .connect_errors
+        .push_back(("AuthenticationFailed", "Authentication Failed"));
+
+    bluez::run(state, |bluez| async move {
+        let (_session, controller) = controller().await;
+        let headphones = Device::new(&controller.adapter, &addr(HEADPHONES))
+            .await
+            .unwrap();
+
+        let err = pairing_manager(&controller)
+            .connect_device(&headphones, |_, _| {})
+            .await
+            .unwrap_err();
+
+        assert!(BluetoothError::classify(&err).is_authentication_error());
+        assert_eq!(bluez.device(HEADPHONES).unwrap().connect_calls, 1);
+    });
+}
+
+#[test]
+fn disconnect_trust_and_forget() {
+    let mut state = state();
+    state.devices.get_mut(HEADPHONES).unwrap().connected = true;
+
+    bluez::run(state, |bluez| async move {
+        let (_session, mut controller) = controller().await;
+        let manager = pairing_manager(&controller);
+        let headphones = Device::new(&controller.adapter, &addr(HEADPHONES))
+            .await
+            .unwrap();
+        assert!(headphones.is_connected);
+
+        manager.disconnect_device(&headphones).await.unwrap();
+        assert!(!bluez.device(HEADPHONES).unwrap().connected);
+
+        headphones.set_trusted(false).await.unwrap();
+        assert!(!bluez.device(HEADPHONES).unwrap().trusted);
+
+        manager.forget_device(&headphones).await.unwrap();
+        assert!(bluez.device(HEADPHONES).is_none());
+
+        controller.refresh().await.unwrap();
+        assert!(controller.paired_devices.is_empty());
+    });
+}
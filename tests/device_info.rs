@@ -0,0 +1,114 @@
+use bluer::{gatt::CharacteristicFlags, Address};
+use bzmenu::bz::{
+    backend::AdapterBackend,
+    device::Device,
+    device_info::{DeviceInfo, PnpId},
+    fake::{FakeAdapter, FakeDevice},
+};
+use std::sync::Arc;
+
+const ADAPTER: Address = Address::new([0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]);
+const SENSOR: Address = Address::new([0xe0, 0x00, 0x00, 0x00, 0x00, 0x02]);
+
+fn uuid(number: u16) -> bluer::Uuid {
+    format!("0000{number:04x}-0000-1000-8000-00805f9b34fb")
+        .parse()
+        .unwrap()
+}
+
+async fn read(device: FakeDevice) -> Option<DeviceInfo> {
+    let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(device);
+    let backend: Arc<dyn AdapterBackend> = Arc::new(adapter);
+    let device = Device::new(&backend, &SENSOR).await.unwrap();
+    DeviceInfo::read(&device).await.unwrap()
+}
+
+#[test]
+fn pnp_id_names_its_vendor() {
+    let usb = PnpId::parse(&[0x02, 0x6d, 0x04, 0x2b, 0xc5, 0x10, 0x00]).unwrap();
+    assert_eq!(
+        usb,
+        PnpId {
+            vendor_id_source: 2,
+            vendor_id: 0x046d,
+            product_id: 0xc52b,
+            product_version: 0x0010,
+        }
+    );
+    assert_eq!(
+        usb.to_string(),
+        "USB 0x046D, product 0xC52B, version 0x0010"
+    );
+
+    let sig = PnpId::parse(&[0x01, 0x4c, 0x00, 0x00, 0x00, 0x00, 0x00]).unwrap();
+    assert!(sig.to_string().starts_with("Apple, Inc. (0x004C)"));
+
+    assert_eq!(PnpId::parse(&[0x01, 0x4c, 0x00]), None);
+}
+
+#[tokio::test]
+async fn reads_device_information_service() {
+    let read_only = CharacteristicFlags {
+        read: true,
+        ..Default::default()
+    };
+    let info = read(
+        FakeDevice::new(SENSOR, "Sensor")
+            .connected(true)
+            .gatt_service(0x0010, uuid(0x180a))
+            .gatt_characteristic(0x0010, 0x0011, uuid(0x2a24), read_only, b"X1\0\0")
+            .gatt_characteristic(0x0010, 0x0012, uuid(0x2a28), read_only, b"")
+            .gatt_characteristic(0x0010, 0x0013, uuid(0x2a23), read_only, &[0; 8]),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        info,
+        DeviceInfo {
+            model: Some("X1".to_string()),
+            ..Default::default()
+        }
+    );
+}
+
+#[tokio::test]
+async fn devices_without_the_service_have_no_information() {
+    let info = read(
+        FakeDevice::new(SENSOR, "Sensor")
+            .connected(true)
+            .gatt_service(0x0020, uuid(0x180f)),
+    )
+    .await;
+
+    assert_eq!(info, None);
+}
+
+#[tokio::test]
+async fn service_is_offered_once_listed_by_a_connected_device() {
+    let offered = |device: FakeDevice| async move {
+        let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(device);
+        let backend: Arc<dyn AdapterBackend> = Arc::new(adapter);
+        let device = Device::new(&backend, &SENSOR).await.unwrap();
+        DeviceInfo::is_offered(&device).await
+    };
+
+    assert!(
+        offered(
+            FakeDevice::new(SENSOR, "Sensor")
+                .connected(true)
+                .uuids([uuid(0x180a)])
+        )
+        .await
+    );
+    assert!(!offered(FakeDevice::new(SENSOR, "Sensor").uuids([uuid(0x180a)])).await);
+    // Classic devices list profiles, not GATT services.
+    assert!(
+        !offered(
+            FakeDevice::new(SENSOR, "Headphones")
+                .connected(true)
+                .uuids([uuid(0x110b)])
+        )
+        .await
+    );
+}
@@ -0,0 +1,437 @@
+use bluer::{gatt::CharacteristicFlags, Address, ErrorKind};
+use bzmenu::{
+    bz::{
+        advertisement::Advertisement,
+        backend::AdapterBackend,
+        controller::Controller,
+        device::Device,
+        error::BluetoothError,
+        fake::{FakeAdapter, FakeDevice, FakeOutcome},
+        pairing::{ConnectionPolicy, PairingManager},
+        scanner::{ScanEvent, ScanState, Scanner},
+        subscription::Subscription,
+    },
+    history::History,
+    state::{DeviceOrder, State},
+};
+use std::{
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::Duration,
+};
+
+const ADAPTER: Address = Address::new([0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]);
+const HEADPHONES: Address = Address::new([0xc0, 0xff, 0xee, 0x12, 0x34, 0x56]);
+const KEYBOARD: Address = Address::new([0xd0, 0xc0, 0xff, 0xee, 0x00, 0x01]);
+const SPEAKER: Address = Address::new([0xe0, 0x00, 0x00, 0x00, 0x00, 0x02]);
+
+fn backend(adapter: &FakeAdapter) -> Arc<dyn AdapterBackend> {
+    Arc::new(adapter.clone())
+}
+
+#[tokio::test]
+async fn controller_splits_paired_and_new_devices() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connected(true)
+                .battery(80),
+        )
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard"));
+
+    let controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+
+    assert_eq!(controller.name, "hci0");
+    assert_eq!(controller.address, ADAPTER);
+    assert!(controller.is_powered);
+
+    assert_eq!(controller.paired_devices.len(), 1);
+    let headphones = &controller.paired_devices[0];
+    assert_eq!(headphones.alias, "Headphones");
+    assert!(headphones.is_connected);
+    assert_eq!(headphones.battery_percentage, Some(80));
+
+    assert_eq!(controller.new_devices.len(), 1);
+    assert_eq!(controller.new_devices[0].addr, KEYBOARD);
+}
+
+#[tokio::test]
+async fn controller_tracks_adapter_settings() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER).powered(false);
+    let mut controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+    assert!(!controller.is_powered);
+
+    controller.power_on().await.unwrap();
+    controller.set_discoverable(true).await.unwrap();
+    controller.set_pairable(false).await.unwrap();
+    controller.refresh().await.unwrap();
+
+    assert!(controller.is_powered);
+    assert!(controller.is_discoverable);
+    assert!(!controller.is_pairable);
+}
+
+#[tokio::test]
+async fn device_type_comes_from_class_then_appearance_then_icon() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(HEADPHONES, "Headphones").class(0x240418))
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard").appearance(961))
+        .with_device(FakeDevice::new(SPEAKER, "Speaker").icon("audio-card"));
+    let adapter = backend(&adapter);
+
+    let device_type = |addr| {
+        let adapter = adapter.clone();
+        async move { Device::new(&adapter, &addr).await.unwrap().device_type }
+    };
+
+    assert_eq!(device_type(HEADPHONES).await, "headphones");
+    assert_eq!(device_type(KEYBOARD).await, "keyboard");
+    assert_eq!(device_type(SPEAKER).await, "speaker");
+}
+
+#[tokio::test]
+async fn scanner_finds_devices_during_discovery() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_discoverable_device(FakeDevice::new(SPEAKER, "Speaker"));
+    let mut controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+    assert!(controller.new_devices.is_empty());
+
+    let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+    assert!(scanner.discover_device(SPEAKER, 1).await.unwrap());
+    assert!(!adapter.is_discovering_now());
+
+    controller.refresh().await.unwrap();
+    assert_eq!(controller.new_devices.len(), 1);
+    assert_eq!(controller.new_devices[0].alias, "Speaker");
+}
+
+#[tokio::test]
+async fn scanner_leaves_running_discovery_alone() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_discoverable_device(FakeDevice::new(SPEAKER, "Speaker").rssi(-60));
+    let controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+
+    let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+    scanner.start_discovery(30).await.unwrap();
+    assert!(scanner.discover_device(SPEAKER, 1).await.unwrap());
+    assert!(scanner
+        .discover_matching("Speaker", 1)
+        .await
+        .unwrap()
+        .is_some());
+    assert!(adapter.is_discovering_now());
+
+    scanner.stop_discovery().await.unwrap();
+    assert!(!adapter.is_discovering_now());
+}
+
+#[tokio::test]
+async fn scanner_keeps_signal_once_discovery_stops() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_discoverable_device(FakeDevice::new(KEYBOARD, "Keyboard").rssi(-85))
+        .with_discoverable_device(FakeDevice::new(SPEAKER, "Speaker").rssi(-60).tx_power(4));
+    let mut controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+
+    let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+    scanner.start_discovery(1).await.unwrap();
+    scanner.wait_for_discovery_completion().await.unwrap();
+
+    // BlueZ no longer reports a signal once discovery stopped.
+    controller.refresh().await.unwrap();
+    assert!(controller.new_devices.iter().all(|d| d.rssi.is_none()));
+
+    scanner.apply_signals(&mut controller.new_devices);
+    State::default().sort_devices(
+        &mut controller.new_devices,
+        DeviceOrder::Signal,
+        &History::default(),
+    );
+    let signals: Vec<_> = controller
+        .new_devices
+        .iter()
+        .map(|d| (d.addr, d.rssi, d.tx_power))
+        .collect();
+    assert_eq!(
+        signals,
+        [(SPEAKER, Some(-60), Some(4)), (KEYBOARD, Some(-85), None)]
+    );
+}
+
+#[tokio::test]
+async fn scanner_waits_for_matching_device_in_range() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(KEYBOARD, "JBL Keyboard"))
+        .with_discoverable_device(FakeDevice::new(SPEAKER, "JBL Flip 5"));
+    let controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+    let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+
+    // The keyboard was known before scanning and never reported a signal.
+    let found = scanner.discover_matching("jbl*", 2).await.unwrap().unwrap();
+    assert_eq!(found.addr, SPEAKER);
+    assert!(found.matches("jbl fl?p *"));
+    assert!(found.matches("e0:00:00:00:00:02"));
+    assert!(found.matches("E0:00:*"));
+    assert!(!found.matches("flip"));
+
+    assert!(scanner
+        .discover_matching("Keyboard", 1)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn scanner_reports_events_until_cancelled() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_discoverable_device(FakeDevice::new(SPEAKER, "Speaker"));
+    let controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+    let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+    assert_eq!(*scanner.subscribe().borrow(), ScanState::Idle);
+
+    let mut events = scanner.events();
+    scanner.start_discovery(60).await.unwrap();
+    assert_eq!(
+        events.recv().await.unwrap(),
+        ScanEvent::DeviceAdded(SPEAKER)
+    );
+
+    adapter.add_device(FakeDevice::new(KEYBOARD, "Keyboard"));
+    assert_eq!(
+        events.recv().await.unwrap(),
+        ScanEvent::DeviceAdded(KEYBOARD)
+    );
+    adapter.remove_device(SPEAKER).await.unwrap();
+    assert_eq!(
+        events.recv().await.unwrap(),
+        ScanEvent::DeviceRemoved(SPEAKER)
+    );
+
+    // Cancelling needs no runtime, as from a notification callback.
+    let token = scanner.cancellation_token();
+    std::thread::spawn(move || token.cancel()).join().unwrap();
+
+    assert_eq!(
+        scanner.wait_for_discovery_completion().await.unwrap(),
+        ScanState::Cancelled
+    );
+    assert!(!adapter.is_discovering_now());
+    assert!(!controller.is_scanning.load(Ordering::Relaxed));
+}
+
+#[tokio::test]
+async fn scanner_tallies_devices_found() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_discoverable_device(FakeDevice::new(SPEAKER, "Speaker").class(0x240414))
+        .with_discoverable_device(FakeDevice::new(KEYBOARD, "Keyboard"));
+    let controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+    let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+
+    let mut events = scanner.events();
+    scanner.start_discovery(60).await.unwrap();
+    for _ in 0..2 {
+        events.recv().await.unwrap();
+    }
+    adapter.add_device(FakeDevice::new(HEADPHONES, "Beacon").unnamed());
+    events.recv().await.unwrap();
+
+    let tally = scanner.tally();
+    assert_eq!((tally.len(), tally.audio()), (3, 1));
+    let newest: Vec<_> = tally.newest_named(3).iter().map(|d| d.addr).collect();
+    assert_eq!(newest, [KEYBOARD, SPEAKER]);
+
+    adapter.remove_device(SPEAKER).await.unwrap();
+    events.recv().await.unwrap();
+    scanner.stop_discovery().await.unwrap();
+
+    // The tally of the latest run is kept once it stopped.
+    let tally = scanner.tally();
+    assert_eq!((tally.len(), tally.audio()), (2, 0));
+}
+
+#[tokio::test]
+async fn scanner_waits_for_advertisement_changes() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(SPEAKER, "Sensor").manufacturer_data(0x0969, &[0x01]));
+    let controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+    let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+    let device = controller.adapter.device(SPEAKER).unwrap();
+    let shown = Advertisement::read(device.as_ref()).await;
+    let wait = Duration::from_secs(5);
+
+    // Nothing is received without discovery.
+    assert!(tokio::time::timeout(
+        Duration::from_millis(100),
+        scanner.wait_for_advertisement_change(SPEAKER, &shown)
+    )
+    .await
+    .is_err());
+
+    scanner.start_discovery(60).await.unwrap();
+    let fake = adapter.fake_device(SPEAKER).unwrap();
+    fake.advertise_manufacturer_data(0x0969, &[0x02]);
+    tokio::time::timeout(wait, scanner.wait_for_advertisement_change(SPEAKER, &shown))
+        .await
+        .unwrap();
+
+    // The end of discovery is a change too.
+    let shown = Advertisement::read(device.as_ref()).await;
+    let token = scanner.cancellation_token();
+    let changed = scanner.wait_for_advertisement_change(SPEAKER, &shown);
+    tokio::pin!(changed);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), &mut changed)
+            .await
+            .is_err()
+    );
+    token.cancel();
+    tokio::time::timeout(wait, changed).await.unwrap();
+}
+
+#[tokio::test]
+async fn scanner_gives_up_on_missing_device() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER);
+    let controller = Controller::with_backend(backend(&adapter)).await.unwrap();
+
+    let scanner = Scanner::new(controller.adapter.clone(), controller.is_scanning.clone());
+    assert!(!scanner.discover_device(SPEAKER, 1).await.unwrap());
+}
+
+#[test]
+fn connection_policy_doubles_delay_until_it_saturates() {
+    let policy = ConnectionPolicy {
+        retries: 100,
+        retry_delay: Duration::from_secs(2),
+        timeout: Duration::from_secs(30),
+    };
+
+    assert_eq!(policy.delay_before(2), Duration::from_secs(2));
+    assert_eq!(policy.delay_before(4), Duration::from_secs(8));
+    assert_eq!(policy.delay_before(64), Duration::MAX);
+
+    let slow = ConnectionPolicy {
+        retry_delay: Duration::from_secs(u64::MAX),
+        ..policy
+    };
+    assert_eq!(slow.delay_before(3), Duration::MAX);
+
+    let endless = ConnectionPolicy {
+        retries: u32::MAX,
+        ..policy
+    };
+    assert_eq!(endless.max_attempts(), u32::MAX);
+}
+
+#[tokio::test]
+async fn pairing_manager_reports_attempts() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+        FakeDevice::new(HEADPHONES, "Headphones")
+            .paired(true)
+            .connect_outcomes([FakeOutcome::Fail(ErrorKind::InProgress)]),
+    );
+    let adapter_backend = backend(&adapter);
+    let manager = PairingManager::new(
+        adapter_backend.clone(),
+        ConnectionPolicy {
+            retries: 1,
+            retry_delay: Duration::from_millis(10),
+            timeout: Duration::from_secs(5),
+        },
+    );
+    let device = Device::new(&adapter_backend, &HEADPHONES).await.unwrap();
+
+    let attempts = std::sync::Mutex::new(Vec::new());
+    manager
+        .connect_device(&device, |attempt, max| {
+            attempts.lock().unwrap().push((attempt, max))
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(*attempts.lock().unwrap(), vec![(1, 2), (2, 2)]);
+}
+
+#[tokio::test]
+async fn fake_errors_classify_like_bluez_errors() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+        FakeDevice::new(HEADPHONES, "Headphones")
+            .connect_outcomes([FakeOutcome::Fail(ErrorKind::AlreadyConnected)]),
+    );
+    let device = Device::new(&backend(&adapter), &HEADPHONES).await.unwrap();
+
+    let err = device.connect().await.unwrap_err();
+    assert_eq!(
+        BluetoothError::classify(&err),
+        BluetoothError::AlreadyConnected
+    );
+}
+
+#[tokio::test]
+async fn failed_connection_attempts_are_retried() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+        FakeDevice::new(HEADPHONES, "Headphones")
+            .paired(true)
+            .connect_outcomes([FakeOutcome::Fail(ErrorKind::ConnectionAttemptFailed)]),
+    );
+    let adapter_backend = backend(&adapter);
+    let manager = PairingManager::new(
+        adapter_backend.clone(),
+        ConnectionPolicy {
+            retries: 1,
+            retry_delay: Duration::from_millis(10),
+            timeout: Duration::from_secs(5),
+        },
+    );
+    let device = Device::new(&adapter_backend, &HEADPHONES).await.unwrap();
+
+    manager.connect_device(&device, |_, _| {}).await.unwrap();
+    assert!(adapter.fake_device(HEADPHONES).unwrap().is_connected_now());
+
+    let local_abort = anyhow::anyhow!("le-connection-abort-by-local");
+    let error = BluetoothError::classify(&local_abort);
+    assert_eq!(error, BluetoothError::ConnectionFailed);
+    assert!(error.is_retryable());
+}
+
+#[tokio::test]
+async fn subscription_shows_latest_value_once_interval_elapses() {
+    const BATTERY_LEVEL: &str = "00002a19-0000-1000-8000-00805f9b34fb";
+
+    let notify = CharacteristicFlags {
+        notify: true,
+        ..Default::default()
+    };
+    let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+        FakeDevice::new(SPEAKER, "Sensor")
+            .connected(true)
+            .gatt_service(
+                0x0010,
+                "0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+            )
+            .gatt_characteristic(0x0010, 0x0011, BATTERY_LEVEL.parse().unwrap(), notify, &[]),
+    );
+    let fake = adapter.fake_device(SPEAKER).unwrap();
+    let device = Device::new(&backend(&adapter), &SPEAKER).await.unwrap();
+
+    let values = device
+        .subscribe_characteristic(0x0010, 0x0011)
+        .await
+        .unwrap();
+    let shown = Arc::new(Mutex::new(Vec::new()));
+    let subscription = Subscription::start(values, Duration::from_millis(300), {
+        let shown = shown.clone();
+        move |value| shown.lock().unwrap().push(value)
+    });
+
+    for level in [100, 99, 98] {
+        fake.notify_value(0x0010, 0x0011, &[level]);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(*shown.lock().unwrap(), [vec![100]]);
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(*shown.lock().unwrap(), [vec![100], vec![98]]);
+
+    assert!(fake.is_subscribed(0x0010, 0x0011));
+    subscription.stop().await;
+    assert!(!fake.is_subscribed(0x0010, 0x0011));
+}
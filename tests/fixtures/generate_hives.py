@@ -0,0 +1,208 @@
+#!/usr/bin/env python3
+"""Builds the SYSTEM hive fixtures used by tests/windows_keys.rs.
+
+    python3 tests/fixtures/generate_hives.py tests/fixtures
+
+Both hives hold the Bluetooth keys Windows keeps under
+ControlSetXXX\\Services\\BTHPORT\\Parameters\\Keys\\<adapter>:
+
+- a classic device C0:FF:EE:12:34:56 with link key 00112233...EEFF,
+- an LE device D0:C0:FF:EE:00:01 with an LTK, IRK, ERand, EDIV and an
+  authenticated key of 16 bytes,
+- the adapter's MasterIRK and CentralIRKStatus, which are not devices.
+
+SYSTEM_lh.hiv uses ASCII key names and "lh" subkey lists. Its Select key
+points at ControlSet002, which holds adapter 00:1A:7D:DA:71:13, while
+ControlSet001 holds a stale adapter AA:BB:CC:DD:EE:FF.
+
+SYSTEM_ri.hiv uses UTF-16 key names and splits every subkey list into two
+"li" lists under an "ri" index root. It has no Select key, so ControlSet001
+with adapter 00:1A:7D:DA:71:13 is the current one, next to the empty
+MountedDevices and Setup keys.
+
+Cells are written children first, so the root key comes last.
+"""
+
+import struct
+import sys
+
+BASE_BLOCK_SIZE = 4096
+HBIN_HEADER_SIZE = 0x20
+
+REG_BINARY = 3
+REG_DWORD = 4
+REG_QWORD = 11
+
+
+class Builder:
+    def __init__(self):
+        self.buf = bytearray()
+
+    def alloc(self, payload):
+        """Appends a used cell and returns its offset from the first bin."""
+        size = (4 + len(payload) + 7) & ~7
+        offset = HBIN_HEADER_SIZE + len(self.buf)
+        cell = struct.pack("<i", -size) + payload
+        self.buf += cell + b"\0" * (size - len(cell))
+        return offset
+
+
+class Key:
+    def __init__(self, name, values=None, subkeys=None):
+        self.name = name
+        self.values = values or []
+        self.subkeys = subkeys or []
+
+
+def encode_name(name, compressed):
+    return name.encode("latin1") if compressed else name.encode("utf-16le")
+
+
+def write_value(builder, name, kind, data, compressed):
+    raw_name = encode_name(name, compressed)
+    if len(data) <= 4:
+        # Small values are stored in the offset field itself.
+        size = len(data) | 0x80000000
+        data_offset = struct.unpack("<I", data.ljust(4, b"\0"))[0]
+    else:
+        size = len(data)
+        data_offset = builder.alloc(data)
+    vk = b"vk" + struct.pack(
+        "<HIIIHH", len(raw_name), size, data_offset, kind, 1 if compressed else 0, 0
+    )
+    return builder.alloc(vk + raw_name)
+
+
+def write_subkey_list(builder, offsets, list_kind):
+    if list_kind == "lh":
+        entries = b"".join(struct.pack("<II", offset, 0) for offset in offsets)
+        return builder.alloc(b"lh" + struct.pack("<H", len(offsets)) + entries)
+
+    half = (len(offsets) + 1) // 2
+    leaves = [
+        builder.alloc(
+            b"li" + struct.pack("<H", len(part)) + b"".join(struct.pack("<I", o) for o in part)
+        )
+        for part in (offsets[:half], offsets[half:])
+        if part
+    ]
+    entries = b"".join(struct.pack("<I", offset) for offset in leaves)
+    return builder.alloc(b"ri" + struct.pack("<H", len(leaves)) + entries)
+
+
+def write_key(builder, key, compressed, list_kind):
+    subkeys = [write_key(builder, subkey, compressed, list_kind) for subkey in key.subkeys]
+    values = [write_value(builder, n, k, d, compressed) for (n, k, d) in key.values]
+
+    value_list = 0xFFFFFFFF
+    if values:
+        value_list = builder.alloc(b"".join(struct.pack("<I", offset) for offset in values))
+    subkey_list = write_subkey_list(builder, subkeys, list_kind) if subkeys else 0xFFFFFFFF
+
+    raw_name = encode_name(key.name, compressed)
+    flags = 0x20 if compressed else 0
+    # The parent offset is not read, so it is left at 0.
+    nk = b"nk" + struct.pack(
+        "<HQ" + "I" * 15 + "HH",
+        flags, 0, 0, 0, len(subkeys), 0, subkey_list, 0xFFFFFFFF,
+        len(values), value_list, 0xFFFFFFFF, 0xFFFFFFFF, 0, 0, 0, 0, 0,
+        len(raw_name), 0,
+    )
+    assert len(nk) == 76
+    return builder.alloc(nk + raw_name)
+
+
+def build(root, compressed, list_kind, path):
+    builder = Builder()
+    root_offset = write_key(builder, root, compressed, list_kind)
+
+    data = bytes(builder.buf)
+    bin_size = ((HBIN_HEADER_SIZE + len(data) + BASE_BLOCK_SIZE - 1) // BASE_BLOCK_SIZE) * BASE_BLOCK_SIZE
+    free = bin_size - HBIN_HEADER_SIZE - len(data)
+    hbin = b"hbin" + struct.pack("<III", 0, bin_size, 0) + b"\0" * 8 + struct.pack("<I", 0) + b"\0" * 4
+    body = hbin + data
+    if free > 0:
+        body += struct.pack("<i", free) + b"\0" * (free - 4)
+
+    base = bytearray(BASE_BLOCK_SIZE)
+    base[0:4] = b"regf"
+    struct.pack_into("<IIQIIIII", base, 4, 1, 1, 0, 1, 5, 0, 1, root_offset)
+    struct.pack_into("<II", base, 0x28, bin_size, 1)
+    checksum = 0
+    for i in range(0, 508, 4):
+        checksum ^= struct.unpack_from("<I", base, i)[0]
+    struct.pack_into("<I", base, 0x1FC, checksum)
+
+    with open(path, "wb") as f:
+        f.write(bytes(base) + body)
+
+
+def dword(value):
+    return struct.pack("<I", value)
+
+
+def qword(value):
+    return struct.pack("<Q", value)
+
+
+LINK_KEY = bytes.fromhex("00112233445566778899aabbccddeeff")
+LTK = bytes.fromhex("0f0e0d0c0b0a09080706050403020100")
+IRK = bytes.fromhex("102132435465768798a9bacbdcedfe0f")
+
+
+def keys(adapter):
+    le_device = Key(
+        "d0c0ffee0001",
+        values=[
+            ("LTK", REG_BINARY, LTK),
+            ("KeyLength", REG_DWORD, dword(16)),
+            ("ERand", REG_QWORD, qword(0x1122334455667788)),
+            ("EDIV", REG_DWORD, dword(0xBEEF)),
+            ("IRK", REG_BINARY, IRK),
+            ("AuthReq", REG_DWORD, dword(0x2D)),
+            ("Address", REG_QWORD, qword(0xD0C0FFEE0001)),
+            ("AddressType", REG_DWORD, dword(1)),
+        ],
+    )
+    adapter_key = Key(
+        adapter,
+        values=[
+            ("c0ffee123456", REG_BINARY, LINK_KEY),
+            ("MasterIRK", REG_BINARY, IRK),
+            ("CentralIRKStatus", REG_DWORD, dword(1)),
+        ],
+        subkeys=[le_device],
+    )
+    return Key("Keys", subkeys=[adapter_key])
+
+
+def control_set(adapter):
+    parameters = Key("Parameters", subkeys=[keys(adapter)])
+    return Key("Services", subkeys=[Key("BTHPORT", subkeys=[parameters])])
+
+
+def main(out_dir):
+    build(
+        Key("ROOT", subkeys=[
+            Key("ControlSet001", subkeys=[control_set("aabbccddeeff")]),
+            Key("ControlSet002", subkeys=[control_set("001a7dda7113")]),
+            Key("Select", values=[("Current", REG_DWORD, dword(2)), ("Default", REG_DWORD, dword(2))]),
+        ]),
+        True,
+        "lh",
+        f"{out_dir}/SYSTEM_lh.hiv",
+    )
+    build(
+        Key("ROOT", subkeys=[
+            Key("ControlSet001", subkeys=[control_set("001a7dda7113")]),
+            Key("MountedDevices"),
+            Key("Setup"),
+        ]),
+        False,
+        "ri",
+        f"{out_dir}/SYSTEM_ri.hiv",
+    )
+
+
+if __name__ == "__main__":
+    main(sys.argv[1] if len(sys.argv) > 1 else ".")
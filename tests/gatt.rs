@@ -0,0 +1,76 @@
+use bluer::{gatt::CharacteristicFlags, Uuid};
+use bzmenu::bz::gatt::{
+    assigned_number, decode_value, parse_value, DecodedValue, GattCharacteristic, GattService,
+};
+
+fn uuid(short: u16) -> Uuid {
+    format!("0000{short:04x}-0000-1000-8000-00805f9b34fb")
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn names_services_and_characteristics_from_assigned_numbers() {
+    let vendor: Uuid = "6e400001-b5a3-f393-e0a9-e50e24dcca9e".parse().unwrap();
+
+    let service = |uuid| GattService {
+        id: 1,
+        uuid,
+        primary: true,
+    };
+    assert_eq!(service(uuid(0x180f)).name(), "Battery (0x180F)");
+    assert_eq!(service(uuid(0xabcd)).name(), "0xABCD");
+    assert_eq!(service(vendor).name(), vendor.to_string());
+
+    let characteristic = GattCharacteristic {
+        service_id: 1,
+        id: 2,
+        uuid: uuid(0x2a37),
+        flags: CharacteristicFlags {
+            notify: true,
+            write_without_response: true,
+            ..Default::default()
+        },
+    };
+    assert_eq!(characteristic.name(), "Heart Rate Measurement (0x2A37)");
+    assert_eq!(
+        characteristic.flag_names(),
+        ["write-without-response", "notify"]
+    );
+    assert!(characteristic.is_writable());
+    assert!(!characteristic.is_readable());
+
+    assert_eq!(assigned_number(&uuid(0x2a19)), Some(0x2a19));
+    assert_eq!(assigned_number(&vendor), None);
+}
+
+#[test]
+fn decodes_values_every_way_they_read() {
+    assert_eq!(
+        decode_value(b"nRF52\0"),
+        DecodedValue {
+            hex: "6E 52 46 35 32 00".to_string(),
+            text: Some("nRF52".to_string()),
+            integer: None,
+        }
+    );
+    assert_eq!(
+        decode_value(&[0x10, 0x27]),
+        DecodedValue {
+            hex: "10 27".to_string(),
+            text: None,
+            integer: Some(10000),
+        }
+    );
+    assert_eq!(decode_value(&[0xff, 0xfe, 0x00]).text, None);
+}
+
+#[test]
+fn parses_text_and_hex_input() {
+    assert_eq!(parse_value("on").unwrap(), b"on");
+    assert_eq!(parse_value(" 0x01 ff ").unwrap(), [0x01, 0xff]);
+    assert_eq!(parse_value("0XA0b1").unwrap(), [0xa0, 0xb1]);
+    assert!(parse_value("0x1").is_err());
+    assert!(parse_value("0x").is_err());
+    assert!(parse_value("0xzz").is_err());
+}
@@ -0,0 +1,134 @@
+use bluer::Address;
+use bzmenu::{
+    bz::{
+        backend::AdapterBackend,
+        device::Device,
+        fake::{FakeAdapter, FakeDevice},
+    },
+    history::{format_elapsed, History},
+    state::{State, StateStore},
+};
+use std::sync::Arc;
+
+const ADAPTER: Address = Address::new([0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]);
+const HEADPHONES: Address = Address::new([0xc0, 0xff, 0xee, 0x12, 0x34, 0x56]);
+const KEYBOARD: Address = Address::new([0xd0, 0xc0, 0xff, 0xee, 0x00, 0x01]);
+
+async fn device(adapter: &FakeAdapter, addr: Address) -> Device {
+    let backend: Arc<dyn AdapterBackend> = Arc::new(adapter.clone());
+    Device::new(&backend, &addr).await.unwrap()
+}
+
+#[test]
+fn formats_elapsed_time() {
+    assert_eq!(format_elapsed(1_000, 1_030), "just now");
+    assert_eq!(format_elapsed(1_000, 1_090), "a minute ago");
+    assert_eq!(format_elapsed(0, 45 * 60), "45 minutes ago");
+    assert_eq!(format_elapsed(0, 3 * 3600 + 59), "3 hours ago");
+    assert_eq!(format_elapsed(0, 86_400 + 5), "yesterday");
+    assert_eq!(format_elapsed(0, 3 * 86_400), "3 days ago");
+    assert_eq!(format_elapsed(2_000, 1_000), "just now");
+}
+
+#[tokio::test]
+async fn observe_tracks_connections_and_sightings() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connected(true)
+                .battery(55),
+        )
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard").paired(true));
+    let mut history = History::default();
+
+    let headphones = device(&adapter, HEADPHONES).await;
+    let keyboard = device(&adapter, KEYBOARD).await;
+    history.observe([&headphones, &keyboard], 100);
+
+    let entry = history.get(HEADPHONES).unwrap();
+    assert_eq!(entry.alias, "Headphones");
+    assert_eq!(entry.first_seen, Some(100));
+    assert_eq!(entry.last_connected, Some(100));
+    assert_eq!(entry.last_battery, Some(55));
+
+    // Paired devices are listed even when out of range.
+    let entry = history.get(KEYBOARD).unwrap();
+    assert_eq!(entry.last_seen, None);
+    assert_eq!(entry.last_connected, None);
+
+    adapter
+        .device(HEADPHONES)
+        .unwrap()
+        .disconnect()
+        .await
+        .unwrap();
+    let headphones = device(&adapter, HEADPHONES).await;
+    history.observe([&headphones], 200);
+
+    let entry = history.get(HEADPHONES).unwrap();
+    assert_eq!(entry.last_connected, Some(100));
+    assert_eq!(entry.last_disconnected, Some(200));
+    assert_eq!(entry.last_seen, Some(100));
+    assert_eq!(entry.last_battery, Some(55));
+
+    // Staying connected does not move the connection time.
+    adapter.device(HEADPHONES).unwrap().connect().await.unwrap();
+    let headphones = device(&adapter, HEADPHONES).await;
+    history.observe([&headphones], 250);
+    let before = history.clone();
+    history.observe([&headphones], 260);
+    assert_eq!(history, before);
+    assert_eq!(history.last_connected(HEADPHONES), Some(250));
+
+    history.record_connected(&keyboard, 300);
+    let recent: Vec<_> = history.recent().into_iter().map(|(a, _)| a).collect();
+    assert_eq!(recent, [KEYBOARD, HEADPHONES]);
+}
+
+#[tokio::test]
+async fn observe_drops_devices_no_longer_listed_unless_connected() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(HEADPHONES, "Headphones"))
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard"));
+    let mut history = History::default();
+
+    let headphones = device(&adapter, HEADPHONES).await;
+    let keyboard = device(&adapter, KEYBOARD).await;
+    history.observe([&headphones, &keyboard], 100);
+    history.record_connected(&headphones, 150);
+    assert!(history.get(KEYBOARD).is_some());
+
+    // Both are gone from BlueZ, and only the one connected to is kept.
+    history.observe([], 200);
+    assert!(history.get(KEYBOARD).is_none());
+    assert_eq!(history.last_connected(HEADPHONES), Some(150));
+}
+
+#[tokio::test]
+async fn store_round_trips_state_and_history() {
+    let dir = std::env::temp_dir().join(format!("bzmenu-history-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = StateStore::new(Some(dir.clone()));
+
+    assert_eq!(store.load_state(), State::default());
+    assert_eq!(store.load_history(), History::default());
+
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(HEADPHONES, "Headphones").paired(true));
+    let mut history = History::default();
+    history.record_connected(&device(&adapter, HEADPHONES).await, 42);
+    let mut state = State::default();
+    state.set_pinned(HEADPHONES, true);
+
+    store.save_history(&history).unwrap();
+    store.save_state(&state).unwrap();
+    assert_eq!(store.load_history(), history);
+    assert_eq!(store.load_state(), state);
+
+    // A corrupt file falls back to an empty history.
+    std::fs::write(dir.join("history.json"), "{").unwrap();
+    assert_eq!(store.load_history(), History::default());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,956 @@
+use bluer::{gatt::CharacteristicFlags, Address};
+use bzmenu::{
+    app::{App, AppConfig},
+    bz::{
+        fake::{FakeAdapter, FakeDevice},
+        pairing::ConnectionPolicy,
+        subscription::SubscriptionOutput,
+    },
+    filter::DeviceFilter,
+    icons::Icons,
+    launcher::{ScriptedLauncher, ScriptedPrompt},
+    menu::Menu,
+    navigation::{MenuState, OpenTarget},
+    state::{DeviceOrder, StateStore},
+};
+use std::{sync::Arc, time::Duration};
+
+const ADAPTER: Address = Address::new([0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]);
+const HEADPHONES: Address = Address::new([0xc0, 0xff, 0xee, 0x12, 0x34, 0x56]);
+const KEYBOARD: Address = Address::new([0xd0, 0xc0, 0xff, 0xee, 0x00, 0x01]);
+const SPEAKER: Address = Address::new([0xe0, 0x00, 0x00, 0x00, 0x00, 0x02]);
+
+fn config(interactive: bool) -> AppConfig {
+    AppConfig {
+        scan_duration: 1,
+        interactive,
+        quick_connect: false,
+        auto_scan: false,
+        device_order: DeviceOrder::default(),
+        new_device_order: DeviceOrder::Address,
+        filter: DeviceFilter::default(),
+        prune_after: None,
+        state_dir: None,
+        connection_policy: ConnectionPolicy {
+            retries: 0,
+            retry_delay: Duration::from_millis(10),
+            timeout: Duration::from_secs(5),
+        },
+        subscription_output: SubscriptionOutput::default(),
+    }
+}
+
+fn adapter() -> FakeAdapter {
+    FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(HEADPHONES, "Headphones").paired(true))
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard"))
+}
+
+/// Runs the app against `adapter`, answering menus with `steps`, and returns
+/// every menu that was rendered.
+async fn run_script(
+    adapter: &FakeAdapter,
+    interactive: bool,
+    steps: &[&str],
+) -> anyhow::Result<Vec<ScriptedPrompt>> {
+    run_script_with(adapter, config(interactive), |_| Ok(()), steps).await
+}
+
+async fn run_script_with(
+    adapter: &FakeAdapter,
+    config: AppConfig,
+    setup: impl FnOnce(&mut App) -> anyhow::Result<()>,
+    steps: &[&str],
+) -> anyhow::Result<Vec<ScriptedPrompt>> {
+    let icons = Arc::new(Icons::default());
+    let script = Arc::new(ScriptedLauncher::new(steps.iter().copied()));
+    let menu = Menu::with_script(script.clone(), icons.clone());
+
+    let mut app = App::with_backend(Arc::new(adapter.clone()), icons, config).await?;
+    setup(&mut app)?;
+    app.run(&menu, &None, "font", 1).await?;
+
+    assert_eq!(script.remaining_steps(), 0, "script was not fully consumed");
+    Ok(script.transcript())
+}
+
+fn labels(prompt: &ScriptedPrompt) -> Vec<&str> {
+    prompt
+        .entries
+        .iter()
+        .map(|entry| Icons::strip_font_icon(entry))
+        .collect()
+}
+
+#[tokio::test]
+async fn main_menu_lists_actions_and_devices() {
+    let adapter = adapter();
+    let transcript = run_script(&adapter, false, &["<escape>"]).await.unwrap();
+
+    assert_eq!(transcript.len(), 1);
+    let main = labels(&transcript[0]);
+    assert_eq!(main.first(), Some(&"Scan for Devices"));
+    assert_eq!(main.last(), Some(&"Settings"));
+    assert!(main.iter().any(|label| label.starts_with("Headphones")));
+    assert!(main.iter().any(|label| label.starts_with("Keyboard")));
+    assert_eq!(transcript[0].hint, None);
+    assert_eq!(transcript[0].selection, None);
+}
+
+#[tokio::test]
+async fn connects_and_exits_outside_interactive_mode() {
+    let adapter = adapter();
+    let transcript = run_script(&adapter, false, &["Headphones", "Connect"])
+        .await
+        .unwrap();
+
+    assert_eq!(transcript.len(), 2);
+    assert_eq!(transcript[1].hint.as_deref(), Some("Manage Headphones"));
+    assert!(labels(&transcript[1]).contains(&"Back"));
+    assert!(adapter.fake_device(HEADPHONES).unwrap().is_connected_now());
+}
+
+#[tokio::test]
+async fn scripts_pick_names_starting_with_non_ascii_letters() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(HEADPHONES, "Émile's Buds").paired(true));
+    let transcript = run_script(&adapter, false, &["Émile's Buds", "<escape>"])
+        .await
+        .unwrap();
+
+    assert_eq!(transcript[1].hint.as_deref(), Some("Manage Émile's Buds"));
+}
+
+#[tokio::test]
+async fn back_returns_to_main_menu() {
+    let adapter = adapter();
+    let transcript = run_script(&adapter, false, &["Keyboard", "Back", "<escape>"])
+        .await
+        .unwrap();
+
+    assert_eq!(transcript.len(), 3);
+    assert_eq!(labels(&transcript[1]), ["Connect", "Details", "Back"]);
+    assert_eq!(labels(&transcript[2]).first(), Some(&"Scan for Devices"));
+    assert_eq!(adapter.fake_device(KEYBOARD).unwrap().pair_attempts(), 0);
+}
+
+#[tokio::test]
+async fn interactive_mode_stays_in_menus_until_escape() {
+    let adapter = adapter();
+    let transcript = run_script(
+        &adapter,
+        true,
+        &[
+            "Settings",
+            "Make Device Visible",
+            "<escape>",
+            "Headphones",
+            "Trust",
+            "<escape>",
+            "<escape>",
+        ],
+    )
+    .await
+    .unwrap();
+
+    let hints: Vec<_> = transcript.iter().map(|p| p.hint.as_deref()).collect();
+    assert_eq!(
+        hints,
+        [
+            None,
+            None,
+            None,
+            None,
+            Some("Manage Headphones"),
+            Some("Manage Headphones"),
+            None,
+        ]
+    );
+
+    // Interactive menus rely on escape instead of a back entry.
+    assert!(!labels(&transcript[1]).contains(&"Back"));
+    assert!(labels(&transcript[2]).contains(&"Make Device Hidden"));
+    assert!(labels(&transcript[5]).contains(&"Revoke Trust"));
+
+    assert!(adapter.fake_device(HEADPHONES).unwrap().is_trusted_now());
+}
+
+#[tokio::test]
+async fn offers_to_power_on_adapter() {
+    let adapter = adapter().powered(false);
+    let transcript = run_script(&adapter, false, &["Enable Bluetooth", "<escape>"])
+        .await
+        .unwrap();
+
+    assert_eq!(labels(&transcript[0]), ["Enable Bluetooth"]);
+    assert_eq!(labels(&transcript[1]).first(), Some(&"Scan for Devices"));
+}
+
+#[tokio::test]
+async fn escape_walks_back_through_the_stack() {
+    let adapter = adapter();
+    let transcript = run_script_with(
+        &adapter,
+        config(true),
+        |app| {
+            app.open(MenuState::Device(HEADPHONES));
+            Ok(())
+        },
+        &["<escape>", "Settings", "<escape>", "<escape>"],
+    )
+    .await
+    .unwrap();
+
+    let hints: Vec<_> = transcript.iter().map(|p| p.hint.as_deref()).collect();
+    assert_eq!(hints, [Some("Manage Headphones"), None, None, None]);
+    assert_eq!(labels(&transcript[1]).first(), Some(&"Scan for Devices"));
+    assert_eq!(labels(&transcript[3]).first(), Some(&"Scan for Devices"));
+}
+
+#[tokio::test]
+async fn forgetting_a_device_returns_to_main_menu() {
+    let adapter = adapter();
+    let transcript = run_script(&adapter, true, &["Headphones", "Forget", "<escape>"])
+        .await
+        .unwrap();
+
+    assert_eq!(transcript.len(), 3);
+    assert!(!labels(&transcript[2])
+        .iter()
+        .any(|label| label.starts_with("Headphones")));
+}
+
+#[tokio::test]
+async fn disabling_adapter_offers_to_power_it_back_on() {
+    let adapter = adapter();
+    let transcript = run_script(
+        &adapter,
+        true,
+        &[
+            "Settings",
+            "Disable Bluetooth",
+            "Enable Bluetooth",
+            "<escape>",
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(transcript.len(), 4);
+    assert_eq!(labels(&transcript[2]), ["Enable Bluetooth"]);
+    assert_eq!(labels(&transcript[3]).first(), Some(&"Scan for Devices"));
+}
+
+#[tokio::test]
+async fn disabling_adapter_exits_outside_interactive_mode() {
+    let adapter = adapter();
+    let transcript = run_script(&adapter, false, &["Settings", "Disable Bluetooth"])
+        .await
+        .unwrap();
+
+    assert_eq!(transcript.len(), 2);
+}
+
+#[tokio::test]
+async fn opens_settings_and_exits_on_escape() {
+    let adapter = adapter();
+    let transcript = run_script_with(
+        &adapter,
+        config(true),
+        |app| app.start_in(&OpenTarget::Settings),
+        &["Make Device Visible", "<escape>"],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(transcript.len(), 2);
+    assert!(labels(&transcript[0]).contains(&"Make Device Visible"));
+    assert!(labels(&transcript[1]).contains(&"Make Device Hidden"));
+}
+
+#[tokio::test]
+async fn opens_device_by_alias_or_address() {
+    let adapter = adapter();
+
+    for query in ["headphones", "C0:FF:EE:12:34:56"] {
+        let target = format!("device:{query}").parse::<OpenTarget>().unwrap();
+        let transcript = run_script_with(
+            &adapter,
+            config(false),
+            |app| app.start_in(&target),
+            &["Back"],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(transcript.len(), 1);
+        assert_eq!(transcript[0].hint.as_deref(), Some("Manage Headphones"));
+    }
+}
+
+#[tokio::test]
+async fn rejects_unknown_open_targets() {
+    assert!("device:".parse::<OpenTarget>().is_err());
+    assert!("devices".parse::<OpenTarget>().is_err());
+
+    let adapter = adapter();
+    let err = run_script_with(
+        &adapter,
+        config(false),
+        |app| app.start_in(&OpenTarget::Device("Speaker".into())),
+        &[],
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("'Speaker'"));
+}
+
+#[tokio::test]
+async fn quick_connect_toggles_paired_devices_from_main_menu() {
+    let adapter = adapter();
+    let quick = AppConfig {
+        quick_connect: true,
+        ..config(true)
+    };
+    let transcript = run_script_with(
+        &adapter,
+        quick.clone(),
+        |_| Ok(()),
+        &["Headphones", "Headphones", "<escape>"],
+    )
+    .await
+    .unwrap();
+
+    let headphones = adapter.fake_device(HEADPHONES).unwrap();
+    assert_eq!(transcript.len(), 3);
+    assert!(labels(&transcript[0]).contains(&"Manage Devices"));
+    assert_eq!(headphones.connect_attempts(), 1);
+    assert_eq!(headphones.disconnects(), 1);
+    assert!(!headphones.is_connected_now());
+
+    // Unpaired devices still open their submenu.
+    let transcript = run_script_with(&adapter, quick, |_| Ok(()), &["Keyboard", "<escape>"])
+        .await
+        .unwrap();
+    assert_eq!(transcript[1].hint.as_deref(), Some("Manage Keyboard"));
+}
+
+#[tokio::test]
+async fn manage_devices_opens_the_full_device_menu() {
+    let adapter = adapter();
+    let quick = AppConfig {
+        quick_connect: true,
+        ..config(false)
+    };
+    let transcript = run_script_with(
+        &adapter,
+        quick,
+        |_| Ok(()),
+        &["Manage Devices", "Headphones", "Back", "Back", "<escape>"],
+    )
+    .await
+    .unwrap();
+
+    let hints: Vec<_> = transcript.iter().map(|p| p.hint.as_deref()).collect();
+    assert_eq!(
+        hints,
+        [
+            None,
+            Some("Manage Devices"),
+            Some("Manage Headphones"),
+            Some("Manage Devices"),
+            None
+        ]
+    );
+    assert!(labels(&transcript[1]).contains(&"Back"));
+    assert_eq!(
+        adapter.fake_device(HEADPHONES).unwrap().connect_attempts(),
+        0
+    );
+}
+
+fn device_labels(prompt: &ScriptedPrompt) -> Vec<&str> {
+    labels(prompt)
+        .into_iter()
+        .filter(|label| !label.starts_with("Scan") && !label.starts_with("Settings"))
+        .map(|label| label.split(' ').next().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn pinned_and_recent_devices_are_listed_first() {
+    let state_dir = std::env::temp_dir().join(format!("bzmenu-state-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&state_dir);
+
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(HEADPHONES, "Headphones").paired(true))
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard").paired(true))
+        .with_device(
+            FakeDevice::new(SPEAKER, "Speaker")
+                .paired(true)
+                .connected(true),
+        );
+    let persistent = AppConfig {
+        state_dir: Some(state_dir.clone()),
+        ..config(false)
+    };
+
+    let transcript = run_script_with(
+        &adapter,
+        persistent.clone(),
+        |_| Ok(()),
+        &["Keyboard", "Pin to Top"],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        device_labels(&transcript[0]),
+        ["Speaker", "Headphones", "Keyboard"]
+    );
+
+    let transcript = run_script_with(
+        &adapter,
+        persistent.clone(),
+        |_| Ok(()),
+        &["Headphones", "Connect"],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        device_labels(&transcript[0]),
+        ["Keyboard", "Speaker", "Headphones"]
+    );
+
+    let transcript = run_script_with(&adapter, persistent, |_| Ok(()), &["Keyboard", "<escape>"])
+        .await
+        .unwrap();
+    assert_eq!(
+        device_labels(&transcript[0]),
+        ["Keyboard", "Headphones", "Speaker"]
+    );
+    assert!(labels(&transcript[1]).contains(&"Unpin"));
+
+    let by_address = AppConfig {
+        device_order: DeviceOrder::Address,
+        ..config(false)
+    };
+    let transcript = run_script_with(&adapter, by_address, |_| Ok(()), &["<escape>"])
+        .await
+        .unwrap();
+    assert_eq!(
+        device_labels(&transcript[0]),
+        ["Headphones", "Keyboard", "Speaker"]
+    );
+
+    std::fs::remove_dir_all(&state_dir).unwrap();
+}
+
+#[tokio::test]
+async fn details_show_history_of_a_device() {
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connected(true)
+                .battery(70),
+        )
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard").paired(true));
+
+    let transcript = run_script(
+        &adapter,
+        true,
+        &[
+            "Headphones",
+            "Details",
+            "<escape>",
+            "Disconnect",
+            "Details",
+            "<escape>",
+            "<escape>",
+            "Keyboard",
+            "Details",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(transcript[2].hint.as_deref(), Some("Details of Headphones"));
+    assert_eq!(
+        labels(&transcript[2]),
+        [
+            "Address: C0:FF:EE:12:34:56",
+            "Connected",
+            "Battery: 70%",
+            "First seen just now",
+            "Last seen just now",
+            "Advertisement"
+        ]
+    );
+    assert_eq!(labels(&transcript[5])[1], "Last connected just now");
+    assert_eq!(
+        labels(&transcript[9]),
+        [
+            "Address: D0:C0:FF:EE:00:01",
+            "Never connected",
+            "Advertisement"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn details_show_device_information_once_read() {
+    const DEVICE_INFORMATION: &str = "0000180a-0000-1000-8000-00805f9b34fb";
+
+    let read = CharacteristicFlags {
+        read: true,
+        ..Default::default()
+    };
+    let characteristic = |number: u16| format!("0000{number:04x}-0000-1000-8000-00805f9b34fb");
+    let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+        FakeDevice::new(SPEAKER, "Sensor")
+            .paired(true)
+            .connected(true)
+            .uuids([DEVICE_INFORMATION.parse().unwrap()])
+            .gatt_service(0x0010, DEVICE_INFORMATION.parse().unwrap())
+            .gatt_characteristic(
+                0x0010,
+                0x0011,
+                characteristic(0x2a29).parse().unwrap(),
+                read,
+                b"Acme\0",
+            )
+            .gatt_characteristic(
+                0x0010,
+                0x0012,
+                characteristic(0x2a26).parse().unwrap(),
+                read,
+                b"1.2.3 ",
+            )
+            .gatt_characteristic(
+                0x0010,
+                0x0013,
+                characteristic(0x2a25).parse().unwrap(),
+                CharacteristicFlags::default(),
+                b"SN-1",
+            )
+            .gatt_characteristic(
+                0x0010,
+                0x0014,
+                characteristic(0x2a50).parse().unwrap(),
+                read,
+                &[0x01, 0x4c, 0x00, 0x34, 0x12, 0x00, 0x01],
+            ),
+    );
+
+    let transcript = run_script(
+        &adapter,
+        true,
+        &[
+            "Sensor",
+            "Details",
+            "<escape>",
+            "Disconnect",
+            "Details",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+        ],
+    )
+    .await
+    .unwrap();
+
+    let info = [
+        "Manufacturer: Acme",
+        "Firmware: 1.2.3",
+        "PnP ID: Apple, Inc. (0x004C), product 0x1234, version 0x0100",
+    ];
+    let connected = labels(&transcript[2]);
+    assert_eq!(connected[2..5], info);
+    assert!(!connected.iter().any(|label| label.starts_with("Serial")));
+
+    let disconnected = labels(&transcript[5]);
+    assert_eq!(disconnected[1], "Last connected just now");
+    assert_eq!(disconnected[2..5], info);
+}
+
+#[tokio::test]
+async fn details_show_decoded_beacons() {
+    const IBEACON: [u8; 23] = [
+        0x02, 0x15, 0xe2, 0xc5, 0x6d, 0xb5, 0xdf, 0xfb, 0x48, 0xd2, 0xb0, 0x60, 0xd0, 0xf5, 0xa7,
+        0x10, 0x96, 0xe0, 0x00, 0x01, 0x00, 0x02, 0xc5,
+    ];
+
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(SPEAKER, "Beacon").manufacturer_data(0x004c, &IBEACON));
+    let transcript = run_script(&adapter, false, &["Beacon", "Details", "<escape>"])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        labels(&transcript[2])[2],
+        "iBeacon e2c56db5-dffb-48d2-b060-d0f5a71096e0, major 1, minor 2"
+    );
+}
+
+#[tokio::test]
+async fn advertisement_shows_decoded_data_and_updates() {
+    const EDDYSTONE: &str = "0000feaa-0000-1000-8000-00805f9b34fb";
+
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(
+            FakeDevice::new(SPEAKER, "Sensor")
+                .manufacturer_data(0x004c, &[0x02, 0x15])
+                .manufacturer_data(0x0969, &[0x01])
+                .manufacturer_data(0xabcd, &[0xff])
+                .service_data(EDDYSTONE.parse().unwrap(), &[0x10, 0x00])
+                .advertising_flags(0x06)
+                .tx_power(-8),
+        )
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard"));
+    let transcript = run_script_with(
+        &adapter,
+        config(true),
+        |_| Ok(()),
+        &[
+            "Sensor",
+            "Details",
+            "Advertisement",
+            "Manufacturer",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+            "Keyboard",
+            "Details",
+            "Advertisement",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        transcript[3].hint.as_deref(),
+        Some("Advertisement of Sensor")
+    );
+    assert_eq!(
+        labels(&transcript[3]),
+        [
+            "Manufacturer Apple, Inc. (0x004C): 02 15",
+            "Manufacturer Woan Technology (Shenzhen) Co., Ltd. (0x0969): 01",
+            "Manufacturer 0xABCD: FF",
+            "Service 0xFEAA: 10 00",
+            "Flags: LE General Discoverable, BR/EDR Not Supported",
+            "Transmit power: -8 dBm"
+        ]
+    );
+    assert_eq!(labels(&transcript[4]), labels(&transcript[3]));
+    // Leaving the view stopped the discovery it started.
+    assert_eq!(labels(&transcript[7])[0], "Scan for Devices");
+    assert_eq!(labels(&transcript[10]), ["No advertisement received"]);
+    assert!(!adapter.is_discovering_now());
+}
+
+#[tokio::test]
+async fn services_browse_read_and_write_characteristics() {
+    const BATTERY: &str = "0000180f-0000-1000-8000-00805f9b34fb";
+    const BATTERY_LEVEL: &str = "00002a19-0000-1000-8000-00805f9b34fb";
+    const UART: &str = "6e400001-b5a3-f393-e0a9-e50e24dcca9e";
+    const UART_RX: &str = "6e400002-b5a3-f393-e0a9-e50e24dcca9e";
+
+    let battery_level = CharacteristicFlags {
+        read: true,
+        notify: true,
+        ..Default::default()
+    };
+    let rx = CharacteristicFlags {
+        write: true,
+        ..Default::default()
+    };
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(
+            FakeDevice::new(SPEAKER, "Sensor")
+                .paired(true)
+                .connected(true)
+                .gatt_service(0x0010, BATTERY.parse().unwrap())
+                .gatt_characteristic(
+                    0x0010,
+                    0x0011,
+                    BATTERY_LEVEL.parse().unwrap(),
+                    battery_level,
+                    &[0x64],
+                )
+                .gatt_service(0x0020, UART.parse().unwrap())
+                .gatt_characteristic(0x0020, 0x0021, UART_RX.parse().unwrap(), rx, &[]),
+        )
+        .with_device(
+            FakeDevice::new(HEADPHONES, "Headphones")
+                .paired(true)
+                .connected(true),
+        );
+
+    let transcript = run_script(
+        &adapter,
+        true,
+        &[
+            "Sensor",
+            "Services",
+            "Battery",
+            "Battery Level",
+            "Read Again",
+            "<escape>",
+            "<escape>",
+            "6e400001",
+            "6e400002",
+            "Write Value",
+            "0x01 ff",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+            "Headphones",
+            "<escape>",
+            "<escape>",
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(transcript[2].hint.as_deref(), Some("Services of Sensor"));
+    assert_eq!(labels(&transcript[2]), ["Battery (0x180F)", UART]);
+    assert_eq!(
+        labels(&transcript[3]),
+        ["Battery Level (0x2A19): read, notify"]
+    );
+    assert_eq!(
+        labels(&transcript[4]),
+        [
+            "Hex: 64",
+            "Text: d",
+            "Integer: 100",
+            "Flags: read, notify",
+            "Read Again",
+            "Subscribe"
+        ]
+    );
+    assert_eq!(labels(&transcript[5]), labels(&transcript[4]));
+    assert_eq!(labels(&transcript[9]), ["Flags: write", "Write Value"]);
+    assert!(transcript[10].entries.is_empty());
+    assert_eq!(
+        transcript[10].hint.as_deref(),
+        Some("Value for 6e400002-b5a3-f393-e0a9-e50e24dcca9e, as text or hex like 0x01 ff")
+    );
+    assert_eq!(
+        adapter
+            .fake_device(SPEAKER)
+            .unwrap()
+            .characteristic_value(0x0020, 0x0021),
+        Some(vec![0x01, 0xff])
+    );
+    assert!(!labels(&transcript[16]).contains(&"Services"));
+}
+
+#[tokio::test]
+async fn subscription_stops_with_its_menu() {
+    const HEART_RATE: &str = "0000180d-0000-1000-8000-00805f9b34fb";
+    const MEASUREMENT: &str = "00002a37-0000-1000-8000-00805f9b34fb";
+
+    let notify = CharacteristicFlags {
+        notify: true,
+        ..Default::default()
+    };
+    let adapter = FakeAdapter::new("hci0", ADAPTER).with_device(
+        FakeDevice::new(SPEAKER, "Strap")
+            .paired(true)
+            .connected(true)
+            .gatt_service(0x0010, HEART_RATE.parse().unwrap())
+            .gatt_characteristic(0x0010, 0x0011, MEASUREMENT.parse().unwrap(), notify, &[]),
+    );
+
+    let transcript = run_script(
+        &adapter,
+        true,
+        &[
+            "Strap",
+            "Services",
+            "Heart Rate",
+            "Heart Rate Measurement",
+            "Subscribe",
+            "Stop",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+            "<escape>",
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(labels(&transcript[4]), ["Flags: notify", "Subscribe"]);
+    assert_eq!(
+        transcript[5].hint.as_deref(),
+        Some("Watching Heart Rate Measurement (0x2A37)")
+    );
+    assert_eq!(labels(&transcript[5]), ["Stop"]);
+    assert_eq!(labels(&transcript[6]), labels(&transcript[4]));
+
+    let strap = adapter.fake_device(SPEAKER).unwrap();
+    assert_eq!(strap.subscriptions(), 1);
+    assert!(!strap.is_subscribed(0x0010, 0x0011));
+}
+
+#[tokio::test]
+async fn filtered_devices_are_listed_on_demand() {
+    const BEACON: Address = Address::new([0xf0, 0x00, 0x00, 0x00, 0x00, 0x03]);
+    const TAG: Address = Address::new([0xac, 0x23, 0x3f, 0x00, 0x00, 0x04]);
+
+    let adapter = FakeAdapter::new("hci0", ADAPTER)
+        .with_device(FakeDevice::new(HEADPHONES, "Headphones").paired(true))
+        .with_device(FakeDevice::new(KEYBOARD, "Keyboard").rssi(-50))
+        .with_device(FakeDevice::new(SPEAKER, "Speaker").rssi(-95))
+        .with_device(FakeDevice::new(BEACON, "").unnamed().rssi(-40))
+        .with_device(FakeDevice::new(TAG, "Tag"));
+    let filtered = AppConfig {
+        filter: DeviceFilter {
+            hide_unnamed: true,
+            min_rssi: Some(-80),
+            hidden_types: Vec::new(),
+            hidden_prefixes: vec!["ac-23-3f".to_string()],
+        },
+        ..config(true)
+    };
+
+    let transcript = run_script_with(
+        &adapter,
+        filtered,
+        |_| Ok(()),
+        &["Show 3 hidden devices", "<escape>"],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        device_labels(&transcript[0]),
+        ["Headphones", "Keyboard", "Show"]
+    );
+    assert_eq!(
+        device_labels(&transcript[1]),
+        [
+            "Headphones",
+            "Tag",
+            "Keyboard",
+            "Speaker",
+            "F0-00-00-00-00-03"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn clears_discovered_devices_from_settings() {
+    let adapter = adapter().with_device(FakeDevice::new(SPEAKER, "Speaker").connected(true));
+    let transcript = run_script(
+        &adapter,
+        true,
+        &[
+            "Settings",
+            "Clear Discovered Devices",
+            "<escape>",
+            "<escape>",
+        ],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        device_labels(&transcript[0]),
+        ["Headphones", "Keyboard", "Speaker"]
+    );
+    assert_eq!(device_labels(&transcript[3]), ["Headphones", "Speaker"]);
+}
+
+#[tokio::test]
+async fn scans_in_background_while_main_menu_is_open() {
+    let adapter = adapter().with_discoverable_device(FakeDevice::new(SPEAKER, "Speaker"));
+    let auto_scan = AppConfig {
+        auto_scan: true,
+        scan_duration: 60,
+        ..config(true)
+    };
+
+    let transcript = run_script_with(&adapter, auto_scan, |_| Ok(()), &["Scanning…", "<escape>"])
+        .await
+        .unwrap();
+
+    assert_eq!(transcript.len(), 2);
+    for prompt in &transcript {
+        assert_eq!(labels(prompt).first(), Some(&"Scanning…"));
+        assert_eq!(device_labels(prompt), ["Headphones", "Keyboard", "Speaker"]);
+    }
+    assert!(!adapter.is_discovering_now());
+}
+
+#[tokio::test]
+async fn prunes_devices_not_seen_recently() {
+    let state_dir = std::env::temp_dir().join(format!("bzmenu-prune-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&state_dir);
+    std::fs::create_dir_all(&state_dir).unwrap();
+    std::fs::write(
+        state_dir.join("history.json"),
+        r#"{"devices": {"E0:00:00:00:00:02": {"alias": "Speaker", "last_seen": 0}}}"#,
+    )
+    .unwrap();
+
+    let adapter = adapter().with_device(FakeDevice::new(SPEAKER, "Speaker"));
+    let pruning = AppConfig {
+        prune_after: Some(Duration::from_secs(600)),
+        state_dir: Some(state_dir.clone()),
+        ..config(false)
+    };
+    let transcript = run_script_with(&adapter, pruning, |_| Ok(()), &["<escape>"])
+        .await
+        .unwrap();
+
+    assert_eq!(device_labels(&transcript[0]), ["Headphones", "Keyboard"]);
+    let history = StateStore::new(Some(state_dir.clone())).load_history();
+    assert!(history.get(SPEAKER).is_none());
+    assert!(history.get(KEYBOARD).is_some());
+
+    std::fs::remove_dir_all(&state_dir).unwrap();
+}
+
+#[tokio::test]
+async fn unknown_selection_fails_loudly() {
+    let adapter = adapter();
+    let err = run_script(&adapter, false, &["Speaker"]).await.unwrap_err();
+
+    assert!(err.to_string().contains("'Speaker' not found"));
+}
+
+#[test]
+fn scripts_skip_comments_and_blank_lines() {
+    let path = std::env::temp_dir().join(format!("bzmenu-script-{}", std::process::id()));
+    std::fs::write(&path, "# reproduce #42\nSettings\n\n  <escape>\n").unwrap();
+
+    let script = ScriptedLauncher::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(script.remaining_steps(), 2);
+    assert_eq!(
+        script
+            .run(Some("\u{f46a} Scan\n\u{f013} Settings"), None)
+            .unwrap(),
+        Some("\u{f013} Settings".to_string())
+    );
+    assert_eq!(
+        script.run(Some("\u{f46a} Scan"), Some("hint")).unwrap(),
+        None
+    );
+    assert_eq!(script.run(Some("\u{f46a} Scan"), None).unwrap(), None);
+    assert_eq!(script.transcript()[1].hint.as_deref(), Some("hint"));
+}
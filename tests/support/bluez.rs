@@ -0,0 +1,706 @@
+//! Stand-in for `bluetoothd` running on a private `dbus-daemon`.
+//!
+//! The stand-in exports just enough of `org.bluez` (ObjectManager, Adapter1,
+//! Device1, Battery1 and AgentManager1) for `bluer` to talk to it as it would
+//! to BlueZ. Tests are skipped when `dbus-daemon` is not installed.
+
+use dbus::{
+    arg::{PropMap, RefArg, Variant},
+    blocking::Connection,
+    channel::{Channel, MatchingReceiver, Sender},
+    message::MatchRule,
+    Message, MethodErr, Path,
+};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs,
+    future::Future,
+    io::{BufRead, BufReader},
+    os::unix::process::CommandExt,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, MutexGuard, OnceLock,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+pub const ADAPTER_PATH: &str = "/org/bluez/hci0";
+
+const OBJECT_MANAGER: &str = "org.freedesktop.DBus.ObjectManager";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const AGENT_MANAGER: &str = "org.bluez.AgentManager1";
+const ADAPTER: &str = "org.bluez.Adapter1";
+const DEVICE: &str = "org.bluez.Device1";
+const BATTERY: &str = "org.bluez.Battery1";
+
+/// Tests share the process environment, so only one bus runs at a time.
+static BUS_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone)]
+pub struct AdapterState {
+    pub address: String,
+    pub alias: String,
+    pub powered: bool,
+    pub pairable: bool,
+    pub discoverable: bool,
+    pub discovering: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeviceState {
+    pub address: String,
+    pub alias: String,
+    pub icon: Option<String>,
+    pub class: Option<u32>,
+    pub paired: bool,
+    pub trusted: bool,
+    pub connected: bool,
+    pub battery: Option<u8>,
+    /// Passkey the default agent is asked to confirm during `Pair`.
+    pub passkey: Option<u32>,
+    /// BlueZ errors returned by the next `Connect` calls, as (name, message).
+    pub connect_errors: VecDeque<(&'static str, &'static str)>,
+    /// Only shows up once discovery has been started.
+    pub hidden: bool,
+    pub connect_calls: u32,
+}
+
+impl DeviceState {
+    pub fn new(address: &str, alias: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            alias: alias.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn path(&self) -> String {
+        device_path(&self.address)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+    pub adapter: AdapterState,
+    pub devices: BTreeMap<String, DeviceState>,
+    /// Registered agents, as (bus name, object path).
+    pub agents: Vec<(String, String)>,
+    pub default_agent: Option<(String, String)>,
+    pub discovery_filter_set: bool,
+}
+
+impl State {
+    pub fn new(address: &str) -> Self {
+        Self {
+            adapter: AdapterState {
+                address: address.to_string(),
+                alias: "stand-in".to_string(),
+                powered: true,
+                pairable: true,
+                discoverable: false,
+                discovering: false,
+            },
+            devices: BTreeMap::new(),
+            agents: Vec::new(),
+            default_agent: None,
+            discovery_filter_set: false,
+        }
+    }
+
+    pub fn with_device(mut self, device: DeviceState) -> Self {
+        self.devices.insert(device.address.clone(), device);
+        self
+    }
+
+    fn visible_devices(&self) -> impl Iterator<Item = &DeviceState> {
+        self.devices.values().filter(|d| !d.hidden)
+    }
+
+    fn device_by_path(&mut self, path: &str) -> Option<&mut DeviceState> {
+        self.devices
+            .values_mut()
+            .find(|d| !d.hidden && d.path() == path)
+    }
+}
+
+/// Handle on the stand-in state, shared with the D-Bus thread.
+#[derive(Debug, Clone)]
+pub struct Bluez {
+    state: Arc<Mutex<State>>,
+}
+
+impl Bluez {
+    pub fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn device(&self, address: &str) -> Option<DeviceState> {
+        self.state().devices.get(address).cloned()
+    }
+}
+
+/// Starts a private bus with the stand-in on it, points the system bus at
+/// it and runs `test` on a fresh runtime.
+pub fn run<F, Fut>(state: State, test: F)
+where
+    F: FnOnce(Bluez) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let _guard = BUS_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let Some(address) = bus_address() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+
+    let stand_in = StandIn::start(address, state);
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(test(stand_in.bluez.clone()));
+    drop(runtime);
+}
+
+/// libdbus only connects to one system bus per process, so a single daemon
+/// is shared by all tests and a fresh stand-in is started for each of them.
+fn bus_address() -> Option<&'static str> {
+    static BUS: OnceLock<Option<String>> = OnceLock::new();
+
+    BUS.get_or_init(|| {
+        let (tx, rx) = mpsc::channel();
+
+        // The daemon is killed when the thread that spawned it exits, so
+        // keep that thread around for the lifetime of the test process.
+        thread::spawn(move || {
+            let daemon = spawn_daemon();
+            let _ = tx.send(daemon.as_ref().map(|(_, address)| address.clone()));
+            if daemon.is_some() {
+                loop {
+                    thread::park();
+                }
+            }
+        });
+
+        let address = rx.recv().ok().flatten()?;
+        std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &address);
+        Some(address)
+    })
+    .as_deref()
+}
+
+fn spawn_daemon() -> Option<(Child, String)> {
+    let config = std::env::temp_dir().join(format!("bzmenu-bus-{}.conf", std::process::id()));
+    fs::write(
+        &config,
+        format!(
+            r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>custom</type>
+  <listen>unix:abstract=bzmenu-test-{}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#,
+            std::process::id()
+        ),
+    )
+    .ok()?;
+
+    let mut command = Command::new("dbus-daemon");
+    command
+        .arg(format!("--config-file={}", config.display()))
+        .args(["--nofork", "--nopidfile", "--print-address"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    unsafe {
+        command.pre_exec(|| {
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM);
+            Ok(())
+        });
+    }
+    let mut daemon = command.spawn().ok()?;
+
+    // The address is printed once the configuration has been loaded.
+    let mut address = String::new();
+    let stdout = daemon.stdout.take()?;
+    BufReader::new(stdout).read_line(&mut address).ok()?;
+    let _ = fs::remove_file(&config);
+
+    if address.trim().is_empty() {
+        return None;
+    }
+
+    Some((daemon, address.trim().to_string()))
+}
+
+struct StandIn {
+    bluez: Bluez,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StandIn {
+    fn start(address: &str, state: State) -> Self {
+        let bluez = Bluez {
+            state: Arc::new(Mutex::new(state)),
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = {
+            let bluez = bluez.clone();
+            let stop = stop.clone();
+            let address = address.to_string();
+            thread::spawn(move || serve(address, bluez, stop, ready_tx))
+        };
+
+        ready_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("stand-in failed to claim org.bluez");
+
+        Self {
+            bluez,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for StandIn {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn connect(address: &str) -> Connection {
+    let mut channel = Channel::open_private(address).expect("failed to open bus");
+    channel.register().expect("failed to register on bus");
+    Connection::from(channel)
+}
+
+fn serve(address: String, bluez: Bluez, stop: Arc<AtomicBool>, ready: mpsc::Sender<()>) {
+    let conn = connect(&address);
+    conn.request_name("org.bluez", true, true, true)
+        .expect("failed to claim org.bluez");
+
+    // Replies produced on helper threads, sent from this connection.
+    let (deferred_tx, deferred_rx) = mpsc::channel::<Message>();
+
+    conn.start_receive(
+        MatchRule::new_method_call(),
+        Box::new(move |msg, conn| {
+            let ctx = Ctx {
+                bluez: &bluez,
+                address: &address,
+                deferred: &deferred_tx,
+            };
+            match ctx.dispatch(&msg) {
+                Ok(Some((reply, signals))) => {
+                    let _ = conn.send(reply);
+                    for signal in signals {
+                        let _ = conn.send(signal);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    let _ = conn.send(err.to_message(&msg));
+                }
+            }
+            true
+        }),
+    );
+
+    let _ = ready.send(());
+
+    while !stop.load(Ordering::Relaxed) {
+        let _ = conn.process(Duration::from_millis(10));
+        while let Ok(reply) = deferred_rx.try_recv() {
+            let _ = conn.send(reply);
+        }
+    }
+}
+
+type Reply = Option<(Message, Vec<Message>)>;
+
+struct Ctx<'a> {
+    bluez: &'a Bluez,
+    address: &'a str,
+    deferred: &'a mpsc::Sender<Message>,
+}
+
+impl Ctx<'_> {
+    fn dispatch(&self, msg: &Message) -> Result<Reply, MethodErr> {
+        let path = msg.path().map(|p| p.to_string()).unwrap_or_default();
+        let interface = msg.interface().map(|i| i.to_string()).unwrap_or_default();
+        let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+
+        match (interface.as_str(), member.as_str()) {
+            (OBJECT_MANAGER, "GetManagedObjects") if path == "/" => {
+                let objects = managed_objects(&self.bluez.state());
+                done(msg.method_return().append1(objects))
+            }
+            (PROPERTIES, "Get") => {
+                let (iface, name): (&str, &str) = msg.read2()?;
+                let mut props = self.properties(&path, iface);
+                match props.remove(name) {
+                    Some(value) => done(msg.method_return().append1(value)),
+                    None => Err(MethodErr::invalid_arg(&format!(
+                        "No such property '{name}'"
+                    ))),
+                }
+            }
+            (PROPERTIES, "GetAll") => {
+                let iface: &str = msg.read1()?;
+                done(msg.method_return().append1(self.properties(&path, iface)))
+            }
+            (PROPERTIES, "Set") => {
+                let (iface, name, value): (&str, &str, Variant<Box<dyn RefArg>>) = msg.read3()?;
+                self.set_property(&path, iface, name, &*value.0)?;
+                let changed = self.properties(&path, iface);
+                let signal = properties_changed(&path, iface, name, changed);
+                Ok(Some((msg.method_return(), signal.into_iter().collect())))
+            }
+            (AGENT_MANAGER, _) => self.agent_manager(msg, &member),
+            (ADAPTER, _) if path == ADAPTER_PATH => self.adapter(msg, &member),
+            (DEVICE, _) => self.device(msg, &path, &member),
+            _ => Err(MethodErr::no_method(&member)),
+        }
+    }
+
+    fn properties(&self, path: &str, iface: &str) -> PropMap {
+        let state = self.bluez.state();
+        if path == ADAPTER_PATH && iface == ADAPTER {
+            return adapter_properties(&state.adapter);
+        }
+        let props = state
+            .visible_devices()
+            .find(|d| d.path() == path)
+            .and_then(|d| device_interfaces(d).remove(iface));
+        props.unwrap_or_default()
+    }
+
+    fn set_property(
+        &self,
+        path: &str,
+        iface: &str,
+        name: &str,
+        value: &dyn RefArg,
+    ) -> Result<(), MethodErr> {
+        let mut state = self.bluez.state();
+        let flag = value.as_i64().map(|v| v != 0);
+
+        match (iface, name) {
+            (ADAPTER, "Powered") if path == ADAPTER_PATH => state.adapter.powered = flag.unwrap(),
+            (ADAPTER, "Pairable") if path == ADAPTER_PATH => state.adapter.pairable = flag.unwrap(),
+            (ADAPTER, "Discoverable") if path == ADAPTER_PATH => {
+                state.adapter.discoverable = flag.unwrap()
+            }
+            (ADAPTER, "Alias") if path == ADAPTER_PATH => {
+                state.adapter.alias = value.as_str().unwrap_or_default().to_string()
+            }
+            (DEVICE, "Trusted") => {
+                state
+                    .device_by_path(path)
+                    .ok_or_else(|| MethodErr::no_path(path))?
+                    .trusted = flag.unwrap()
+            }
+            _ => return Err(MethodErr::ro_property(name)),
+        }
+        Ok(())
+    }
+
+    fn agent_manager(&self, msg: &Message, member: &str) -> Result<Reply, MethodErr> {
+        let sender = msg.sender().map(|s| s.to_string()).unwrap_or_default();
+        let mut state = self.bluez.state();
+
+        match member {
+            "RegisterAgent" => {
+                let (path, _capability): (Path, &str) = msg.read2()?;
+                state.agents.push((sender, path.to_string()));
+            }
+            "UnregisterAgent" => {
+                let path: Path = msg.read1()?;
+                let agent = (sender, path.to_string());
+                state.agents.retain(|a| *a != agent);
+                if state.default_agent.as_ref() == Some(&agent) {
+                    state.default_agent = None;
+                }
+            }
+            "RequestDefaultAgent" => {
+                let path: Path = msg.read1()?;
+                let agent = (sender, path.to_string());
+                if !state.agents.contains(&agent) {
+                    return Err(bluez_error("DoesNotExist", "Does Not Exist"));
+                }
+                state.default_agent = Some(agent);
+            }
+            _ => return Err(MethodErr::no_method(member)),
+        }
+        done(msg.method_return())
+    }
+
+    fn adapter(&self, msg: &Message, member: &str) -> Result<Reply, MethodErr> {
+        let mut state = self.bluez.state();
+
+        match member {
+            "SetDiscoveryFilter" => {
+                let _filter: PropMap = msg.read1()?;
+                state.discovery_filter_set = true;
+                done(msg.method_return())
+            }
+            "StartDiscovery" => {
+                if !state.adapter.powered {
+                    return Err(bluez_error("NotReady", "Resource Not Ready"));
+                }
+                state.adapter.discovering = true;
+
+                let mut signals = Vec::new();
+                for device in state.devices.values_mut().filter(|d| d.hidden) {
+                    device.hidden = false;
+                    signals.push(
+                        Message::signal(
+                            &"/".into(),
+                            &OBJECT_MANAGER.into(),
+                            &"InterfacesAdded".into(),
+                        )
+                        .append2(Path::from(device.path()), device_interfaces(device)),
+                    );
+                }
+                signals.extend(discovering_changed(&state));
+                Ok(Some((msg.method_return(), signals)))
+            }
+            "StopDiscovery" => {
+                if !state.adapter.discovering {
+                    return Err(bluez_error("Failed", "No discovery started"));
+                }
+                state.adapter.discovering = false;
+                Ok(Some((msg.method_return(), discovering_changed(&state))))
+            }
+            "RemoveDevice" => {
+                let path: Path = msg.read1()?;
+                let address = state
+                    .visible_devices()
+                    .find(|d| d.path() == *path)
+                    .map(|d| d.address.clone())
+                    .ok_or_else(|| bluez_error("DoesNotExist", "Does Not Exist"))?;
+                state.devices.remove(&address);
+
+                let signal = Message::signal(
+                    &"/".into(),
+                    &OBJECT_MANAGER.into(),
+                    &"InterfacesRemoved".into(),
+                )
+                .append2(path, vec![DEVICE.to_string(), PROPERTIES.to_string()]);
+                Ok(Some((msg.method_return(), vec![signal])))
+            }
+            _ => Err(MethodErr::no_method(member)),
+        }
+    }
+
+    fn device(&self, msg: &Message, path: &str, member: &str) -> Result<Reply, MethodErr> {
+        let mut state = self.bluez.state();
+        let default_agent = state.default_agent.clone();
+        let device = state
+            .device_by_path(path)
+            .ok_or_else(|| MethodErr::no_path(path))?;
+
+        match member {
+            "Connect" => {
+                device.connect_calls += 1;
+                if let Some((name, message)) = device.connect_errors.pop_front() {
+                    return Err(bluez_error(name, message));
+                }
+                device.connected = true;
+                Ok(Some((
+                    msg.method_return(),
+                    device_changed(device, "Connected"),
+                )))
+            }
+            "Disconnect" => {
+                if !device.connected {
+                    return Err(bluez_error("NotConnected", "Not Connected"));
+                }
+                device.connected = false;
+                Ok(Some((
+                    msg.method_return(),
+                    device_changed(device, "Connected"),
+                )))
+            }
+            "Pair" => {
+                if device.paired {
+                    return Err(bluez_error("AlreadyExists", "Already Exists"));
+                }
+
+                let (Some(passkey), Some(agent)) = (device.passkey, default_agent) else {
+                    device.paired = true;
+                    return Ok(Some((
+                        msg.method_return(),
+                        device_changed(device, "Paired"),
+                    )));
+                };
+
+                // The agent lives on the caller's connection, so ask it from
+                // another one and reply once it answered.
+                let accepted = msg.method_return();
+                let rejected = bluez_error("AuthenticationRejected", "Authentication Rejected")
+                    .to_message(msg);
+                let bluez = self.bluez.clone();
+                let address = self.address.to_string();
+                let deferred = self.deferred.clone();
+                let device_address = device.address.clone();
+                let device_path = path.to_string();
+
+                thread::spawn(move || {
+                    let conn = connect(&address);
+                    let result: Result<(), dbus::Error> = conn
+                        .with_proxy(agent.0, agent.1, Duration::from_secs(10))
+                        .method_call(
+                            "org.bluez.Agent1",
+                            "RequestConfirmation",
+                            (Path::from(device_path), passkey),
+                        );
+
+                    let reply = match result {
+                        Ok(()) => {
+                            if let Some(device) = bluez.state().devices.get_mut(&device_address) {
+                                device.paired = true;
+                            }
+                            accepted
+                        }
+                        Err(_) => rejected,
+                    };
+                    let _ = deferred.send(reply);
+                });
+
+                Ok(None)
+            }
+            "CancelPairing" => done(msg.method_return()),
+            _ => Err(MethodErr::no_method(member)),
+        }
+    }
+}
+
+fn done(reply: Message) -> Result<Reply, MethodErr> {
+    Ok(Some((reply, Vec::new())))
+}
+
+fn bluez_error(name: &str, message: &str) -> MethodErr {
+    (format!("org.bluez.Error.{name}"), message.to_string()).into()
+}
+
+fn device_path(address: &str) -> String {
+    format!("{ADAPTER_PATH}/dev_{}", address.replace(':', "_"))
+}
+
+fn variant<T: RefArg + 'static>(value: T) -> Variant<Box<dyn RefArg>> {
+    Variant(Box::new(value))
+}
+
+fn adapter_properties(adapter: &AdapterState) -> PropMap {
+    let mut props = PropMap::new();
+    props.insert("Address".into(), variant(adapter.address.clone()));
+    props.insert("AddressType".into(), variant("public".to_string()));
+    props.insert("Name".into(), variant("stand-in".to_string()));
+    props.insert("Alias".into(), variant(adapter.alias.clone()));
+    props.insert("Class".into(), variant(0u32));
+    props.insert("Powered".into(), variant(adapter.powered));
+    props.insert("Discoverable".into(), variant(adapter.discoverable));
+    props.insert("DiscoverableTimeout".into(), variant(180u32));
+    props.insert("Pairable".into(), variant(adapter.pairable));
+    props.insert("PairableTimeout".into(), variant(0u32));
+    props.insert("Discovering".into(), variant(adapter.discovering));
+    props.insert("UUIDs".into(), variant(Vec::<String>::new()));
+    props
+}
+
+fn device_interfaces(device: &DeviceState) -> HashMap<String, PropMap> {
+    let mut props = PropMap::new();
+    props.insert("Address".into(), variant(device.address.clone()));
+    props.insert("AddressType".into(), variant("public".to_string()));
+    props.insert("Name".into(), variant(device.alias.clone()));
+    props.insert("Alias".into(), variant(device.alias.clone()));
+    props.insert("Paired".into(), variant(device.paired));
+    props.insert("Bonded".into(), variant(device.paired));
+    props.insert("Trusted".into(), variant(device.trusted));
+    props.insert("Blocked".into(), variant(false));
+    props.insert("Connected".into(), variant(device.connected));
+    props.insert("ServicesResolved".into(), variant(device.connected));
+    props.insert("LegacyPairing".into(), variant(false));
+    props.insert("Adapter".into(), variant(Path::from(ADAPTER_PATH)));
+    if let Some(icon) = &device.icon {
+        props.insert("Icon".into(), variant(icon.clone()));
+    }
+    if let Some(class) = device.class {
+        props.insert("Class".into(), variant(class));
+    }
+
+    let mut interfaces = HashMap::new();
+    interfaces.insert(DEVICE.to_string(), props);
+
+    if let Some(percentage) = device.battery {
+        let mut battery = PropMap::new();
+        battery.insert("Percentage".into(), variant(percentage));
+        interfaces.insert(BATTERY.to_string(), battery);
+    }
+
+    interfaces
+}
+
+fn managed_objects(state: &State) -> HashMap<Path<'static>, HashMap<String, PropMap>> {
+    let mut objects = HashMap::new();
+
+    let mut adapter = HashMap::new();
+    adapter.insert(ADAPTER.to_string(), adapter_properties(&state.adapter));
+    objects.insert(Path::from(ADAPTER_PATH), adapter);
+
+    let mut root = HashMap::new();
+    root.insert(AGENT_MANAGER.to_string(), PropMap::new());
+    objects.insert(Path::from("/org/bluez"), root);
+
+    for device in state.visible_devices() {
+        objects.insert(Path::from(device.path()), device_interfaces(device));
+    }
+
+    objects
+}
+
+fn properties_changed(path: &str, iface: &str, name: &str, mut props: PropMap) -> Option<Message> {
+    let mut changed = PropMap::new();
+    changed.insert(name.to_string(), props.remove(name)?);
+
+    Some(
+        Message::signal(
+            &Path::from(path.to_string()),
+            &PROPERTIES.into(),
+            &"PropertiesChanged".into(),
+        )
+        .append3(iface, changed, Vec::<String>::new()),
+    )
+}
+
+fn discovering_changed(state: &State) -> Vec<Message> {
+    properties_changed(
+        ADAPTER_PATH,
+        ADAPTER,
+        "Discovering",
+        adapter_properties(&state.adapter),
+    )
+    .into_iter()
+    .collect()
+}
+
+fn device_changed(device: &DeviceState, name: &str) -> Vec<Message> {
+    let props = device_interfaces(device).remove(DEVICE).unwrap_or_default();
+    properties_changed(&device.path(), DEVICE, name, props)
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,195 @@
+use bluer::Address;
+use bzmenu::{
+    bz::windows_keys::{merge_info, parse_windows_address, read_windows_keys, BlueZKeyStore},
+    hive::Hive,
+};
+use std::{convert::TryInto, fs, os::unix::fs::PermissionsExt, path::Path};
+
+const LINK_KEY: [u8; 16] = [
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+];
+
+/// Loads a hive from tests/fixtures, built by tests/fixtures/generate_hives.py.
+fn fixture(name: &str) -> Hive {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    Hive::open(&path).expect("fixture hive should parse")
+}
+
+fn addr(s: &str) -> Address {
+    s.parse().unwrap()
+}
+
+#[test]
+fn reads_keys_from_current_control_set() {
+    let adapters = read_windows_keys(&fixture("SYSTEM_lh.hiv")).unwrap();
+
+    // Select\Current points at ControlSet002, ControlSet001 holds a stale adapter.
+    assert_eq!(adapters.len(), 1);
+    let adapter = &adapters[0];
+    assert_eq!(adapter.adapter, addr("00:1A:7D:DA:71:13"));
+
+    assert_eq!(adapter.devices.len(), 2);
+
+    let classic = &adapter.devices[0];
+    assert_eq!(classic.addr, addr("C0:FF:EE:12:34:56"));
+    assert_eq!(classic.link_key, Some(LINK_KEY));
+    assert!(classic.le.is_none());
+    assert_eq!(classic.key_types(), vec!["LinkKey"]);
+
+    let le_device = &adapter.devices[1];
+    assert_eq!(le_device.addr, addr("D0:C0:FF:EE:00:01"));
+    assert!(le_device.link_key.is_none());
+
+    let le = le_device.le.as_ref().unwrap();
+    assert_eq!(le.key_length, 16);
+    assert_eq!(le.erand, 0x1122_3344_5566_7788);
+    assert_eq!(le.ediv, 0xbeef);
+    assert!(le.authenticated);
+    assert!(le.irk.is_some());
+    assert_eq!(le_device.key_types(), vec!["LTK", "IRK", "ERand/EDIV"]);
+}
+
+#[test]
+fn reads_indexed_lists_and_utf16_names() {
+    let hive = fixture("SYSTEM_ri.hiv");
+    let root = hive.root();
+
+    let names: Vec<String> = root.subkeys().unwrap().iter().map(|k| k.name()).collect();
+    assert_eq!(names, vec!["ControlSet001", "MountedDevices", "Setup"]);
+
+    // No Select key, so ControlSet001 is used.
+    let adapters = read_windows_keys(&hive).unwrap();
+    assert_eq!(adapters.len(), 1);
+    assert_eq!(adapters[0].adapter, addr("00:1A:7D:DA:71:13"));
+    assert_eq!(adapters[0].devices.len(), 2);
+}
+
+#[test]
+fn rejects_cyclic_index_roots() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/SYSTEM_ri.hiv");
+    let mut data = fs::read(path).unwrap();
+    let u32_at = |data: &[u8], at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+
+    // Point the first entry of the root key's index root back at itself.
+    let root = 4096 + u32_at(&data, 0x24) as usize + 4;
+    let list_offset = u32_at(&data, root + 28);
+    let list = 4096 + list_offset as usize + 4;
+    assert_eq!(&data[list..list + 2], b"ri");
+    data[list + 4..list + 8].copy_from_slice(&list_offset.to_le_bytes());
+
+    let hive = Hive::from_bytes(data).unwrap();
+    assert!(hive.root().subkeys().is_err());
+}
+
+#[test]
+fn rejects_corrupt_and_truncated_hives() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/SYSTEM_ri.hiv");
+    let data = fs::read(path).unwrap();
+    let u32_at = |data: &[u8], at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+    let root = 4096 + u32_at(&data, 0x24) as usize + 4;
+
+    // The count in the key node is only a hint, the list holds the keys.
+    let mut inflated = data.clone();
+    inflated[root + 20..root + 24].copy_from_slice(&u32::MAX.to_le_bytes());
+    let hive = Hive::from_bytes(inflated).unwrap();
+    assert_eq!(hive.root().subkeys().unwrap().len(), 3);
+
+    // A list offset past the end of the file.
+    let mut dangling = data.clone();
+    dangling[root + 28..root + 32].copy_from_slice(&0x7fff_0000u32.to_le_bytes());
+    let hive = Hive::from_bytes(dangling).unwrap();
+    assert!(hive.root().subkeys().is_err());
+    assert!(read_windows_keys(&hive).is_err());
+
+    // Cut in the middle of the root key.
+    let mut truncated = data;
+    truncated.truncate(root + 40);
+    assert!(Hive::from_bytes(truncated).is_err());
+}
+
+#[test]
+fn rejects_non_hive_data() {
+    assert!(Hive::from_bytes(vec![0; 8192]).is_err());
+    assert!(Hive::from_bytes(b"regf".to_vec()).is_err());
+}
+
+#[test]
+fn parses_windows_addresses() {
+    assert_eq!(
+        parse_windows_address("c0ffee123456"),
+        Some(addr("C0:FF:EE:12:34:56"))
+    );
+    assert_eq!(parse_windows_address("MasterIRK"), None);
+    assert_eq!(parse_windows_address("c0ffee12345"), None);
+    assert_eq!(parse_windows_address("c0ffee12345g"), None);
+}
+
+#[test]
+fn merges_keys_into_info_file() {
+    let adapters = read_windows_keys(&fixture("SYSTEM_lh.hiv")).unwrap();
+    let devices = &adapters[0].devices;
+
+    let info = "[General]\nName=Headphones\nTrusted=true\n\n[LinkKey]\nKey=DEADBEEF\nType=5\nPINLength=0\n";
+    let merged = merge_info(info, &devices[0]);
+    assert_eq!(
+        merged,
+        "[General]\nName=Headphones\nTrusted=true\n\n[LinkKey]\nKey=00112233445566778899AABBCCDDEEFF\nType=5\nPINLength=0\n"
+    );
+
+    let merged = merge_info("[General]\nName=Mouse\n", &devices[1]);
+    assert!(merged.starts_with("[General]\nName=Mouse\n\n[LongTermKey]\n"));
+    assert!(merged.contains("Key=0F0E0D0C0B0A09080706050403020100\n"));
+    assert!(merged.contains("Authenticated=1\n"));
+    assert!(merged.contains("EncSize=16\n"));
+    assert!(merged.contains(&format!("EDiv={}\n", 0xbeef)));
+    assert!(merged.contains(&format!("Rand={}\n", 0x1122_3344_5566_7788u64)));
+    assert!(merged.contains("[IdentityResolvingKey]\nKey=0FFEEDDCCBBAA9988776655443322110\n"));
+    assert!(!merged.contains("[LinkKey]"));
+}
+
+#[test]
+fn applies_keys_to_bluez_storage() {
+    let root = std::env::temp_dir().join(format!("bzmenu-keys-{}", std::process::id()));
+    let store = BlueZKeyStore::new(&root);
+
+    let adapters = read_windows_keys(&fixture("SYSTEM_lh.hiv")).unwrap();
+    let adapter = adapters[0].adapter;
+    let device = &adapters[0].devices[0];
+
+    assert!(!store.has_device(adapter, device.addr).unwrap());
+    assert!(store.apply(adapter, device).is_err());
+
+    let path = store.info_path(adapter, device.addr);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, "[General]\nName=Headphones\n").unwrap();
+    assert!(store.has_device(adapter, device.addr).unwrap());
+
+    assert_eq!(store.apply(adapter, device).unwrap(), path);
+    let written = fs::read_to_string(&path).unwrap();
+    assert!(
+        written.contains("[LinkKey]\nKey=00112233445566778899AABBCCDDEEFF\nType=4\nPINLength=0\n")
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn reports_unreadable_bluez_storage() {
+    let root = std::env::temp_dir().join(format!("bzmenu-locked-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::set_permissions(&root, fs::Permissions::from_mode(0o000)).unwrap();
+
+    // Permissions do not apply to root.
+    if fs::read_dir(&root).is_err() {
+        let store = BlueZKeyStore::new(&root);
+        let err = store
+            .has_device(addr("00:1A:7D:DA:71:13"), addr("C0:FF:EE:12:34:56"))
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    fs::set_permissions(&root, fs::Permissions::from_mode(0o700)).unwrap();
+    fs::remove_dir_all(&root).unwrap();
+}